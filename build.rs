@@ -0,0 +1,13 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the bundled protoc so building does not depend on one being installed
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+
+    tonic_build::configure().compile_protos_with_config(
+        config,
+        &["proto/migration.proto"],
+        &["proto"],
+    )?;
+
+    Ok(())
+}
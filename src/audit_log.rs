@@ -0,0 +1,185 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use cdk_common::bitcoin::hashes::{Hash, sha256};
+use futures::TryStreamExt;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::output::say;
+use crate::sqlite::{self, blob_as_hex, quote_identifier};
+
+// With --audit-log every row the migration inserts, updates or deletes in a
+// SQLite database is recorded, as forensic proof of what the tool wrote.
+// Most writes go through cdk-sqlite, so the rows are not seen by this tool:
+// triggers on every table of the schema log each change to `_audit_log`,
+// within the transaction of the write, so the log holds exactly what was
+// committed, also across an interrupted and resumed run. Before the database
+// is verified the entries are appended to the log file as JSON lines and the
+// triggers and the table are dropped again.
+
+const TABLE: &str = "_audit_log";
+
+/// Fails early on a log file that cannot be written
+pub fn check_writable(log: &Path) -> Result<()> {
+    open_log(log).map(|_| ())
+}
+
+/// Starts logging the writes to every table of the database
+pub async fn start(sql_db_path: &Path) -> Result<()> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '\\_%' ESCAPE '\\'",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, at TEXT NOT NULL, table_name TEXT NOT NULL, operation TEXT NOT NULL, key TEXT NOT NULL, row TEXT NOT NULL)",
+        TABLE
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    for table in &tables {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", quote_identifier(table)))
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut keys: Vec<(i64, String)> = columns
+            .iter()
+            .filter(|column| column.get::<i64, _>("pk") > 0)
+            .map(|column| (column.get("pk"), column.get("name")))
+            .collect();
+        keys.sort();
+        let names: Vec<String> = columns.iter().map(|column| column.get("name")).collect();
+
+        for (operation, event, row) in [
+            ("insert", "INSERT", "NEW"),
+            ("update", "UPDATE", "NEW"),
+            ("delete", "DELETE", "OLD"),
+        ] {
+            // JSON cannot hold blobs, they are logged hex encoded
+            let value = |name: &str| blob_as_hex(&format!("{}.{}", row, quote_identifier(name)));
+            let key = match keys.is_empty() {
+                true => format!("{}.rowid", row),
+                false => keys
+                    .iter()
+                    .map(|(_, name)| value(name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            let fields = names
+                .iter()
+                .map(|name| format!("'{}', {}", name.replace('\'', "''"), value(name)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            sqlx::query(&format!(
+                "CREATE TRIGGER IF NOT EXISTS {} AFTER {} ON {} BEGIN INSERT INTO {} (at, table_name, operation, key, row) VALUES (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), '{}', '{}', json_array({}), json_object({})); END",
+                quote_identifier(&trigger_name(table, operation)),
+                event,
+                quote_identifier(table),
+                TABLE,
+                table.replace('\'', "''"),
+                operation,
+                key,
+                fields
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    pool.close().await;
+
+    tracing::info!(
+        "Logging the writes to {} tables of {:?}",
+        tables.len(),
+        sql_db_path
+    );
+
+    Ok(())
+}
+
+/// Appends the logged writes to `log` and stops logging, recording
+/// `database` as the database written. Without a log the writes an earlier
+/// run logged are dropped with the triggers.
+pub async fn finish(sql_db_path: &Path, database: &Path, log: Option<&Path>) -> Result<()> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let logging: Option<String> =
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(TABLE)
+            .fetch_optional(&pool)
+            .await?;
+    if logging.is_none() {
+        pool.close().await;
+        return Ok(());
+    }
+
+    if let Some(log) = log {
+        let select = format!(
+            "SELECT at, table_name, operation, key, row FROM {} ORDER BY id",
+            TABLE
+        );
+        let mut entries =
+            sqlx::query_as::<_, (String, String, String, String, String)>(&select).fetch(&pool);
+
+        let mut file = open_log(log)?;
+        let mut written = 0;
+        while let Some((at, table, operation, key, row)) = entries.try_next().await? {
+            let entry = json!({
+                "at": at,
+                "database": database,
+                "table": table,
+                "operation": operation,
+                "key": serde_json::from_str::<serde_json::Value>(&key)?,
+                "sha256": sha256::Hash::hash(row.as_bytes()).to_string(),
+            });
+            writeln!(file, "{}", entry)?;
+            written += 1;
+        }
+        // Hand back the connection the stream holds before the cleanup below
+        drop(entries);
+        file.sync_all()?;
+
+        say!(
+            "📋 Appended {} write(s) to {:?} to the audit log {:?}",
+            written,
+            database,
+            log
+        );
+    }
+
+    let triggers: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'trigger' AND name LIKE '\\_audit\\_%' ESCAPE '\\'",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let mut tx = pool.begin().await?;
+    for trigger in triggers {
+        sqlx::query(&format!("DROP TRIGGER {}", quote_identifier(&trigger)))
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(&format!("DROP TABLE {}", TABLE))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+fn open_log(log: &Path) -> Result<std::fs::File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .map_err(|e| anyhow!("Could not open the audit log {:?}: {}", log, e))
+}
+
+fn trigger_name(table: &str, operation: &str) -> String {
+    format!("_audit_{}_{}", table, operation)
+}
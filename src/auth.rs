@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use cdk_common::database::MintAuthDatabase;
+use cdk_common::{PublicKey, State};
+use cdk_redb::mint::MintRedbAuthDatabase;
+use cdk_sqlite::mint::MintSqliteAuthDatabase;
+
+use tracing::Instrument;
+
+use crate::checkpoint::Checkpoints;
+use crate::cli::MigrateArgs;
+use crate::output::say;
+use crate::paths::{self, MigrationPaths};
+use crate::progress::{Phase, ProgressObserver, WarningCode};
+use crate::raw::RedbReader;
+use crate::row_counts::SourceCounts;
+use crate::{lock, permissions, redb_format, sqlite};
+
+// Migration and verification of the auth database, left out of builds
+// without the auth feature
+
+pub async fn migrate_auth(
+    auth_redb_path: &Path,
+    auth_sqlite_path: &Path,
+    audit_log: bool,
+    progress: &dyn ProgressObserver,
+) -> Result<()> {
+    let sqlite_auth_db = MintSqliteAuthDatabase::new(auth_sqlite_path).await?;
+    sqlite_auth_db.migrate().await;
+    if audit_log {
+        crate::audit_log::start(auth_sqlite_path).await?;
+    }
+
+    migrate_auth_blind_signatures(auth_redb_path, &sqlite_auth_db).await?;
+
+    let auth_proofs = RedbReader::open(auth_redb_path)?.auth_proofs()?;
+    let ys: Vec<PublicKey> = auth_proofs
+        .iter()
+        .map(|a| a.y().expect("valid y"))
+        .collect();
+
+    let redb_auth_db = MintRedbAuthDatabase::new(auth_redb_path)?;
+    let states = redb_auth_db.get_proofs_states(&ys).await?;
+
+    assert_eq!(auth_proofs.len(), states.len());
+
+    // The proof is added as unspent, its state can only be set once it exists
+    for (proof, state) in auth_proofs.into_iter().zip(states) {
+        let y = proof.y().expect("Valid y");
+        sqlite_auth_db.add_proof(proof).await?;
+
+        if let Some(state) = state {
+            sqlite_auth_db.update_proof_state(&y, state).await?;
+        }
+    }
+
+    migrate_auth_keysets(&redb_auth_db, &sqlite_auth_db).await?;
+    migrate_protected_endpoints(&redb_auth_db, &sqlite_auth_db, progress).await?;
+
+    Ok(())
+}
+
+/// Converts only the auth database, for a mint whose main database was
+/// moved to SQLite before auth was enabled
+///
+/// The auth SQLite is built next to the target, verified and only then
+/// renamed into place. An existing target is never overwritten.
+pub async fn migrate_auth_only(
+    work_dir: &Path,
+    args: &MigrateArgs,
+    progress: &dyn ProgressObserver,
+) -> Result<()> {
+    let defaults = MigrationPaths::new(work_dir);
+    let paths = MigrationPaths {
+        auth_redb: args.auth_redb.clone().unwrap_or(defaults.auth_redb),
+        auth_sqlite: args.auth_sqlite.clone().unwrap_or(defaults.auth_sqlite),
+        ..defaults
+    };
+
+    if !paths.auth_redb.exists() {
+        return Err(anyhow!("No auth database at {:?}", paths.auth_redb));
+    }
+    if paths.auth_sqlite.exists() {
+        return Err(anyhow!(
+            "Auth SQLite database already exists at {:?}. Will not overwrite existing database.",
+            paths.auth_sqlite
+        ));
+    }
+    redb_format::check_format(&paths.auth_redb)?;
+    let deadline = args
+        .wait_for_lock
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    lock::wait_for_lock(&paths.auth_redb, deadline)?;
+    if let Some(log) = &args.audit_log {
+        crate::audit_log::check_writable(log)?;
+    }
+
+    let building = MigrationPaths {
+        auth_sqlite: paths::with_suffix(&paths.auth_sqlite, ".tmp"),
+        ..paths.clone()
+    };
+    // Whatever an interrupted run left is rebuilt, the auth database is small
+    sqlite::remove_database(&building.auth_sqlite)?;
+
+    say!(
+        "Migrating auth database {:?} to {:?}",
+        paths.auth_redb,
+        paths.auth_sqlite
+    );
+    progress.phase_started(Phase::Auth);
+    let counts = SourceCounts::read(&paths.auth_redb, &[Phase::Auth]);
+    migrate_auth(
+        &building.auth_redb,
+        &building.auth_sqlite,
+        args.audit_log.is_some(),
+        progress,
+    )
+    .instrument(Phase::Auth.span())
+    .await?;
+    counts
+        .check_phase(Phase::Auth, &building.auth_sqlite, 0)
+        .await?;
+    crate::audit_log::finish(
+        &building.auth_sqlite,
+        &paths.auth_sqlite,
+        args.audit_log.as_deref(),
+    )
+    .await?;
+
+    progress.phase_started(Phase::Verification);
+    verify_auth_blind_signatures(&building, &mut Checkpoints::none()).await?;
+    verify_auth(&building).await?;
+
+    sqlite::rename_database(&building.auth_sqlite, &paths.auth_sqlite).await?;
+    permissions::apply(&paths.auth_sqlite, args.chmod, args.chown)?;
+    if args.fsync {
+        sqlite::sync_database(&paths.auth_sqlite)?;
+        if let Some(dir) = paths.auth_sqlite.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            paths::sync_dir(dir)?;
+        }
+    }
+
+    say!("🎉 Auth database migrated to {:?}", paths.auth_sqlite);
+
+    Ok(())
+}
+
+pub async fn verify_auth(paths: &MigrationPaths) -> Result<()> {
+    say!("\n=== Verifying Auth Database ===");
+    let auth_redb_path = paths.auth_redb.clone();
+    let auth_sql_db_path = paths.auth_sqlite.clone();
+
+    // Read before cdk-redb takes the lock on the file
+    let redb_auth_proofs = RedbReader::open(&auth_redb_path)?.auth_proofs()?;
+    let redb_auth_db = MintRedbAuthDatabase::new(&auth_redb_path)?;
+    let sqlite_auth_db = MintSqliteAuthDatabase::new(&auth_sql_db_path).await?;
+
+    // Verify auth keysets
+    say!("📋 Checking auth keysets...");
+    let redb_auth_keysets = redb_auth_db.get_keyset_infos().await?;
+    let sqlite_auth_keysets = sqlite_auth_db.get_keyset_infos().await?;
+    assert_eq!(
+        redb_auth_keysets.len(),
+        sqlite_auth_keysets.len(),
+        "Auth keyset count mismatch"
+    );
+    for keyset in &redb_auth_keysets {
+        assert!(
+            sqlite_auth_keysets.contains(keyset),
+            "Missing auth keyset in SQLite DB"
+        );
+    }
+    say!("✅ All {} auth keysets match", redb_auth_keysets.len());
+
+    // Verify auth proofs and their states
+    say!("📋 Checking auth proofs...");
+    let ys = redb_auth_proofs
+        .iter()
+        .map(|proof| proof.y())
+        .collect::<Result<Vec<_>, _>>()?;
+    let redb_auth_states = redb_auth_db.get_proofs_states(&ys).await?;
+    let sqlite_auth_states = sqlite_auth_db.get_proofs_states(&ys).await?;
+    for ((y, redb_state), sqlite_state) in ys.iter().zip(redb_auth_states).zip(sqlite_auth_states) {
+        let sqlite_state =
+            sqlite_state.unwrap_or_else(|| panic!("Missing auth proof {} in SQLite DB", y));
+        // redb only records a state once the proof is used
+        assert_eq!(
+            redb_state.unwrap_or(State::Unspent),
+            sqlite_state,
+            "Auth proof {} state mismatch",
+            y
+        );
+    }
+    say!("✅ All {} auth proofs and their states match", ys.len());
+
+    // Verify protected endpoints
+    say!("📋 Checking protected endpoints...");
+    let redb_protected_endpoints = redb_auth_db.get_auth_for_endpoints().await?;
+    let sqlite_protected_endpoints = sqlite_auth_db.get_auth_for_endpoints().await?;
+    // Endpoints without an auth requirement are not recorded in SQLite
+    assert_eq!(
+        redb_protected_endpoints
+            .values()
+            .filter(|auth| auth.is_some())
+            .count(),
+        sqlite_protected_endpoints.len(),
+        "Protected endpoints count mismatch"
+    );
+    for (endpoint, auth) in &redb_protected_endpoints {
+        let sqlite_auth = sqlite_protected_endpoints.get(endpoint).cloned().flatten();
+        assert_eq!(*auth, sqlite_auth, "Protected endpoint auth mismatch");
+    }
+    say!(
+        "✅ All {} protected endpoints match",
+        redb_protected_endpoints.len()
+    );
+
+    Ok(())
+}
+
+/// Compares the number and total amount of the blind auth signatures each
+/// keyset issued, as the mint's blind signatures are compared
+pub async fn verify_auth_blind_signatures(
+    paths: &MigrationPaths,
+    checkpoints: &mut Checkpoints,
+) -> Result<()> {
+    say!("\n=== Verifying Auth Blind Signatures ===");
+
+    let redb_sigs = RedbReader::open(&paths.auth_redb)?.blind_signatures()?;
+    let mut redb_keysets: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for (_, sig) in &redb_sigs {
+        let (count, amount) = redb_keysets.entry(sig.keyset_id.to_string()).or_default();
+        *count += 1;
+        *amount += u64::from(sig.amount);
+    }
+
+    let pool = sqlite::connect(&paths.auth_sqlite).await?;
+    let sqlite_keysets: BTreeMap<String, (u64, u64)> = sqlx::query_as::<_, (String, i64, i64)>(
+        "SELECT keyset_id, count(*), sum(amount) FROM blind_signature GROUP BY keyset_id",
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(|(keyset_id, count, amount)| (keyset_id, (count as u64, amount as u64)))
+    .collect();
+    pool.close().await;
+
+    say!(
+        "Checking auth blind signatures across {} keysets...",
+        redb_keysets.len()
+    );
+    for keyset_id in sqlite_keysets.keys() {
+        assert!(
+            redb_keysets.contains_key(keyset_id),
+            "SQLite has auth blind signatures of keyset {} that the redb does not",
+            keyset_id
+        );
+    }
+
+    let mut total_sigs = 0u64;
+    let mut total_amount = 0u64;
+    for (keyset_id, (redb_count, redb_amount)) in &redb_keysets {
+        let step = format!("auth_blind_signatures.{}", keyset_id);
+        if let Some(records) = checkpoints.passed(&step) {
+            total_sigs += records;
+            continue;
+        }
+
+        let (sqlite_count, sqlite_amount) =
+            sqlite_keysets.get(keyset_id).copied().unwrap_or_default();
+        say!(
+            "Keyset {}: {} signatures with total amount {} in Redb, {} with total amount {} in SQLite",
+            keyset_id,
+            redb_count,
+            redb_amount,
+            sqlite_count,
+            sqlite_amount
+        );
+        assert_eq!(
+            *redb_count, sqlite_count,
+            "Auth blind signature count mismatch for keyset {}: Redb has {} but SQLite has {}",
+            keyset_id, redb_count, sqlite_count
+        );
+        assert_eq!(
+            *redb_amount, sqlite_amount,
+            "Auth total amount mismatch for keyset {}: Redb total is {} but SQLite total is {}",
+            keyset_id, redb_amount, sqlite_amount
+        );
+
+        total_sigs += redb_count;
+        total_amount += redb_amount;
+        checkpoints.pass(&step, *redb_count).await?;
+    }
+
+    say!(
+        "✅ All {} auth blind signatures match, total amount {} units",
+        total_sigs,
+        total_amount
+    );
+
+    Ok(())
+}
+
+async fn migrate_auth_blind_signatures(
+    redb_path: &Path,
+    sqlite_db: &MintSqliteAuthDatabase,
+) -> Result<()> {
+    let (messages, sigs): (Vec<_>, Vec<_>) = RedbReader::open(redb_path)?
+        .blind_signatures()?
+        .into_iter()
+        .unzip();
+    tracing::info!("Found {} blind signatures to migrate", messages.len());
+    sqlite_db.add_blind_signatures(&messages, &sigs).await?;
+    tracing::info!("Auth Blind signatures migration complete");
+    Ok(())
+}
+
+async fn migrate_auth_keysets(
+    redb_db: &MintRedbAuthDatabase,
+    sqlite_db: &MintSqliteAuthDatabase,
+) -> Result<()> {
+    let keysets = redb_db.get_keyset_infos().await?;
+
+    for keyset in keysets {
+        sqlite_db.add_keyset_info(keyset).await?;
+    }
+    Ok(())
+}
+
+async fn migrate_protected_endpoints(
+    redb_db: &MintRedbAuthDatabase,
+    sqlite_db: &MintSqliteAuthDatabase,
+    progress: &dyn ProgressObserver,
+) -> Result<()> {
+    // cdk-redb only stores endpoints that require auth, and the
+    // protected_endpoints table of cdk-sqlite has no way to record an
+    // explicit "no auth" entry, so any such entry can only be reported
+    let mut protected_endpoints = HashMap::new();
+    for (endpoint, auth) in redb_db.get_auth_for_endpoints().await? {
+        match auth {
+            Some(auth) => {
+                protected_endpoints.insert(endpoint, auth);
+            }
+            None => {
+                tracing::warn!(
+                    "Endpoint {:?} has no auth requirement and cannot be recorded in SQLite",
+                    endpoint
+                );
+                say!(
+                    "⚠️  [{}] Endpoint {:?} has no auth requirement, it stays unprotected but is not recorded",
+                    WarningCode::UnprotectedEndpoint,
+                    endpoint
+                );
+                progress.warning(
+                    WarningCode::UnprotectedEndpoint,
+                    &format!(
+                        "Endpoint {:?} has no auth requirement and is not recorded",
+                        endpoint
+                    ),
+                );
+            }
+        }
+    }
+
+    sqlite_db
+        .add_protected_endpoints(protected_endpoints)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, anyhow};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+
+use crate::meta;
+use crate::output::say;
+use crate::paths::{MigrationPaths, with_suffix};
+
+// `--backup-encrypt` keeps a copy of the source for off-site retention before
+// anything is migrated: the redb files and a manifest of their checksums in a
+// gzip compressed tar archive, encrypted to the operator's key by the age or
+// gpg binary, so their key policy applies and no key material passes through
+// this tool. age recipients start with `age1` or are SSH public keys,
+// anything else is given to gpg as a key id, fingerprint or email address.
+
+#[derive(Serialize)]
+struct Manifest {
+    created_at: u64,
+    tool_version: &'static str,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Writes the encrypted archive of the redb files next to them
+pub fn backup_sources(paths: &MigrationPaths, recipient: &str) -> Result<PathBuf> {
+    let sources: Vec<&Path> = [&paths.redb, &paths.auth_redb]
+        .into_iter()
+        .filter(|source| source.exists())
+        .map(PathBuf::as_path)
+        .collect();
+
+    let created_at = meta::unix_time();
+    let mut files = vec![];
+    for source in &sources {
+        files.push(ManifestFile {
+            name: file_name(source),
+            size: std::fs::metadata(source)?.len(),
+            sha256: meta::sha256_file(source)?,
+        });
+    }
+    let manifest = serde_json::to_vec_pretty(&Manifest {
+        created_at,
+        tool_version: env!("CARGO_PKG_VERSION"),
+        files,
+    })?;
+
+    let (mut encrypt, extension) = encrypt_command(recipient);
+    let dir = paths.redb.parent().unwrap_or(Path::new("."));
+    let archive = dir.join(format!(
+        "cdk-mintd-redb-backup-{}.tar.gz.{}",
+        created_at, extension
+    ));
+    if archive.exists() {
+        return Err(anyhow!("{:?} already exists", archive));
+    }
+    let partial = with_suffix(&archive, ".partial");
+
+    let mut child = encrypt
+        .stdin(Stdio::piped())
+        .stdout(File::create(&partial)?)
+        .spawn()
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&partial);
+            anyhow!("Could not run {} to encrypt the backup: {}", extension, e)
+        })?;
+
+    let written = (|| -> Result<()> {
+        let stdin = child.stdin.take().ok_or(anyhow!("No stdin to encrypt"))?;
+        let mut tar = tar::Builder::new(GzEncoder::new(stdin, Compression::default()));
+        append(
+            &mut tar,
+            "manifest.json",
+            &manifest[..],
+            manifest.len() as u64,
+        )?;
+        for source in &sources {
+            let size = std::fs::metadata(source)?.len();
+            append(
+                &mut tar,
+                &file_name(source),
+                BufReader::new(File::open(source)?),
+                size,
+            )?;
+        }
+        tar.into_inner()?.finish()?;
+        Ok(())
+    })();
+    let status = child.wait()?;
+
+    // An encryptor that gave up breaks the pipe, its exit status says why
+    let written = match status.success() {
+        true => written,
+        false => Err(anyhow!("{} exited with {}", extension, status)),
+    };
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(anyhow!(
+            "Could not write the encrypted backup for {}: {}",
+            recipient,
+            err
+        ));
+    }
+    File::open(&partial)?.sync_all()?;
+    std::fs::rename(&partial, &archive)?;
+    say!(
+        "🔒 Backed up the source, encrypted for {}, to {:?}",
+        recipient,
+        archive
+    );
+
+    Ok(archive)
+}
+
+fn encrypt_command(recipient: &str) -> (Command, &'static str) {
+    let age = recipient.starts_with("age1") || recipient.starts_with("ssh-");
+    if age {
+        let mut age = Command::new("age");
+        age.args(["--encrypt", "--recipient", recipient]);
+        (age, "age")
+    } else {
+        let mut gpg = Command::new("gpg");
+        gpg.args([
+            "--batch",
+            "--encrypt",
+            "--recipient",
+            recipient,
+            "--output",
+            "-",
+        ]);
+        (gpg, "gpg")
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Appends a file to the archive, failing if it does not have `size` bytes
+fn append(
+    tar: &mut tar::Builder<impl Write>,
+    name: &str,
+    data: impl Read,
+    size: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_mtime(meta::unix_time());
+    header.set_size(size);
+
+    let mut data = Counted {
+        inner: data,
+        read: 0,
+    };
+    tar.append_data(&mut header, name, &mut data)?;
+    if data.read != size {
+        return Err(anyhow!("{} changed size while it was backed up", name));
+    }
+
+    Ok(())
+}
+
+// The tar crate writes whatever the reader yields, the count tells whether
+// that matches the size in the header
+struct Counted<R> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
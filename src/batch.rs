@@ -0,0 +1,80 @@
+use std::any::Any;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+
+use crate::cli::MigrateArgs;
+use crate::output::say;
+
+/// Reads work dirs from a file, one per line, ignoring blank lines and `#` comments
+pub fn read_work_dir_list(path: &Path) -> Result<Vec<PathBuf>> {
+    let list = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read work dir list {:?}: {}", path, e))?;
+
+    Ok(list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Migrates each work dir in turn, a failing mint does not stop the ones after it
+pub async fn migrate_batch(work_dirs: Vec<PathBuf>, args: MigrateArgs) -> Result<()> {
+    let mut results = vec![];
+
+    for (i, work_dir) in work_dirs.iter().enumerate() {
+        println!(
+            "\n##### Migrating mint {}/{}: {:?} #####",
+            i + 1,
+            work_dirs.len(),
+            work_dir
+        );
+
+        // Verification asserts on mismatches, running each mint in its own task
+        // turns those panics into a failure of that mint only
+        let result = match tokio::spawn(crate::migrate(work_dir.clone(), args.clone())).await {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => Err(anyhow!(
+                "Migration panicked: {}",
+                panic_message(&*err.into_panic())
+            )),
+            Err(err) => Err(err.into()),
+        };
+
+        results.push((work_dir, result));
+    }
+
+    println!("\n=== Batch Summary ===");
+    let mut failed = 0;
+    for (work_dir, result) in &results {
+        match result {
+            Ok(()) => say!("✅ {:?}", work_dir),
+            Err(err) => {
+                failed += 1;
+                say!("❌ {:?}: {}", work_dir, err);
+            }
+        }
+    }
+    println!(
+        "{} of {} mints migrated successfully",
+        results.len() - failed,
+        results.len()
+    );
+
+    if failed > 0 {
+        return Err(anyhow!("{} of {} migrations failed", failed, results.len()));
+    }
+
+    Ok(())
+}
+
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else {
+        "unknown panic".to_string()
+    }
+}
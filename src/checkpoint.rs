@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::meta;
+use crate::output::say;
+
+// Verifying a mint with millions of proofs takes hours. Each step that
+// passed, the proofs or blind signatures of one keyset or a table of quotes,
+// is recorded in `_migration_meta`, e.g. as `checkpoint.proofs.<keyset id>`,
+// with the number of records it compared. A verification that is interrupted
+// or fails continues after the steps that passed, one that completes removes
+// the checkpoints and the next one starts over.
+
+const PREFIX: &str = "checkpoint.";
+
+pub struct Checkpoints {
+    sql_db_path: Option<PathBuf>,
+    passed: HashMap<String, u64>,
+}
+
+impl Checkpoints {
+    /// Steps an earlier verification of the database passed
+    pub async fn load(sql_db_path: &Path) -> Result<Self> {
+        let passed: HashMap<String, u64> = meta::read_meta(sql_db_path)
+            .await?
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let step = key.strip_prefix(PREFIX)?.to_string();
+                Some((step, value.parse().ok()?))
+            })
+            .collect();
+
+        if !passed.is_empty() {
+            say!(
+                "📋 Continuing an earlier verification, {} step(s) already passed",
+                passed.len()
+            );
+        }
+
+        Ok(Self {
+            sql_db_path: Some(sql_db_path.to_path_buf()),
+            passed,
+        })
+    }
+
+    /// Checkpoints that are not recorded, for a comparison that is not the
+    /// verification of a migration
+    pub fn none() -> Self {
+        Self {
+            sql_db_path: None,
+            passed: HashMap::new(),
+        }
+    }
+
+    /// Records compared by the step if it passed before
+    pub fn passed(&self, step: &str) -> Option<u64> {
+        let records = self.passed.get(step).copied();
+        if let Some(records) = records {
+            say!(
+                "✅ Skipping {}, its {} records were verified before",
+                step,
+                records
+            );
+        }
+        records
+    }
+
+    pub async fn pass(&mut self, step: &str, records: u64) -> Result<()> {
+        if let Some(sql_db_path) = &self.sql_db_path {
+            meta::set_value(
+                sql_db_path,
+                &format!("{}{}", PREFIX, step),
+                &records.to_string(),
+            )
+            .await?;
+        }
+        self.passed.insert(step.to_string(), records);
+
+        Ok(())
+    }
+}
+
+pub async fn clear(sql_db_path: &Path) -> Result<()> {
+    meta::remove_prefix(sql_db_path, PREFIX).await
+}
@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_common::bitcoin::hashes::{Hash, HashEngine, sha256};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::marker;
+use crate::meta;
+use crate::output::say;
+use crate::paths::{self, MigrationPaths, with_suffix};
+use crate::rollback::check_unchanged;
+
+// Once the mint runs on SQLite the redb is dead weight, but it is also the
+// only other copy of the mint's data. `cleanup` removes it, or archives it
+// as gzip, only while everything still shows the SQLite database is a
+// verified copy of it.
+
+pub async fn cleanup(work_dir: PathBuf, archive: bool, accept_changes: bool) -> Result<()> {
+    let paths = MigrationPaths::new(&work_dir);
+
+    let Some(marker) = marker::read_marker(&paths.redb)? else {
+        return Err(anyhow!(
+            "{:?} has no migrated marker, only a source whose migration was verified is retired",
+            paths.redb
+        ));
+    };
+    if !paths.redb.exists() {
+        return Err(anyhow!("{:?} was already retired", paths.redb));
+    }
+    if !marker.target.exists() {
+        return Err(anyhow!(
+            "The migrated database {:?} is gone, keeping the source",
+            marker.target
+        ));
+    }
+
+    let verification = meta::read_verification(&marker.target).await?;
+    if verification.as_deref() != Some("verified") {
+        return Err(anyhow!(
+            "{:?} did not pass verification (verification: {}), keeping the source",
+            marker.target,
+            verification.as_deref().unwrap_or("never reached")
+        ));
+    }
+
+    // A source written to after the migration holds records SQLite lacks
+    if meta::sha256_file(&paths.redb)? != marker.source_sha256 {
+        return Err(anyhow!(
+            "{:?} changed since it was migrated and may hold records the SQLite database lacks. Migrate it again with --force first.",
+            paths.redb
+        ));
+    }
+
+    let mut sources = vec![(&paths.redb, &marker.target, marker.target_sha256.as_deref())];
+    if paths.auth_redb.exists() {
+        if !paths.auth_sqlite.exists() {
+            return Err(anyhow!(
+                "{:?} has no migrated database at {:?}, keeping the source",
+                paths.auth_redb,
+                paths.auth_sqlite
+            ));
+        }
+        sources.push((
+            &paths.auth_redb,
+            &paths.auth_sqlite,
+            marker.auth_target_sha256.as_deref(),
+        ));
+    }
+
+    for (_, target, expected) in &sources {
+        if let Some(reason) = check_unchanged(target, *expected)? {
+            if !accept_changes {
+                return Err(anyhow!(
+                    "{:?} {}, keeping the source. Use --accept-changes if mintd has been running on it.",
+                    target,
+                    reason
+                ));
+            }
+            say!("⚠️  {:?} {}, retiring the source anyway", target, reason);
+        }
+    }
+
+    for (source, _, _) in sources {
+        if archive {
+            let archived = archive_file(source)?;
+            say!("📋 Archived {:?} as {:?}", source, archived);
+        }
+        std::fs::remove_file(source)?;
+        say!("✅ Removed {:?}", source);
+    }
+
+    Ok(())
+}
+
+/// Shreds the redb databases of a verified migration, recording each file's
+/// SHA-256 and the time in the migrated database's `_migration_meta`
+pub async fn shred_sources(paths: &MigrationPaths, source_sha256: &str) -> Result<()> {
+    // The source is locked while it is migrated, a different checksum means
+    // it is not the file that was verified
+    if meta::sha256_file(&paths.redb)? != source_sha256 {
+        return Err(anyhow!(
+            "{:?} changed while it was migrated, not shredding it",
+            paths.redb
+        ));
+    }
+
+    for source in [&paths.redb, &paths.auth_redb] {
+        if !source.exists() {
+            continue;
+        }
+
+        let sha256 = meta::sha256_file(source)?;
+        shred_file(source)?;
+        let name = source.file_name().unwrap_or_default().to_string_lossy();
+        meta::set_value(
+            &paths.sqlite,
+            &format!("shredded.{}", name),
+            &format!("sha256 {} at unix time {}", sha256, meta::unix_time()),
+        )
+        .await?;
+        say!("✅ Shredded {:?} (sha256 {})", source, sha256);
+    }
+
+    Ok(())
+}
+
+// A single pass of zeros, flushed before the file is removed. Copy-on-write
+// filesystems and SSDs may still keep the old blocks elsewhere.
+fn shred_file(path: &Path) -> Result<()> {
+    let mut left = std::fs::metadata(path)?.len();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let zeros = vec![0u8; 1024 * 1024];
+    while left > 0 {
+        let len = left.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..len])?;
+        left -= len as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::remove_file(path)?;
+    if let Some(dir) = path.parent() {
+        paths::sync_dir(dir)?;
+    }
+
+    Ok(())
+}
+
+pub fn archive_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".gz")
+}
+
+// The archive is read back and compared with the source before the source
+// may be removed
+fn archive_file(path: &Path) -> Result<PathBuf> {
+    let archived = archive_path(path);
+    if archived.exists() {
+        return Err(anyhow!("{:?} already exists", archived));
+    }
+    gzip_file(path, &archived)?;
+
+    Ok(archived)
+}
+
+/// Writes a gzip copy of `path` to `to`, replacing it only once the copy
+/// reads back as the file
+pub fn gzip_file(path: &Path, to: &Path) -> Result<()> {
+    let partial = with_suffix(to, ".partial");
+
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(&partial)?),
+        Compression::default(),
+    );
+    std::io::copy(&mut BufReader::new(File::open(path)?), &mut encoder)?;
+    let file = encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+
+    if sha256_gz(&partial)? != meta::sha256_file(path)? {
+        let _ = std::fs::remove_file(&partial);
+        return Err(anyhow!(
+            "The archive of {:?} does not read back as it",
+            path
+        ));
+    }
+    std::fs::rename(&partial, to)?;
+
+    Ok(())
+}
+
+/// Unpacks an archive made by `cleanup` to `to`, if it holds the file with
+/// the `expected` SHA-256
+pub fn restore_file(archived: &Path, to: &Path, expected: Option<&str>) -> Result<()> {
+    let partial = with_suffix(to, ".partial");
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(archived)?));
+    let mut file = BufWriter::new(File::create(&partial)?);
+    std::io::copy(&mut decoder, &mut file)?;
+    file.flush()?;
+    file.get_ref().sync_all()?;
+    drop(file);
+
+    if let Some(expected) = expected
+        && meta::sha256_file(&partial)? != expected
+    {
+        let _ = std::fs::remove_file(&partial);
+        return Err(anyhow!(
+            "{:?} does not hold the redb that was migrated",
+            archived
+        ));
+    }
+
+    std::fs::rename(&partial, to)?;
+    Ok(())
+}
+
+fn sha256_gz(path: &Path) -> Result<String> {
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+    let mut engine = sha256::HashEngine::default();
+    let mut buf = vec![0u8; 1024 * 1024];
+
+    loop {
+        let read = decoder.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        engine.input(&buf[..read]);
+    }
+
+    Ok(sha256::Hash::from_engine(engine).to_string())
+}
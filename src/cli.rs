@@ -1,6 +1,16 @@
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+
+use crate::merge::{ConflictRule, parse_conflict_rule};
+use crate::meta::unix_time;
+use crate::paths::parse_output_name;
+use crate::permissions::{Owner, parse_mode, parse_owner};
+use crate::progress::WarningCode;
+use crate::stats::civil_from_days;
+use crate::throttle::{RateLimit, parse_rate_limit};
 
 #[derive(Parser)]
 #[command(about = "Tool to convert cdk redb mint to sqlite", author = env!("CARGO_PKG_AUTHORS"), version = env!("CARGO_PKG_VERSION"))]
@@ -8,8 +18,686 @@ pub struct CLIArgs {
     #[arg(
         short,
         long,
-        help = "Use the <directory> as the location of the database",
-        required = false
+        help = "Use the <directory> as the location of the database, repeat it to migrate several mints",
+        required = false,
+        global = true
+    )]
+    pub work_dir: Vec<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "File listing work dirs to migrate, one per line"
+    )]
+    pub work_dir_list: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "TOML file mapping cdk-redb table names to the names used by the database",
+        global = true
+    )]
+    pub table_names: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Do not color the log output, also the default when stdout is not a terminal or NO_COLOR is set",
+        global = true
+    )]
+    pub no_color: bool,
+    #[arg(
+        long,
+        help = "Print ASCII instead of emoji and no colors, also the default when stdout is not a terminal",
+        global = true
+    )]
+    pub plain: bool,
+    #[arg(
+        long,
+        value_name = "PATH|URL",
+        help = "If the run fails or panics, write a report with the error, the phase it happened in, recent log lines and versions to this file, or POST it to this http:// URL",
+        global = true
+    )]
+    pub error_report: Option<String>,
+    #[arg(
+        long,
+        help = "Print a one-line JSON digest of the run to stdout at the end, with everything else on stderr",
+        global = true
+    )]
+    pub json: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the result of the run, counts, amounts, durations and whether it succeeded, to this .prom file for node_exporter's textfile collector",
+        global = true
+    )]
+    pub metrics_file: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Run at the lowest CPU and, on Linux, idle I/O priority so a background run never competes with mintd",
+        global = true
+    )]
+    pub nice: bool,
+    #[cfg(feature = "otel")]
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Send the tracing spans of the run to this OTLP/gRPC collector, e.g. http://localhost:4317",
+        global = true
+    )]
+    pub otlp_endpoint: Option<String>,
+    #[command(flatten)]
+    pub migrate: MigrateArgs,
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Args, Clone)]
+pub struct MigrateArgs {
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "SQL script to run against the SQLite database after the data is loaded"
+    )]
+    pub post_sql: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Migrate even if the source redb is marked as already migrated"
+    )]
+    pub force: bool,
+    #[arg(
+        long,
+        conflicts_with = "wipe",
+        help = "Continue into an incomplete SQLite database left by an earlier run"
+    )]
+    pub resume: bool,
+    #[arg(
+        long,
+        help = "Delete an incomplete SQLite database left by an earlier run and start over"
+    )]
+    pub wipe: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SECTION",
+        help = "Re-migrate only this section into an existing SQLite database"
+    )]
+    pub replace_section: Option<Section>,
+    #[arg(
+        long,
+        conflicts_with_all = ["replace_section", "merge", "resume", "salvage"],
+        help = "Convert only the auth database, for mints that enabled auth after moving their main database to SQLite"
+    )]
+    pub auth_only: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "auth_only",
+        help = "Auth redb to convert with --auth-only, instead of the one in the work dir"
+    )]
+    pub auth_redb: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "auth_only",
+        help = "Where --auth-only writes the auth SQLite database, instead of the work dir"
+    )]
+    pub auth_sqlite: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Flush the SQLite files, their WAL and the work dir to disk before reporting success"
+    )]
+    pub fsync: bool,
+    #[arg(
+        long,
+        help = "If the redb cannot be read as a whole, migrate every record that still parses"
+    )]
+    pub salvage: bool,
+    #[arg(
+        long,
+        help = "Repair and compact a copy of the redb and migrate from the copy, for databases that were not shut down cleanly"
+    )]
+    pub repair: bool,
+    #[arg(
+        long,
+        help = "If the SQLite database already exists, merge the migrated records into it instead of refusing"
+    )]
+    pub merge: bool,
+    #[arg(
+        long,
+        value_name = "[TABLE=]STRATEGY",
+        value_parser = parse_conflict_rule,
+        requires = "merge",
+        help = "How --merge resolves rows whose key is taken by a different row: skip, overwrite or fail, for every table or one such as proof=skip. Defaults to fail"
+    )]
+    pub on_conflict: Vec<ConflictRule>,
+    #[arg(
+        long,
+        help = "After migrating, run the database queries mintd makes on startup against the new SQLite database"
+    )]
+    pub smoke_test: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["merge", "salvage"],
+        help = "Verify the SQLite databases again once they are in place, and set them aside as .failed if they do not match"
+    )]
+    pub verify_after: bool,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Wait up to this many seconds for another process, e.g. a stopping mintd, to release the redb"
+    )]
+    pub wait_for_lock: Option<u64>,
+    #[arg(
+        long,
+        help = "Stream proofs and blind signatures in small batches to keep memory use low"
+    )]
+    pub low_memory: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Build the SQLite databases and keep SQLite's temporary files in this directory instead of the work dir"
+    )]
+    pub tmp_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = parse_mode,
+        help = "Set this octal mode, e.g. 640, on the produced SQLite files"
+    )]
+    pub chmod: Option<u32>,
+    #[arg(
+        long,
+        value_name = "USER[:GROUP]",
+        value_parser = parse_owner,
+        help = "Hand the produced SQLite files to this user and group, e.g. the one mintd runs as"
     )]
-    pub work_dir: Option<PathBuf>,
+    pub chown: Option<Owner>,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = "Serve the phase, progress, ETA and warnings of the running migration as JSON on this address, e.g. 127.0.0.1:9876"
+    )]
+    pub status_addr: Option<SocketAddr>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Stream the phases, record counts and warnings of the running migration as JSON lines to every client of this Unix socket"
+    )]
+    pub progress_socket: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "After a verified migration, overwrite the redb files with zeros and remove them, recording their SHA-256 in _migration_meta"
+    )]
+    pub shred_source: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_output_name,
+        conflicts_with_all = ["timestamped_output", "merge", "replace_section"],
+        help = "Write the SQLite database under this file name in the work dir and point a cdk-mintd.sqlite symlink at it"
+    )]
+    pub output_name: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["merge", "replace_section"],
+        help = "Like --output-name, with today's date in the name, e.g. cdk-mintd-2024-06-01.sqlite"
+    )]
+    pub timestamped_output: bool,
+    #[arg(
+        long,
+        conflicts_with = "low_memory",
+        help = "Collapse proofs the redb holds more than once under different keys into one, reporting how many"
+    )]
+    pub compact: bool,
+    #[arg(
+        long,
+        help = "Check the signature of every migrated proof with keys derived from mintd's mnemonic, from CDK_MINTD_MNEMONIC or the work dir's config.toml"
+    )]
+    pub validate_proofs: bool,
+    #[arg(
+        long,
+        value_name = "RATE",
+        value_parser = parse_rate_limit,
+        help = "Copy at most this many records per second, or bytes per second such as 20MB/s, to spare the disk of a running mintd"
+    )]
+    pub rate_limit: Option<RateLimit>,
+    #[arg(
+        long,
+        help = "Do not check for enough free disk space before writing the SQLite databases"
+    )]
+    pub skip_space_check: bool,
+    #[arg(
+        long,
+        value_name = "RECIPIENT",
+        help = "Before migrating, back up the redb files with a manifest as a compressed archive encrypted to this age recipient or gpg key, for off-site retention"
+    )]
+    pub backup_encrypt: Option<String>,
+    #[arg(
+        long,
+        value_name = "ARCHIVE",
+        conflicts_with = "shred_source",
+        help = "Migrate from the redb databases in this .redb.gz or .tar.gz archive instead of the work dir's, unpacked next to the build and removed afterwards"
+    )]
+    pub source_archive: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "After migrating, also write a gzip copy of each SQLite database next to it, e.g. cdk-mintd.sqlite.gz, as a cold backup"
+    )]
+    pub compress_output: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Append the time, table, key and SHA-256 of every row written to the SQLite databases to this file, one JSON line each, as a forensic record of what the migration inserted"
+    )]
+    pub audit_log: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Fail the migration if it raises any warning, e.g. a skipped record, a defaulted column or a merge conflict. The databases are kept for investigation but the redb is not marked as migrated. See --allow"
+    )]
+    pub strict: bool,
+    #[arg(
+        long,
+        value_name = "CODE",
+        ignore_case = true,
+        help = "Fail the migration if it raises a warning with this code, e.g. W-MERGE-CONFLICT, also without --strict"
+    )]
+    pub deny: Vec<WarningCode>,
+    #[arg(
+        long,
+        value_name = "CODE",
+        ignore_case = true,
+        requires = "strict",
+        help = "Let --strict tolerate warnings with this code, e.g. W-TIMESTAMP"
+    )]
+    pub allow: Vec<WarningCode>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Sqlite,
+        help = "Once verified, also write each SQLite database in this format next to it"
+    )]
+    pub output_format: OutputFormat,
+}
+
+impl MigrateArgs {
+    /// File name of the mint database if it is not written as cdk-mintd.sqlite
+    pub fn output_name(&self) -> Option<String> {
+        if self.timestamped_output {
+            let (year, month, day) = civil_from_days((unix_time() / 86_400) as i64);
+            return Some(format!(
+                "cdk-mintd-{:04}-{:02}-{:02}.sqlite",
+                year, month, day
+            ));
+        }
+
+        self.output_name.clone()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Section {
+    /// Mint info and quote TTL
+    Info,
+    Keysets,
+    /// Mint and melt quotes with their melt requests
+    Quotes,
+    Proofs,
+    Signatures,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Inspect the contents of the mint databases
+    #[command(subcommand)]
+    Inspect(InspectCommand),
+    /// Export part of the redb database into a signed file, or the migrated
+    /// SQLite database in another format
+    Export {
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = ExportFormat::Signed,
+            help = "Format of the export"
+        )]
+        format: ExportFormat,
+        #[arg(
+            long,
+            value_enum,
+            help = "Data to export, required for a signed export"
+        )]
+        only: Option<ExportScope>,
+        #[arg(
+            long,
+            help = "Write the migrated auth database instead of the mint database, for every format but signed"
+        )]
+        auth: bool,
+        #[arg(short, long, help = "File to write the export to")]
+        output: PathBuf,
+        #[arg(
+            long,
+            help = "Hex secret key to sign the export with. If omitted, a one-time key is generated and only its public key is shown"
+        )]
+        signing_key: Option<String>,
+    },
+    /// Import a signed export into the SQLite database
+    Import {
+        #[arg(long, value_enum, help = "Data to import")]
+        only: ExportScope,
+        #[arg(short, long, help = "Export file to read")]
+        input: PathBuf,
+        #[arg(long, help = "Hex public key the export must be signed by")]
+        pubkey: Option<String>,
+    },
+    /// Copy one keyset's proofs, signatures and quotes into a new SQLite database
+    Split {
+        #[arg(long, help = "Id of the keyset to extract")]
+        keyset: String,
+        #[arg(short, long, help = "SQLite file to create")]
+        output: PathBuf,
+    },
+    /// Show what a migration with the given flags would do, without reading
+    /// the records or writing anything
+    Plan,
+    /// Remove the SQLite databases a migration produced and its marker, so the
+    /// mint runs on its redb again
+    Rollback {
+        #[arg(
+            long,
+            help = "Remove the SQLite databases even if they changed since the migration, losing what mintd wrote to them"
+        )]
+        discard_changes: bool,
+    },
+    /// Remove the redb databases of a verified migration, or archive them
+    Cleanup {
+        #[arg(
+            long,
+            help = "Compress the redb databases to .gz files instead of only removing them"
+        )]
+        archive: bool,
+        #[arg(
+            long,
+            help = "Retire the source even though the SQLite databases changed since the migration, e.g. because mintd runs on them"
+        )]
+        accept_changes: bool,
+    },
+    /// Convert a cdk wallet SQLite database into a redb wallet database
+    #[cfg(feature = "wallet")]
+    WalletToRedb {
+        #[arg(
+            short,
+            long,
+            help = "Wallet SQLite database to convert, it is not modified"
+        )]
+        input: PathBuf,
+        #[arg(short, long, help = "Redb wallet database to create")]
+        output: PathBuf,
+    },
+    /// Show proof counts and amounts by state per keyset in both databases
+    Stats {
+        #[command(flatten)]
+        period: PeriodArgs,
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["by_day", "by_week", "by_month"],
+            help = "Also save the counts to this JSON file, to compare against later"
+        )]
+        save: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["by_day", "by_week", "by_month"],
+            help = "List the counts that changed since the snapshot saved in this file with --save"
+        )]
+        compare: Option<PathBuf>,
+    },
+    /// Compare the migrated SQLite database with the redb again, continuing an
+    /// interrupted verification where it stopped
+    Verify {
+        #[arg(
+            long,
+            help = "Discard the checkpoints of an earlier verification and compare everything"
+        )]
+        restart: bool,
+        #[arg(
+            long,
+            value_name = "URL",
+            conflicts_with = "restart",
+            help = "Instead of comparing with the redb, check that the mint running on the migrated database at this http:// or https:// URL serves its keysets and mint info"
+        )]
+        mint_url: Option<String>,
+        #[arg(
+            long,
+            value_name = "N",
+            requires = "mint_url",
+            help = "Also look up the state of this many proofs, sampled across the redb, with the mint's NUT-07 checkstate"
+        )]
+        check_states: Option<usize>,
+        #[arg(
+            long,
+            value_name = "PATH",
+            conflicts_with_all = ["restart", "mint_url"],
+            help = "Compare the migrated SQLite database with this SQLite mint database instead of the redb, e.g. one restored from a dump"
+        )]
+        against: Option<PathBuf>,
+    },
+    /// Run every consistency, orphan and invariant check against the redb or
+    /// the SQLite mint database, independently of a migration
+    Doctor {
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Backend::Redb,
+            help = "Database to check"
+        )]
+        backend: Backend,
+        #[arg(
+            long,
+            help = "Also check every proof's signature with keys derived from mintd's mnemonic"
+        )]
+        validate_proofs: bool,
+    },
+    /// Migrate every mint listed in a TOML manifest concurrently
+    Fleet {
+        #[arg(long, help = "TOML manifest describing the mints to migrate")]
+        manifest: PathBuf,
+    },
+    /// Serve a gRPC API to start migrations and follow their progress
+    Serve {
+        #[arg(
+            long,
+            default_value = "127.0.0.1:50051",
+            help = "Address to listen on, the API has no authentication so it must be a loopback address unless --insecure is given"
+        )]
+        addr: SocketAddr,
+        #[arg(
+            long,
+            help = "Listen on --addr even if it is not a loopback address, anyone who can reach it can start migrations"
+        )]
+        insecure: bool,
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Directory the work dirs of started migrations must be in, a relative work dir is taken as inside it"
+        )]
+        root: PathBuf,
+    },
+    /// Migrate a generated sample mint in a temp dir and verify it, to check
+    /// the binary works on this platform
+    SelfTest,
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum, help = "Shell to generate completions for")]
+        shell: Shell,
+    },
+    /// Generate man pages from the command line definitions
+    #[command(hide = true)]
+    Mangen {
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Write a page for every subcommand into this directory instead of the main page to stdout"
+        )]
+        out_dir: Option<PathBuf>,
+    },
+    /// Copy a SQLite mint database into a new file, dropping data the filters exclude
+    Clone {
+        #[arg(
+            long,
+            help = "SQLite database to clone, defaults to cdk-mintd.sqlite in the work dir"
+        )]
+        source: Option<PathBuf>,
+        #[arg(short, long, help = "SQLite file to create")]
+        output: PathBuf,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct FilterArgs {
+    #[arg(long, help = "Leave out proofs that are already spent")]
+    pub drop_spent_proofs: bool,
+    #[arg(
+        long,
+        value_name = "UNIX_TIME",
+        help = "Leave out mint and melt quotes created before this time"
+    )]
+    pub prune_quotes_before: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Signed JSON of the data chosen with --only, read from the redb
+    Signed,
+    /// A fresh copy of the migrated SQLite database
+    Sqlite,
+    /// INSERT statements of the migrated SQLite database with its schema
+    Sql,
+    /// COPY blocks of the migrated SQLite database for psql
+    Postgres,
+    /// Every table of the migrated SQLite database as JSON
+    Json,
+}
+
+impl ExportFormat {
+    /// The output the migrated database is written as, none for a signed export
+    pub fn output_format(self) -> Option<OutputFormat> {
+        match self {
+            ExportFormat::Signed => None,
+            ExportFormat::Sqlite => Some(OutputFormat::Sqlite),
+            ExportFormat::Sql => Some(OutputFormat::Sql),
+            ExportFormat::Postgres => Some(OutputFormat::Postgres),
+            ExportFormat::Json => Some(OutputFormat::Json),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The SQLite database only
+    Sqlite,
+    /// Also INSERT statements with the schema, as cdk-mintd.sql
+    Sql,
+    /// Also COPY blocks for psql, as cdk-mintd.pgcopy.sql
+    Postgres,
+    /// Also every table as JSON, as cdk-mintd.json
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportScope {
+    /// Keyset infos including their derivation counters
+    Keysets,
+}
+
+#[derive(Subcommand)]
+pub enum InspectCommand {
+    /// Print every redb table with hex keys and raw values
+    DumpRaw {
+        #[arg(long, help = "Dump the auth database instead of the mint database")]
+        auth: bool,
+        #[arg(long, help = "Only dump the table with this name")]
+        table: Option<String>,
+    },
+    /// Print a single record as stored and as parsed into its cdk type
+    Get {
+        #[arg(long, help = "redb table name of the record, e.g. proofs")]
+        table: String,
+        #[arg(long, help = "Hex encoded key of the record")]
+        key: String,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Backend::Redb,
+            help = "Database to read the record from"
+        )]
+        backend: Backend,
+        #[arg(
+            long,
+            help = "Read from the auth database instead of the mint database"
+        )]
+        auth: bool,
+    },
+    /// Report whether a proof exists in redb and SQLite and its state in both
+    FindProof {
+        #[arg(help = "Hex encoded Y of the proof")]
+        y: String,
+    },
+    /// Show a mint or melt quote and its melt request from both databases
+    Quote {
+        #[arg(help = "Id of the mint or melt quote")]
+        quote_id: String,
+    },
+    /// Show the blind signature on a blinded message from both databases
+    Signature {
+        #[arg(help = "Hex encoded blinded message (B_) that was signed")]
+        blinded_message: String,
+    },
+    /// Browse keysets, proofs, quotes and signatures of either database interactively
+    Shell,
+}
+
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct PeriodArgs {
+    #[arg(
+        long,
+        help = "Show quote creation and settlement counts of the redb per day instead"
+    )]
+    pub by_day: bool,
+    #[arg(
+        long,
+        help = "Show quote creation and settlement counts of the redb per week instead"
+    )]
+    pub by_week: bool,
+    #[arg(
+        long,
+        help = "Show quote creation and settlement counts of the redb per month instead"
+    )]
+    pub by_month: bool,
+}
+
+impl PeriodArgs {
+    pub fn period(&self) -> Option<Period> {
+        if self.by_day {
+            Some(Period::Day)
+        } else if self.by_week {
+            Some(Period::Week)
+        } else if self.by_month {
+            Some(Period::Month)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Redb,
+    Sqlite,
 }
@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::cli::FilterArgs;
+use crate::output::say;
+use crate::sqlite;
+
+pub async fn clone(
+    work_dir: PathBuf,
+    source: Option<PathBuf>,
+    output: &Path,
+    filters: FilterArgs,
+) -> Result<()> {
+    let source = source.unwrap_or_else(|| work_dir.join("cdk-mintd.sqlite"));
+
+    println!("Cloning {:?} into {:?}", source, output);
+    if filters.drop_spent_proofs {
+        println!("Dropping spent proofs");
+    }
+    if let Some(cutoff) = filters.prune_quotes_before {
+        println!("Pruning quotes created before unix time {}", cutoff);
+    }
+
+    let pool = sqlite::create_from_source(&source, output).await?;
+
+    // Tables starting with an underscore belong to sqlx or this tool, such as
+    // the migration metadata, and describe how the source was produced, not
+    // the clone
+    let tables: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM source.sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+         AND name NOT LIKE '\\_%' ESCAPE '\\'
+         ORDER BY name",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    for (table,) in tables {
+        let mut sql = format!("INSERT INTO main.{table} SELECT * FROM source.{table}");
+        if let Some(filter) = table_filter(&table, &filters) {
+            sql.push_str(&format!(" WHERE {}", filter));
+        }
+
+        let copied = sqlx::query(&sql).execute(&mut *tx).await?.rows_affected();
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM source.{}", table))
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tracing::info!("Copied {} of {} rows of {}", copied, total, table);
+        println!("  {}: {} of {} rows", table, copied, total);
+    }
+    tx.commit().await?;
+
+    pool.close().await;
+
+    say!("✅ Clone written to {:?}", output);
+
+    Ok(())
+}
+
+fn table_filter(table: &str, filters: &FilterArgs) -> Option<String> {
+    match (table, filters.prune_quotes_before) {
+        ("proof", _) if filters.drop_spent_proofs => Some("state != 'SPENT'".to_string()),
+        ("mint_quote" | "melt_quote", Some(cutoff)) => Some(format!("created_time >= {}", cutoff)),
+        ("melt_request", Some(cutoff)) => Some(format!(
+            "lower(hex(id)) IN
+                (SELECT replace(id, '-', '') FROM source.melt_quote WHERE created_time >= {})",
+            cutoff
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use anyhow::Result;
+use sqlx::Row;
+
+use crate::meta::META_TABLE;
+use crate::output::say;
+use crate::progress::{ProgressObserver, WarningCode};
+use crate::sqlite;
+
+// Columns of the cdk-sqlite schema this tool fills, per table. A newer
+// cdk-sqlite may add tables or columns, which the migrated rows leave at
+// their default until the migration learns about them. Listing them tells
+// the operator before mintd starts relying on them.
+const MINT_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "keyset",
+        &[
+            "id",
+            "unit",
+            "active",
+            "valid_from",
+            "valid_to",
+            "derivation_path",
+            "max_order",
+            "input_fee_ppk",
+            "derivation_path_index",
+        ],
+    ),
+    (
+        "mint_quote",
+        &[
+            "id",
+            "amount",
+            "unit",
+            "request",
+            "expiry",
+            "state",
+            "request_lookup_id",
+            "pubkey",
+            "created_time",
+            "paid_time",
+            "issued_time",
+        ],
+    ),
+    (
+        "melt_quote",
+        &[
+            "id",
+            "unit",
+            "amount",
+            "request",
+            "fee_reserve",
+            "expiry",
+            "state",
+            "payment_preimage",
+            "request_lookup_id",
+            "msat_to_pay",
+            "created_time",
+            "paid_time",
+        ],
+    ),
+    (
+        "melt_request",
+        &["id", "inputs", "outputs", "method", "unit"],
+    ),
+    ("config", &["id", "value"]),
+    (
+        "proof",
+        &[
+            "y",
+            "amount",
+            "keyset_id",
+            "secret",
+            "c",
+            "witness",
+            "state",
+            "quote_id",
+            "created_time",
+        ],
+    ),
+    (
+        "blind_signature",
+        &[
+            "y",
+            "amount",
+            "keyset_id",
+            "c",
+            "dleq_e",
+            "dleq_s",
+            "quote_id",
+            "created_time",
+        ],
+    ),
+];
+
+const AUTH_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "keyset",
+        &[
+            "id",
+            "unit",
+            "active",
+            "valid_from",
+            "valid_to",
+            "derivation_path",
+            "max_order",
+            "derivation_path_index",
+        ],
+    ),
+    ("proof", &["y", "keyset_id", "secret", "c", "state"]),
+    ("blind_signature", &["y", "amount", "keyset_id", "c"]),
+    ("protected_endpoints", &["endpoint", "auth"]),
+];
+
+// Bookkeeping of sqlx and of this tool, not mint data
+const IGNORED_TABLES: &[&str] = &["_sqlx_migrations", META_TABLE];
+
+pub async fn audit_columns(
+    sql_db_path: &Path,
+    auth: bool,
+    progress: &dyn ProgressObserver,
+) -> Result<()> {
+    say!("\n🔎 Checking schema coverage of {:?}", sql_db_path);
+
+    let filled = if auth { AUTH_COLUMNS } else { MINT_COLUMNS };
+    let pool = sqlite::connect(sql_db_path).await?;
+    let mut gaps = 0;
+
+    let tables: Vec<String> = sqlx::query(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )
+    .fetch_all(&pool)
+    .await?
+    .iter()
+    .map(|row| row.get("name"))
+    .collect();
+
+    for table in tables {
+        if IGNORED_TABLES.contains(&table.as_str()) {
+            continue;
+        }
+
+        let Some((_, columns)) = filled.iter().find(|(name, _)| *name == table) else {
+            let rows: i64 = sqlx::query(&format!(
+                "SELECT count(*) FROM {}",
+                sqlite::quote_identifier(&table)
+            ))
+            .fetch_one(&pool)
+            .await?
+            .get(0);
+            gaps += 1;
+            let gap = format!(
+                "Table {} is not filled by this tool, it holds {} row(s)",
+                table, rows
+            );
+            progress.warning(WarningCode::UnfilledTable, &gap);
+            say!("⚠️  [{}] {}", WarningCode::UnfilledTable, gap);
+            continue;
+        };
+
+        let schema = sqlx::query(&format!(
+            "PRAGMA table_info({})",
+            sqlite::quote_identifier(&table)
+        ))
+        .fetch_all(&pool)
+        .await?;
+        for column in schema {
+            let name: String = column.get("name");
+            if columns.contains(&name.as_str()) {
+                continue;
+            }
+
+            gaps += 1;
+            let default: Option<String> = column.get("dflt_value");
+            let gap = format!(
+                "Column {}.{} is not filled by this tool, migrated rows keep its default {}",
+                table,
+                name,
+                default.as_deref().unwrap_or("NULL")
+            );
+            progress.warning(WarningCode::UnfilledColumn, &gap);
+            say!("⚠️  [{}] {}", WarningCode::UnfilledColumn, gap);
+        }
+    }
+
+    if gaps == 0 {
+        say!("✅ Every table and column of the schema is filled");
+    }
+
+    pool.close().await;
+
+    Ok(())
+}
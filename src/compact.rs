@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use cdk_common::nuts::ProofsMethods;
+use cdk_common::{Proof, PublicKey, State};
+
+// cdk-redb keys proofs by the Y it computed when they were added. Old cdk
+// versions could store the same proof under a second key, e.g. a Y from
+// before the hash_to_curve change, and both come back from the redb. SQLite
+// keys proofs by the Y of their secret, so such a pair can only be one row.
+// Blind signatures are keyed by their blinded message in both databases and
+// cannot repeat.
+
+/// Drops proofs that repeat an earlier one exactly, with their states, and
+/// returns how many were dropped. Proofs that share a Y but differ are kept,
+/// so verification still reports them.
+pub fn dedup_proofs(proofs: &mut Vec<Proof>, states: &mut Vec<Option<State>>) -> Result<u64> {
+    let ys = proofs.ys()?;
+    let mut first: HashMap<PublicKey, usize> = HashMap::with_capacity(ys.len());
+    let mut keep = Vec::with_capacity(ys.len());
+
+    for (i, y) in ys.into_iter().enumerate() {
+        let duplicate = match first.get(&y) {
+            Some(&earlier) => proofs[earlier] == proofs[i],
+            None => {
+                first.insert(y, i);
+                false
+            }
+        };
+        keep.push(!duplicate);
+    }
+
+    let before = proofs.len();
+    let mut flags = keep.iter();
+    proofs.retain(|_| *flags.next().unwrap_or(&true));
+    let mut flags = keep.iter();
+    states.retain(|_| *flags.next().unwrap_or(&true));
+
+    Ok((before - proofs.len()) as u64)
+}
@@ -0,0 +1,521 @@
+use anyhow::Result;
+use cdk_common::database::{self, MintDatabase, MintKeysDatabase};
+use cdk_common::{Amount, MeltQuoteState};
+use uuid::Uuid;
+
+use crate::checkpoint::Checkpoints;
+use crate::compact;
+use crate::melt_quotes;
+use crate::mint_info::mint_info_differences;
+use crate::output::say;
+use crate::payment_methods;
+use crate::progress::{ProgressObserver, WarningCode};
+
+// Verification compares two mint databases through the cdk database traits
+// only, so it does not matter which backend either of them is: the redb
+// source with the SQLite database built from it, or two SQLite databases,
+// and any backend cdk implements the traits for. Checks that need to look
+// at how a backend stores its records, like the SQLite columns cdk-sqlite
+// reads back with a default, stay with the caller.
+
+/// A mint database cdk can read the keysets, quotes, proofs and signatures of
+pub trait MintBackend:
+    MintDatabase<database::Error> + MintKeysDatabase<Err = database::Error> + Sync
+{
+}
+
+impl<D> MintBackend for D where
+    D: MintDatabase<database::Error> + MintKeysDatabase<Err = database::Error> + Sync
+{
+}
+
+/// Records each part of the comparison found equal
+pub struct Compared {
+    pub keysets: usize,
+    pub proofs: usize,
+    pub mint_quotes: u64,
+    pub melt_quotes: u64,
+}
+
+/// Compares everything but the blind signatures of `target` with `source`,
+/// `compact` when `target` holds each proof that `source` repeats once
+pub async fn compare_mints<S: MintBackend, T: MintBackend>(
+    source: &S,
+    target: &T,
+    compact: bool,
+    checkpoints: &mut Checkpoints,
+    progress: &dyn ProgressObserver,
+) -> Result<Compared> {
+    // Verify mint info
+    say!("📋 Checking mint info...");
+    let source_mint_info = source.get_mint_info().await?;
+    let target_mint_info = target.get_mint_info().await?;
+    let differences = mint_info_differences(&source_mint_info, &target_mint_info)?;
+    assert!(
+        differences.is_empty(),
+        "Mint info mismatch in {}",
+        differences.join(", ")
+    );
+    if source_mint_info == target_mint_info {
+        say!("✅ Mint info matches");
+    } else {
+        say!("✅ Mint info matches, with its NUT settings normalized");
+    }
+
+    // Verify quote TTL
+    say!("📋 Checking quote TTL...");
+    let source_quote_ttl = source.get_quote_ttl().await?;
+    let target_quote_ttl = target.get_quote_ttl().await?;
+    assert_eq!(source_quote_ttl, target_quote_ttl, "Quote TTL mismatch");
+    say!("✅ Quote TTL matches");
+
+    // Verify keysets
+    say!("📋 Checking keysets...");
+    let source_keysets = source.get_keyset_infos().await?;
+    let target_keysets = target.get_keyset_infos().await?;
+    assert_eq!(
+        source_keysets.len(),
+        target_keysets.len(),
+        "Keyset count mismatch"
+    );
+    for keyset in &source_keysets {
+        let target_keyset = target_keysets
+            .iter()
+            .find(|target_keyset| target_keyset.id == keyset.id)
+            .unwrap_or_else(|| panic!("Missing keyset {} in the target", keyset.id));
+        // A wrong derivation makes the mint derive different keys on its first start
+        assert_eq!(
+            keyset.derivation_path, target_keyset.derivation_path,
+            "Keyset {} derivation_path mismatch",
+            keyset.id
+        );
+        assert_eq!(
+            keyset.derivation_path_index, target_keyset.derivation_path_index,
+            "Keyset {} derivation_path_index mismatch",
+            keyset.id
+        );
+        assert_eq!(
+            keyset.max_order, target_keyset.max_order,
+            "Keyset {} max_order mismatch",
+            keyset.id
+        );
+        // Every swap after switchover charges the fee of its inputs' keysets
+        assert_eq!(
+            keyset.input_fee_ppk, target_keyset.input_fee_ppk,
+            "Keyset {} input_fee_ppk mismatch",
+            keyset.id
+        );
+        assert!(
+            target_keysets.contains(keyset),
+            "Missing keyset in the target"
+        );
+    }
+    say!("✅ All {} keysets match", source_keysets.len());
+
+    // Verify proofs for each keyset
+    say!("📋 Checking proofs for each keyset...");
+    let mut total_proofs = 0;
+    for keyset in &source_keysets {
+        let step = format!("proofs.{}", keyset.id);
+        if let Some(records) = checkpoints.passed(&step) {
+            total_proofs += records as usize;
+            continue;
+        }
+        let (mut source_proofs, mut source_states) =
+            source.get_proofs_by_keyset_id(&keyset.id).await?;
+        // --compact migrated each of them once
+        if compact {
+            compact::dedup_proofs(&mut source_proofs, &mut source_states)?;
+        }
+        let (target_proofs, _) = target.get_proofs_by_keyset_id(&keyset.id).await?;
+
+        assert_eq!(
+            source_proofs.len(),
+            target_proofs.len(),
+            "Proof count mismatch for keyset"
+        );
+
+        for (source_proof, source_state) in source_proofs.iter().zip(source_states.iter()) {
+            let matching_proof = target_proofs.iter().find(|p| p == &source_proof);
+            assert!(matching_proof.is_some(), "Missing proof in the target");
+
+            if let Some(source_state) = source_state {
+                let y = source_proof.y()?;
+                let target_state = target.get_proofs_states(&[y]).await?;
+                assert_eq!(
+                    source_state,
+                    &target_state.first().unwrap().unwrap(),
+                    "Proof state mismatch"
+                );
+            }
+        }
+        total_proofs += source_proofs.len();
+        checkpoints.pass(&step, source_proofs.len() as u64).await?;
+    }
+    say!("✅ All {} proofs match across all keysets", total_proofs);
+
+    // Verify quotes
+    say!("📋 Checking quotes...");
+    let mint_quotes = match checkpoints.passed("mint_quotes") {
+        Some(records) => records,
+        None => {
+            let records = compare_mint_quotes(source, target).await?;
+            checkpoints.pass("mint_quotes", records).await?;
+            records
+        }
+    };
+    let melt_quotes = match checkpoints.passed("melt_quotes") {
+        Some(records) => records,
+        None => {
+            let records = compare_melt_quotes(source, target, progress).await?;
+            checkpoints.pass("melt_quotes", records).await?;
+            records
+        }
+    };
+
+    Ok(Compared {
+        keysets: source_keysets.len(),
+        proofs: total_proofs,
+        mint_quotes,
+        melt_quotes,
+    })
+}
+
+/// Compares the number and total amount of the blind signatures of each
+/// keyset
+pub async fn compare_blind_signatures<S: MintBackend, T: MintBackend>(
+    source: &S,
+    target: &T,
+    checkpoints: &mut Checkpoints,
+) -> Result<usize> {
+    let keysets = source.get_keyset_infos().await?;
+    say!(
+        "Checking blind signatures across {} keysets...",
+        keysets.len()
+    );
+
+    let mut total_source_amount = 0u64;
+    let mut total_target_amount = 0u64;
+    let mut total_sigs = 0usize;
+
+    for keyset in keysets {
+        let step = format!("blind_signatures.{}", keyset.id);
+        if let Some(records) = checkpoints.passed(&step) {
+            total_sigs += records as usize;
+            continue;
+        }
+        say!("📋 Checking blind signatures for keyset: {}", keyset.id);
+
+        let source_sigs = source.get_blind_signatures_for_keyset(&keyset.id).await?;
+        let source_amount_sum: u64 = source_sigs.iter().map(|sig| u64::from(sig.amount)).sum();
+        say!(
+            "Found {} signatures in the source with total amount {}",
+            source_sigs.len(),
+            source_amount_sum
+        );
+
+        let target_sigs = target.get_blind_signatures_for_keyset(&keyset.id).await?;
+        let target_amount_sum: u64 = target_sigs.iter().map(|sig| u64::from(sig.amount)).sum();
+        say!(
+            "Found {} signatures in the target with total amount {}",
+            target_sigs.len(),
+            target_amount_sum
+        );
+
+        assert_eq!(
+            source_sigs.len(),
+            target_sigs.len(),
+            "Blind signature count mismatch for keyset {}: the source has {} but the target has {}",
+            keyset.id,
+            source_sigs.len(),
+            target_sigs.len()
+        );
+        assert_eq!(
+            source_amount_sum, target_amount_sum,
+            "Total amount mismatch for keyset {}: the source total is {} but the target total is {}",
+            keyset.id, source_amount_sum, target_amount_sum
+        );
+
+        total_source_amount += source_amount_sum;
+        total_target_amount += target_amount_sum;
+        total_sigs += source_sigs.len();
+
+        say!("✅ All blind signatures match for keyset {}", keyset.id);
+        checkpoints.pass(&step, source_sigs.len() as u64).await?;
+    }
+
+    say!("\n✅ Blind signatures verification complete!");
+    say!("Total blind signatures: {}", total_sigs);
+    say!("Total amount: {} units", total_source_amount);
+    assert_eq!(
+        total_source_amount, total_target_amount,
+        "Total amounts don't match across all keysets"
+    );
+
+    Ok(total_sigs)
+}
+
+async fn compare_mint_quotes<S: MintBackend, T: MintBackend>(
+    source: &S,
+    target: &T,
+) -> Result<u64> {
+    let source_mint_quotes = source.get_mint_quotes().await?;
+    let target_mint_quotes = target.get_mint_quotes().await?;
+    assert_eq!(
+        source_mint_quotes.len(),
+        target_mint_quotes.len(),
+        "Mint quote count mismatch"
+    );
+
+    for quote in &source_mint_quotes {
+        let target_quote = target_mint_quotes
+            .iter()
+            .find(|target_quote| target_quote.id == quote.id)
+            .unwrap_or_else(|| panic!("Missing mint quote {} in the target", quote.id));
+        // Compare the timestamps on their own so a drift names the field
+        assert_eq!(
+            quote.created_time, target_quote.created_time,
+            "Mint quote {} created_time mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.paid_time, target_quote.paid_time,
+            "Mint quote {} paid_time mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.issued_time, target_quote.issued_time,
+            "Mint quote {} issued_time mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.expiry, target_quote.expiry,
+            "Mint quote {} expiry mismatch",
+            quote.id
+        );
+        assert_request(
+            "Mint",
+            &quote.id,
+            (&quote.request, &target_quote.request),
+            (&quote.request_lookup_id, &target_quote.request_lookup_id),
+        );
+        assert!(
+            target_mint_quotes.contains(quote),
+            "Missing mint quote in the target"
+        );
+    }
+    say!("✅ All {} mint quotes match", source_mint_quotes.len());
+    say!(
+        "✅ Mint quote requests keep their payment method: {}",
+        payment_methods::describe_counts(&payment_methods::count_kinds(
+            source_mint_quotes
+                .iter()
+                .map(|quote| quote.request.as_str())
+        ))
+    );
+
+    Ok(source_mint_quotes.len() as u64)
+}
+
+async fn compare_melt_quotes<S: MintBackend, T: MintBackend>(
+    source: &S,
+    target: &T,
+    progress: &dyn ProgressObserver,
+) -> Result<u64> {
+    let source_melt_quotes = source.get_melt_quotes().await?;
+    let target_melt_quotes = target.get_melt_quotes().await?;
+    assert_eq!(
+        source_melt_quotes.len(),
+        target_melt_quotes.len(),
+        "Melt quote count mismatch"
+    );
+
+    // A mint that loses the reserve of a pending melt mis-accounts its fees
+    let source_reserves = melt_quotes::fee_reserves(&source_melt_quotes);
+    let target_reserves = melt_quotes::fee_reserves(&target_melt_quotes);
+    for (unit, (total, pending)) in &source_reserves {
+        assert_eq!(
+            Some(&(*total, *pending)),
+            target_reserves.get(unit),
+            "Melt quote fee reserve mismatch for unit {}",
+            unit
+        );
+        say!(
+            "✅ {} {} fee reserve, {} {} on pending melts, match",
+            total,
+            unit,
+            pending,
+            unit
+        );
+    }
+
+    let mut paid_with_preimage = 0;
+    for quote in &source_melt_quotes {
+        let quote = &melt_quotes::convert_melt_quote(quote);
+        let target_quote = target_melt_quotes
+            .iter()
+            .find(|target_quote| target_quote.id == quote.id)
+            .unwrap_or_else(|| panic!("Missing melt quote {} in the target", quote.id));
+        assert_eq!(
+            quote.created_time, target_quote.created_time,
+            "Melt quote {} created_time mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.paid_time, target_quote.paid_time,
+            "Melt quote {} paid_time mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.expiry, target_quote.expiry,
+            "Melt quote {} expiry mismatch",
+            quote.id
+        );
+        // Amounts are compared exactly so lost sub-sat precision names the field
+        assert_eq!(
+            quote.amount, target_quote.amount,
+            "Melt quote {} amount mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.fee_reserve, target_quote.fee_reserve,
+            "Melt quote {} fee_reserve mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.msat_to_pay, target_quote.msat_to_pay,
+            "Melt quote {} msat_to_pay mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.unit, target_quote.unit,
+            "Melt quote {} unit mismatch",
+            quote.id
+        );
+        // The preimage is the mint's proof of payment, a dispute over a paid
+        // melt cannot be settled without it
+        assert_eq!(
+            quote.state, target_quote.state,
+            "Melt quote {} state mismatch",
+            quote.id
+        );
+        assert_eq!(
+            quote.payment_preimage, target_quote.payment_preimage,
+            "Melt quote {} payment_preimage mismatch",
+            quote.id
+        );
+        if quote.state == MeltQuoteState::Paid {
+            match &quote.payment_preimage {
+                Some(_) => paid_with_preimage += 1,
+                // Already missing in the source, so only reported
+                None => warn(
+                    progress,
+                    WarningCode::MissingPreimage,
+                    format!(
+                        "Melt quote {} is paid but has no payment preimage in the source",
+                        quote.id
+                    ),
+                ),
+            }
+        }
+        assert_request(
+            "Melt",
+            &quote.id,
+            (&quote.request, &target_quote.request),
+            (&quote.request_lookup_id, &target_quote.request_lookup_id),
+        );
+
+        // The melt request names the backend that pays the quote
+        // As in the migration, a redb source without the table has no melt
+        // requests
+        let source_melt_request = source.get_melt_request(&quote.id).await.ok().flatten();
+        let target_melt_request = target.get_melt_request(&quote.id).await?;
+        assert_eq!(
+            source_melt_request, target_melt_request,
+            "Melt quote {} melt request mismatch",
+            quote.id
+        );
+        if let Some((_, payment_key)) = &target_melt_request {
+            let kind = payment_methods::request_kind(&quote.request);
+            // Already off in the source, so only reported
+            if !payment_methods::method_matches(kind, &payment_key.method) {
+                warn(
+                    progress,
+                    WarningCode::PaymentMethod,
+                    format!(
+                        "Melt quote {}: {} request is paid with payment method {}",
+                        quote.id, kind, payment_key.method
+                    ),
+                );
+            }
+        }
+        // Already off in the source, so only reported
+        if let Some(mismatch) = melt_quotes::msat_mismatch(target_quote) {
+            warn(
+                progress,
+                WarningCode::MsatMismatch,
+                format!("Melt quote {}: {}", quote.id, mismatch),
+            );
+        }
+        assert!(
+            target_melt_quotes.contains(quote),
+            "Missing melt quote in the target"
+        );
+        if melt_quotes::is_amountless(quote) && quote.msat_to_pay.is_some() {
+            assert_ne!(
+                target_quote.amount,
+                Amount::ZERO,
+                "Amountless melt quote {} has a zero amount in the target",
+                quote.id
+            );
+        }
+    }
+    say!("✅ All {} melt quotes match", source_melt_quotes.len());
+    say!(
+        "✅ {} paid melt quote(s) keep their payment preimage",
+        paid_with_preimage
+    );
+    say!(
+        "✅ Melt quote requests keep their payment method: {}",
+        payment_methods::describe_counts(&payment_methods::count_kinds(
+            source_melt_quotes
+                .iter()
+                .map(|quote| quote.request.as_str())
+        ))
+    );
+
+    Ok(source_melt_quotes.len() as u64)
+}
+
+/// Reports something that was already off in the source
+fn warn(progress: &dyn ProgressObserver, code: WarningCode, message: String) {
+    progress.warning(code, &message);
+    say!("⚠️  [{}] {}", code, message);
+}
+
+// Compares the request of a quote field by field, so a bolt12 offer that came
+// out bolt11-shaped names what changed
+fn assert_request(
+    quotes: &str,
+    id: &Uuid,
+    (source_request, target_request): (&str, &str),
+    (source_lookup_id, target_lookup_id): (&str, &str),
+) {
+    let source_kind = payment_methods::request_kind(source_request);
+    let target_kind = payment_methods::request_kind(target_request);
+    assert_eq!(
+        source_kind, target_kind,
+        "{} quote {} payment method changed from {} to {}",
+        quotes, id, source_kind, target_kind
+    );
+    assert_eq!(
+        source_request, target_request,
+        "{} quote {} {} request mismatch",
+        quotes, id, source_kind
+    );
+    assert_eq!(
+        source_lookup_id, target_lookup_id,
+        "{} quote {} request_lookup_id mismatch",
+        quotes, id
+    );
+}
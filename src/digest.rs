@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::output;
+use crate::progress::{Phase, ProgressObserver};
+
+// `--json` ends every command with one line of JSON on stdout for scripts to
+// read, e.g. with jq. Everything else the run prints, the console output and
+// the logs, goes to stderr instead: stdout is pointed at stderr for the whole
+// run and only the digest is written to the original stdout.
+
+#[derive(Serialize)]
+struct Digest {
+    command: String,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    duration_ms: u128,
+    // Records written per phase of a migration
+    counts: BTreeMap<String, u64>,
+    warnings: Vec<String>,
+}
+
+/// Collects the record counts of a migration for the digest
+#[derive(Default)]
+pub struct DigestRecorder {
+    counts: Mutex<BTreeMap<String, u64>>,
+}
+
+impl ProgressObserver for DigestRecorder {
+    fn records(&self, phase: Phase, done: u64, _total: Option<u64>) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.insert(phase.to_string().replace(' ', "_"), done);
+    }
+}
+
+static RECORDER: OnceLock<Arc<DigestRecorder>> = OnceLock::new();
+
+/// Observer to hand to a migration when a digest is printed
+pub fn recorder() -> Option<Arc<DigestRecorder>> {
+    RECORDER.get().cloned()
+}
+
+pub struct DigestOutput {
+    command: String,
+    started: Instant,
+    #[cfg(unix)]
+    stdout: std::fs::File,
+}
+
+/// Moves stdout over to stderr, keeping the original for the digest
+#[cfg(unix)]
+pub fn start(command: &str) -> Result<DigestOutput> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    // Plain integer arguments, and the duplicate is owned right away
+    let stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if stdout < 0 || unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(anyhow!(
+            "Cannot redirect stdout for --json: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    let stdout = unsafe { OwnedFd::from_raw_fd(stdout) };
+
+    let _ = RECORDER.set(Arc::default());
+    output::record_warnings();
+
+    Ok(DigestOutput {
+        command: command.to_string(),
+        started: Instant::now(),
+        stdout: stdout.into(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn start(_command: &str) -> Result<DigestOutput> {
+    Err(anyhow!("--json is only supported on unix"))
+}
+
+impl DigestOutput {
+    /// Prints the digest of a run that ended in `outcome`, an error message
+    /// if it failed
+    pub fn finish(self, outcome: Result<(), String>) {
+        let counts = recorder()
+            .map(|recorder| {
+                recorder
+                    .counts
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone()
+            })
+            .unwrap_or_default();
+        let digest = Digest {
+            command: self.command,
+            status: if outcome.is_ok() { "ok" } else { "failed" },
+            error: outcome.err(),
+            duration_ms: self.started.elapsed().as_millis(),
+            counts,
+            warnings: output::take_warnings(),
+        };
+
+        #[cfg(not(unix))]
+        let _ = digest;
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+
+            let _ = std::io::stdout().flush();
+            let mut stdout = self.stdout;
+            if let Ok(json) = serde_json::to_string(&digest) {
+                let _ = writeln!(stdout, "{}", json);
+            }
+        }
+    }
+}
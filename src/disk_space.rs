@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+use crate::output::say;
+use crate::paths::MigrationPaths;
+use crate::raw::RedbReader;
+
+// A migration that runs out of disk dies in the middle of a write with an
+// I/O error from deep inside SQLite. Before anything is written, the size of
+// the SQLite databases is estimated and compared with the free space where
+// they are built, and where they are copied to when built in --tmp-dir on
+// another filesystem.
+
+// Bytes a migrated record takes in SQLite, with its share of the indexes,
+// rounded up from proofs and blind signatures
+const BYTES_PER_RECORD: u64 = 1024;
+
+// Left free for SQLite's journal and temporary files on top of the WAL
+const MARGIN: u64 = 16 * 1024 * 1024;
+
+/// Fails if the filesystems the migration writes to lack the space it needs
+pub fn check(paths: &MigrationPaths, building: &MigrationPaths, repair: bool) -> Result<()> {
+    let mut sources = vec![paths.redb.as_path()];
+    if paths.auth_redb.exists() {
+        sources.push(&paths.auth_redb);
+    }
+    let estimate: u64 = sources.iter().map(|redb| estimate(redb)).sum();
+
+    // The WAL can grow to the size of the largest transaction before it is
+    // checkpointed, and proofs are written a keyset at a time
+    let mut needed = estimate * 2 + MARGIN;
+    if repair {
+        // The repaired copies of the redb are made next to the build
+        needed += sources.iter().map(|redb| file_size(redb)).sum::<u64>();
+    }
+
+    let build_dir = building.sqlite.parent().unwrap_or(Path::new("."));
+    let target_dir = paths.sqlite.parent().unwrap_or(Path::new("."));
+    let mut checks = vec![(build_dir, needed)];
+    if !same_filesystem(build_dir, target_dir) {
+        checks.push((target_dir, estimate + MARGIN));
+    }
+
+    for (dir, needed) in checks {
+        let Some(available) = available_space(dir)? else {
+            tracing::warn!("Cannot check the free space on this platform");
+            return Ok(());
+        };
+        if available < needed {
+            return Err(anyhow!(
+                "{:?} has {} free, the migration needs about {} for the SQLite databases and their WAL. Free up space, build elsewhere with --tmp-dir or skip this check with --skip-space-check",
+                dir,
+                mib(available),
+                mib(needed)
+            ));
+        }
+        tracing::info!(
+            "{:?} has {} free, the migration needs about {}",
+            dir,
+            mib(available),
+            mib(needed)
+        );
+    }
+    say!(
+        "✅ Enough free space for about {} of SQLite databases",
+        mib(estimate)
+    );
+
+    Ok(())
+}
+
+/// Size the SQLite database migrated from `redb` will take
+fn estimate(redb: &Path) -> u64 {
+    let size = file_size(redb);
+
+    // redb keeps the pages it freed, so an old database can be much larger
+    // than its records
+    match count_all_records(redb) {
+        Some(records) => size.min(records * BYTES_PER_RECORD),
+        None => size,
+    }
+}
+
+fn count_all_records(redb: &Path) -> Option<u64> {
+    RedbReader::open(redb).ok()?.record_count().ok()
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or_default()
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// The directory itself or, if it is not created yet, the closest parent
+fn existing(dir: &Path) -> &Path {
+    dir.ancestors()
+        .find(|dir| dir.exists())
+        .unwrap_or(Path::new("."))
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (
+        std::fs::metadata(existing(a)),
+        std::fs::metadata(existing(b)),
+    ) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Result<Option<u64>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = existing(dir);
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // statvfs only writes into the struct it is given
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(anyhow!(
+            "Cannot check the free space of {:?}: {}",
+            dir,
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    // The field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn available_space(_dir: &Path) -> Result<Option<u64>> {
+    Ok(None)
+}
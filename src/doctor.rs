@@ -0,0 +1,423 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_common::database::{self, MintDatabase, MintKeysDatabase};
+use cdk_common::mint::MintKeySetInfo;
+use cdk_common::nuts::{Id, ProofsMethods};
+use cdk_common::{Amount, MeltQuoteState, MintQuoteState, PublicKey, State};
+use cdk_redb::MintRedbDatabase;
+use cdk_sqlite::MintSqliteDatabase;
+use uuid::Uuid;
+
+use crate::cli::Backend;
+use crate::column_audit::audit_columns;
+use crate::compact::dedup_proofs;
+use crate::inspect;
+use crate::melt_quotes::msat_mismatch;
+use crate::output::say;
+use crate::progress::NoProgress;
+use crate::raw::RedbReader;
+use crate::spending_conditions::{invalid_secrets, print_invalid_secrets};
+use crate::sqlite;
+use crate::timestamp_audit::audit_timestamps;
+use crate::validate_proofs::validate_db_proofs;
+
+// `doctor` runs the checks a migration does on its result against one mint
+// database as it is, redb or SQLite, so it works on a mint that was never
+// migrated as well as on one that has been running on SQLite since. Problems
+// are inconsistencies mintd can trip over and fail the run, warnings are
+// oddities worth a look.
+
+#[derive(Default)]
+struct Findings {
+    problems: usize,
+    warnings: usize,
+}
+
+impl Findings {
+    fn problem(&mut self, message: String) {
+        self.problems += 1;
+        say!("❌ {}", message);
+    }
+
+    fn warning(&mut self, message: String) {
+        self.warnings += 1;
+        say!("⚠️  {}", message);
+    }
+}
+
+pub async fn doctor(work_dir: PathBuf, backend: Backend, validate_proofs: bool) -> Result<()> {
+    let mut findings = Findings::default();
+
+    match backend {
+        Backend::Redb => {
+            let path = inspect::redb_path(&work_dir, false);
+            say!("🩺 Checking {:?}", path);
+
+            // redb allows one handle per file, the raw one is closed before
+            // cdk-redb opens it
+            check_redb_orphans(&path, &mut findings)?;
+            let db = MintRedbDatabase::new(&path)?;
+            check_database(&work_dir, &db, validate_proofs, &mut findings).await?;
+        }
+        Backend::Sqlite => {
+            let path = inspect::sqlite_path(&work_dir, false);
+            // cdk-sqlite would create an empty database where there is none
+            if !path.exists() {
+                return Err(anyhow!("No SQLite database found at {:?}", path));
+            }
+            say!("🩺 Checking {:?}", path);
+
+            check_sqlite_orphans(&path, &mut findings).await?;
+            let db = MintSqliteDatabase::new(&path).await?;
+            check_database(&work_dir, &db, validate_proofs, &mut findings).await?;
+            audit_columns(&path, false, &NoProgress).await?;
+            audit_timestamps(&path, &NoProgress).await?;
+        }
+    }
+
+    if findings.problems > 0 {
+        return Err(anyhow!(
+            "{} problem(s) and {} warning(s) found",
+            findings.problems,
+            findings.warnings
+        ));
+    }
+
+    say!("\n✅ No problems found, {} warning(s)", findings.warnings);
+
+    Ok(())
+}
+
+async fn check_database<D>(
+    work_dir: &Path,
+    db: &D,
+    validate_proofs: bool,
+    findings: &mut Findings,
+) -> Result<()>
+where
+    D: MintDatabase<database::Error> + MintKeysDatabase<Err = database::Error>,
+{
+    let keysets = check_keysets(db, findings).await?;
+    check_proofs(db, &keysets, findings).await?;
+    check_quotes(db, findings).await?;
+
+    // A bad signature is already reported as a problem by the check itself
+    if validate_proofs && let Err(err) = validate_db_proofs(work_dir, db).await {
+        findings.problem(err.to_string());
+    }
+
+    Ok(())
+}
+
+async fn check_keysets<D>(db: &D, findings: &mut Findings) -> Result<Vec<MintKeySetInfo>>
+where
+    D: MintKeysDatabase<Err = database::Error>,
+{
+    say!("\n📋 Checking keysets...");
+
+    let keysets = db.get_keyset_infos().await?;
+    let mut active = HashMap::new();
+    for keyset in keysets.iter().filter(|keyset| keyset.active) {
+        *active.entry(keyset.unit.clone()).or_insert(0) += 1;
+    }
+    for (unit, count) in active.iter().filter(|(_, count)| **count > 1) {
+        findings.warning(format!(
+            "{} keysets of unit {} are marked active, mintd signs with only one",
+            count, unit
+        ));
+    }
+
+    for (unit, id) in db.get_active_keysets().await? {
+        if !keysets.iter().any(|keyset| keyset.id == id) {
+            findings.problem(format!(
+                "Active keyset {} of unit {} has no keyset info",
+                id, unit
+            ));
+        }
+    }
+
+    Ok(keysets)
+}
+
+async fn check_proofs<D>(db: &D, keysets: &[MintKeySetInfo], findings: &mut Findings) -> Result<()>
+where
+    D: MintDatabase<database::Error>,
+{
+    say!("\n📋 Checking proofs and blind signatures...");
+
+    let mut invalid = vec![];
+    for keyset in keysets {
+        let (mut proofs, mut states) = db.get_proofs_by_keyset_id(&keyset.id).await?;
+
+        let duplicates = dedup_proofs(&mut proofs, &mut states)?;
+        if duplicates > 0 {
+            findings.warning(format!(
+                "{} proof(s) of keyset {} are stored more than once, --compact migrates them once",
+                duplicates, keyset.id
+            ));
+        }
+
+        let ys: HashSet<_> = proofs.ys()?.into_iter().collect();
+        if ys.len() < proofs.len() {
+            findings.problem(format!(
+                "{} proof(s) of keyset {} share their Y with a different proof",
+                proofs.len() - ys.len(),
+                keyset.id
+            ));
+        }
+
+        let bad_amounts = proofs
+            .iter()
+            .filter(|proof| !valid_amount(proof.amount, keyset.max_order))
+            .count();
+        if bad_amounts > 0 {
+            findings.problem(format!(
+                "{} proof(s) of keyset {} have an amount the keyset has no key for",
+                bad_amounts, keyset.id
+            ));
+        }
+
+        invalid.extend(invalid_secrets(&proofs, &states)?);
+
+        let signatures = db.get_blind_signatures_for_keyset(&keyset.id).await?;
+        let bad_amounts = signatures
+            .iter()
+            .filter(|signature| !valid_amount(signature.amount, keyset.max_order))
+            .count();
+        if bad_amounts > 0 {
+            findings.problem(format!(
+                "{} blind signature(s) of keyset {} have an amount the keyset has no key for",
+                bad_amounts, keyset.id
+            ));
+        }
+
+        // The mint cannot have taken back more than it signed
+        let issued = sum(signatures.iter().map(|signature| signature.amount));
+        let redeemed = sum(proofs
+            .iter()
+            .zip(&states)
+            .filter(|(_, state)| matches!(state, Some(State::Spent | State::Pending)))
+            .map(|(proof, _)| proof.amount));
+        if redeemed > issued {
+            findings.problem(format!(
+                "Keyset {} redeemed {} but only issued {}",
+                keyset.id, redeemed, issued
+            ));
+        }
+    }
+
+    if !invalid.is_empty() {
+        findings.warnings += 1;
+        print_invalid_secrets(&invalid);
+    }
+
+    Ok(())
+}
+
+async fn check_quotes<D>(db: &D, findings: &mut Findings) -> Result<()>
+where
+    D: MintDatabase<database::Error>,
+{
+    say!("\n📋 Checking quotes...");
+
+    for quote in db.get_melt_quotes().await? {
+        if let Some(mismatch) = msat_mismatch(&quote) {
+            findings.warning(format!("Melt quote {}: {}", quote.id, mismatch));
+        }
+
+        let ys = db.get_proof_ys_by_quote_id(&quote.id).await?;
+        let states = db.get_proofs_states(&ys).await?;
+        let (expected, description) = match quote.state {
+            MeltQuoteState::Pending => (State::Pending, "pending"),
+            MeltQuoteState::Paid => (State::Spent, "spent"),
+            _ => continue,
+        };
+        if ys.is_empty() && quote.state == MeltQuoteState::Pending {
+            findings.warning(format!(
+                "Pending melt quote {} has no proofs reserved",
+                quote.id
+            ));
+        }
+        let off = states
+            .iter()
+            .filter(|state| **state != Some(expected))
+            .count();
+        if off > 0 {
+            findings.problem(format!(
+                "{} proof(s) of {} melt quote {} are not {}",
+                off,
+                quote.state.to_string().to_lowercase(),
+                quote.id,
+                description
+            ));
+        }
+    }
+
+    for quote in db.get_mint_quotes().await? {
+        if quote.state != MintQuoteState::Issued {
+            continue;
+        }
+
+        // Quotes issued before signatures were linked to them have none
+        let signatures = db.get_blind_signatures_for_quote(&quote.id).await?;
+        let signed = sum(signatures.iter().map(|signature| signature.amount));
+        if !signatures.is_empty() && signed != quote.amount {
+            findings.problem(format!(
+                "Mint quote {} for {} was issued with signatures for {}",
+                quote.id, quote.amount, signed
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn valid_amount(amount: Amount, max_order: u8) -> bool {
+    let amount = u64::from(amount);
+    amount.is_power_of_two()
+        && 1u64
+            .checked_shl(max_order.into())
+            .is_none_or(|max| amount < max)
+}
+
+fn sum(amounts: impl Iterator<Item = Amount>) -> Amount {
+    Amount::from(amounts.map(u64::from).sum::<u64>())
+}
+
+// SQLite no longer enforces the keyset foreign keys and never had ones for
+// quotes, so rows can point at records that are gone
+const SQLITE_ORPHANS: &[(&str, &str, bool)] = &[
+    (
+        "proof(s) belong to a keyset that does not exist",
+        "SELECT count(*) FROM proof WHERE keyset_id NOT IN (SELECT id FROM keyset)",
+        true,
+    ),
+    (
+        "blind signature(s) belong to a keyset that does not exist",
+        "SELECT count(*) FROM blind_signature WHERE keyset_id NOT IN (SELECT id FROM keyset)",
+        true,
+    ),
+    (
+        "proof(s) reference a melt quote that does not exist",
+        "SELECT count(*) FROM proof WHERE quote_id IS NOT NULL AND quote_id NOT IN (SELECT id FROM melt_quote)",
+        false,
+    ),
+    (
+        "blind signature(s) reference a mint quote that does not exist",
+        "SELECT count(*) FROM blind_signature WHERE quote_id IS NOT NULL AND quote_id NOT IN (SELECT id FROM mint_quote)",
+        false,
+    ),
+    (
+        "melt request(s) belong to a melt quote that does not exist",
+        "SELECT count(*) FROM melt_request WHERE id NOT IN (SELECT id FROM melt_quote)",
+        false,
+    ),
+];
+
+async fn check_sqlite_orphans(path: &Path, findings: &mut Findings) -> Result<()> {
+    say!("\n📋 Checking for orphaned records...");
+
+    let pool = sqlite::connect(path).await?;
+    for (description, query, problem) in SQLITE_ORPHANS {
+        let count: i64 = sqlx::query_scalar(query).fetch_one(&pool).await?;
+        report_orphans(findings, count as usize, description, *problem);
+    }
+    pool.close().await;
+
+    Ok(())
+}
+
+fn check_redb_orphans(path: &Path, findings: &mut Findings) -> Result<()> {
+    say!("\n📋 Checking for orphaned records...");
+
+    let reader = RedbReader::open(path)?;
+
+    let keysets: HashSet<Id> = reader.keysets()?.iter().map(|keyset| keyset.id).collect();
+    let mint_quotes: HashSet<Uuid> = reader.mint_quotes()?.iter().map(|quote| quote.id).collect();
+    let melt_quotes: HashSet<Uuid> = reader.melt_quotes()?.iter().map(|quote| quote.id).collect();
+    let proofs = reader.proofs()?;
+
+    let orphaned = proofs
+        .iter()
+        .filter(|(_, proof)| !keysets.contains(&proof.keyset_id))
+        .count();
+    report_orphans(
+        findings,
+        orphaned,
+        "proof(s) belong to a keyset that does not exist",
+        true,
+    );
+
+    let orphaned = reader
+        .blind_signatures()?
+        .iter()
+        .filter(|(_, signature)| !keysets.contains(&signature.keyset_id))
+        .count();
+    report_orphans(
+        findings,
+        orphaned,
+        "blind signature(s) belong to a keyset that does not exist",
+        true,
+    );
+
+    let proof_ys: HashSet<PublicKey> = proofs.iter().map(|(y, _)| *y).collect();
+    let orphaned = reader
+        .proof_states()?
+        .keys()
+        .filter(|y| !proof_ys.contains(y))
+        .count();
+    report_orphans(
+        findings,
+        orphaned,
+        "proof state(s) belong to no proof",
+        false,
+    );
+
+    let orphaned = reader
+        .quote_proofs()?
+        .iter()
+        .filter(|(quote, _)| !melt_quotes.contains(quote))
+        .count();
+    report_orphans(
+        findings,
+        orphaned,
+        "proof(s) reference a melt quote that does not exist",
+        false,
+    );
+
+    let orphaned = reader
+        .quote_signatures()?
+        .iter()
+        .filter(|(quote, _)| !mint_quotes.contains(quote))
+        .count();
+    report_orphans(
+        findings,
+        orphaned,
+        "blind signature(s) reference a mint quote that does not exist",
+        false,
+    );
+
+    let orphaned = reader
+        .melt_requests()?
+        .iter()
+        .filter(|(quote, ..)| !melt_quotes.contains(quote))
+        .count();
+    report_orphans(
+        findings,
+        orphaned,
+        "melt request(s) belong to a melt quote that does not exist",
+        false,
+    );
+
+    Ok(())
+}
+
+fn report_orphans(findings: &mut Findings, count: usize, description: &str, problem: bool) {
+    match (count, problem) {
+        (0, _) => (),
+        (count, true) => findings.problem(format!("{} {}", count, description)),
+        (count, false) => findings.warning(format!("{} {}", count, description)),
+    }
+}
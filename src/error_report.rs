@@ -0,0 +1,331 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, Registry, Scope};
+
+use crate::output::say;
+
+// With --error-report a run that fails or panics leaves a JSON bundle with
+// the error, the spans it happened in (migration, phase, batch), the last log
+// lines and the versions involved, so an operator can attach it to a bug
+// report as is instead of reproducing the failure with more logging.
+
+// Log lines kept for the report
+const LOG_LINES: usize = 500;
+
+// The cdk crates the migration is built against, kept in line with Cargo.toml
+const CDK_VERSION: &str = "0.10.0";
+
+// Arguments whose value is a secret and stays out of the report
+const SECRET_ARGS: &[&str] = &["--signing-key"];
+
+pub enum Destination {
+    File(PathBuf),
+    // Only plain http, e.g. a collector on the host or a local relay
+    Url(String),
+}
+
+impl Destination {
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.starts_with("http://") {
+            Ok(Self::Url(value.to_string()))
+        } else if value.starts_with("https://") {
+            Err(anyhow!(
+                "Error reports can only be sent to http:// URLs, write them to a file instead"
+            ))
+        } else {
+            Ok(Self::File(PathBuf::from(value)))
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct PanicReport {
+    message: String,
+    location: Option<String>,
+    thread: Option<String>,
+    spans: Vec<String>,
+    backtrace: String,
+}
+
+struct Recorded {
+    started: Instant,
+    log: VecDeque<String>,
+    // Spans of the last log line, outermost first
+    spans: Vec<String>,
+    // Also panics redb raised and the migration recovered from, which only
+    // end up in a report if the run fails after all
+    panics: Vec<PanicReport>,
+}
+
+#[derive(Serialize)]
+struct Bundle {
+    tool: &'static str,
+    version: &'static str,
+    cdk: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    created: u64,
+    arguments: Vec<String>,
+    error: Option<String>,
+    spans: Vec<String>,
+    panics: Vec<PanicReport>,
+    log: Vec<String>,
+}
+
+/// Collects what goes into a report while the run goes on
+pub struct ErrorReport {
+    destination: Destination,
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+/// Records log lines and the spans they were logged in
+pub struct Recorder {
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+pub fn install(destination: Destination) -> (ErrorReport, Recorder) {
+    let recorded = Arc::new(Mutex::new(Recorded {
+        started: Instant::now(),
+        log: VecDeque::with_capacity(LOG_LINES),
+        spans: vec![],
+        panics: vec![],
+    }));
+
+    let hook_recorded = recorded.clone();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => info
+                .payload()
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        };
+        let panic = PanicReport {
+            message,
+            location: info.location().map(|location| location.to_string()),
+            thread: std::thread::current().name().map(str::to_string),
+            spans: current_spans(),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+        };
+        if let Ok(mut recorded) = hook_recorded.lock() {
+            recorded.panics.push(panic);
+        }
+        previous(info);
+    }));
+
+    (
+        ErrorReport {
+            destination,
+            recorded: recorded.clone(),
+        },
+        Recorder { recorded },
+    )
+}
+
+impl ErrorReport {
+    /// Writes or sends the report of a failed run, `error` is `None` after a
+    /// panic. Failing to do so is printed but leaves the run's error as is.
+    pub async fn send(&self, error: Option<&anyhow::Error>) {
+        let bundle = self.bundle(error);
+        let sent = match serde_json::to_vec_pretty(&bundle) {
+            Ok(json) => match &self.destination {
+                Destination::File(path) => std::fs::write(path, json).map_err(anyhow::Error::from),
+                Destination::Url(url) => post(url, &json).await,
+            },
+            Err(err) => Err(err.into()),
+        };
+
+        match sent {
+            Ok(()) => say!("📝 Error report written to {}", self.destination),
+            Err(err) => say!(
+                "⚠️  Could not write the error report to {}: {}",
+                self.destination,
+                err
+            ),
+        }
+    }
+
+    fn bundle(&self, error: Option<&anyhow::Error>) -> Bundle {
+        let recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut arguments: Vec<String> = std::env::args().collect();
+        for i in 0..arguments.len() {
+            if let Some(secret) = SECRET_ARGS.iter().find(|arg| {
+                arguments[i]
+                    .strip_prefix(**arg)
+                    .is_some_and(|rest| rest.starts_with('='))
+            }) {
+                arguments[i] = format!("{}=<redacted>", secret);
+            } else if i > 0 && SECRET_ARGS.contains(&arguments[i - 1].as_str()) {
+                arguments[i] = "<redacted>".to_string();
+            }
+        }
+
+        Bundle {
+            tool: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            cdk: CDK_VERSION,
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            arguments,
+            // With the causes, and a backtrace if RUST_BACKTRACE is set
+            error: error.map(|err| format!("{:?}", err)),
+            spans: recorded.spans.clone(),
+            panics: recorded.panics.clone(),
+            log: recorded.log.iter().cloned().collect(),
+        }
+    }
+}
+
+async fn post(url: &str, body: &[u8]) -> Result<()> {
+    let rest = url.trim_start_matches("http://");
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let addr = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(&addr).await?;
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        authority,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await?;
+    let status = String::from_utf8_lossy(&response)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(anyhow!("{} answered {:?}", url, status)),
+    }
+}
+
+// Fields of a span, stored with it for the report
+struct SpanFields(String);
+
+#[derive(Default)]
+struct FieldVisitor(String);
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, "{}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+}
+
+fn describe<'a, R: LookupSpan<'a>>(scope: Scope<'a, R>) -> Vec<String> {
+    scope
+        .from_root()
+        .map(|span| match span.extensions().get::<SpanFields>() {
+            Some(fields) if !fields.0.is_empty() => format!("{}{{{}}}", span.name(), fields.0),
+            _ => span.name().to_string(),
+        })
+        .collect()
+}
+
+// Spans the panicking code ran in
+fn current_spans() -> Vec<String> {
+    tracing::Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            let registry = dispatch.downcast_ref::<Registry>()?;
+            Some(describe(registry.span(id)?.scope()))
+        })
+        .flatten()
+        .unwrap_or_default()
+}
+
+impl<S> Layer<S> for Recorder
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.0));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<SpanFields>()
+        {
+            let mut visitor = FieldVisitor(std::mem::take(&mut fields.0));
+            values.record(&mut visitor);
+            fields.0 = visitor.0;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let spans = ctx.event_scope(event).map(describe).unwrap_or_default();
+
+        let Ok(mut recorded) = self.recorded.lock() else {
+            return;
+        };
+        let metadata = event.metadata();
+        let line = format!(
+            "+{:.3}s {} {}: {}",
+            recorded.started.elapsed().as_secs_f64(),
+            metadata.level(),
+            metadata.target(),
+            visitor.0
+        );
+        if recorded.log.len() == LOG_LINES {
+            recorded.log.pop_front();
+        }
+        recorded.log.push_back(line);
+        recorded.spans = spans;
+    }
+}
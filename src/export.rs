@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use cdk_common::bitcoin::secp256k1::schnorr::Signature;
+use cdk_common::database::MintKeysDatabase;
+use cdk_common::mint::MintKeySetInfo;
+use cdk_common::nuts::{CurrencyUnit, Id};
+use cdk_common::{PublicKey, SecretKey};
+use cdk_redb::MintRedbDatabase;
+use cdk_sqlite::MintSqliteDatabase;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{ExportFormat, ExportScope};
+use crate::output::say;
+use crate::output_sink::write_output;
+use crate::paths::MigrationPaths;
+use crate::redb_format;
+
+const KEYSET_EXPORT_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct KeysetPayload {
+    version: u8,
+    keysets: Vec<MintKeySetInfo>,
+    active_keysets: BTreeMap<CurrencyUnit, Id>,
+}
+
+// The payload is kept as the exact JSON text that was signed, so a later
+// change to how cdk serializes keyset infos cannot invalidate old exports.
+#[derive(Serialize, Deserialize)]
+struct KeysetExport {
+    payload: String,
+    pubkey: PublicKey,
+    signature: String,
+}
+
+pub async fn export(
+    work_dir: PathBuf,
+    format: ExportFormat,
+    only: Option<ExportScope>,
+    auth: bool,
+    output: &Path,
+    signing_key: Option<String>,
+) -> Result<()> {
+    let Some(output_format) = format.output_format() else {
+        return match only {
+            _ if auth => Err(anyhow!("--auth does not apply to signed exports")),
+            None => Err(anyhow!("A signed export needs --only")),
+            Some(ExportScope::Keysets) => export_keysets(&work_dir, output, signing_key).await,
+        };
+    };
+    if only.is_some() {
+        return Err(anyhow!(
+            "--only applies to signed exports, the other formats write the whole database"
+        ));
+    }
+
+    let paths = MigrationPaths::new(&work_dir);
+    let sql_db_path = if auth {
+        paths.auth_sqlite
+    } else {
+        paths.sqlite
+    };
+    if !sql_db_path.exists() {
+        return Err(anyhow!("No SQLite database found at {:?}", sql_db_path));
+    }
+    if output.exists() {
+        return Err(anyhow!(
+            "Export file already exists at {:?}. Will not overwrite it.",
+            output
+        ));
+    }
+    write_output(&sql_db_path, output, output_format).await
+}
+
+pub async fn import(
+    work_dir: PathBuf,
+    only: ExportScope,
+    input: &Path,
+    pubkey: Option<String>,
+) -> Result<()> {
+    match only {
+        ExportScope::Keysets => import_keysets(&work_dir, input, pubkey).await,
+    }
+}
+
+async fn export_keysets(work_dir: &Path, output: &Path, signing_key: Option<String>) -> Result<()> {
+    let redb_path = work_dir.join("cdk-mintd.redb");
+    if !redb_path.exists() {
+        return Err(anyhow!("No redb database found at {:?}", redb_path));
+    }
+    redb_format::check_format(&redb_path)?;
+
+    if output.exists() {
+        return Err(anyhow!(
+            "Export file already exists at {:?}. Will not overwrite it.",
+            output
+        ));
+    }
+
+    // A generated key is only used for this export and never shown, the
+    // public key printed below is all an import needs
+    let signing_key = match signing_key {
+        Some(key) => SecretKey::from_hex(key).map_err(|_| anyhow!("Invalid signing key"))?,
+        None => SecretKey::generate(),
+    };
+
+    let redb_db = MintRedbDatabase::new(&redb_path)?;
+
+    let payload = KeysetPayload {
+        version: KEYSET_EXPORT_VERSION,
+        keysets: redb_db.get_keyset_infos().await?,
+        active_keysets: redb_db.get_active_keysets().await?.into_iter().collect(),
+    };
+
+    let keysets = payload.keysets.len();
+    let payload = serde_json::to_string(&payload)?;
+    let signature = signing_key.sign(payload.as_bytes())?;
+
+    let export = KeysetExport {
+        payload,
+        pubkey: signing_key.public_key(),
+        signature: signature.to_string(),
+    };
+
+    std::fs::write(output, serde_json::to_string_pretty(&export)?)?;
+
+    say!("✅ Exported {} keysets to {:?}", keysets, output);
+    println!("Signed by {}, keep it to verify the import", export.pubkey);
+
+    Ok(())
+}
+
+async fn import_keysets(work_dir: &Path, input: &Path, pubkey: Option<String>) -> Result<()> {
+    let export: KeysetExport = serde_json::from_slice(&std::fs::read(input)?)?;
+
+    if let Some(pubkey) = pubkey {
+        let expected = PublicKey::from_hex(pubkey).map_err(|_| anyhow!("Invalid public key"))?;
+        if expected != export.pubkey {
+            return Err(anyhow!(
+                "Export was signed by {}, expected {}",
+                export.pubkey,
+                expected
+            ));
+        }
+    } else {
+        say!(
+            "⚠️  No --pubkey given, only checking the export against its embedded key {}",
+            export.pubkey
+        );
+    }
+
+    let signature =
+        Signature::from_str(&export.signature).map_err(|_| anyhow!("Malformed signature"))?;
+    export
+        .pubkey
+        .verify(export.payload.as_bytes(), &signature)
+        .map_err(|_| anyhow!("Signature does not match the keyset data, refusing to import"))?;
+    let payload: KeysetPayload = serde_json::from_str(&export.payload)?;
+
+    if payload.version != KEYSET_EXPORT_VERSION {
+        return Err(anyhow!(
+            "Unsupported keyset export version {}",
+            payload.version
+        ));
+    }
+
+    let sql_db_path = work_dir.join("cdk-mintd.sqlite");
+    println!("Importing keysets into {:?}", sql_db_path);
+
+    let sqlite_db = MintSqliteDatabase::new(&sql_db_path).await?;
+
+    for keyset in &payload.keysets {
+        tracing::info!("Importing keyset {}", keyset.id);
+        sqlite_db.add_keyset_info(keyset.clone()).await?;
+    }
+
+    for (unit, id) in &payload.active_keysets {
+        sqlite_db.set_active_keyset(unit.clone(), *id).await?;
+    }
+
+    say!(
+        "✅ Imported {} keysets, {} active",
+        payload.keysets.len(),
+        payload.active_keysets.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Deserializer};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::batch::panic_message;
+use crate::cli::{MigrateArgs, OutputFormat};
+use crate::output::say;
+use crate::permissions::{Owner, parse_mode, parse_owner};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default = "default_concurrency")]
+    concurrency: usize,
+    #[serde(default)]
+    retries: u32,
+    #[serde(default)]
+    low_memory: bool,
+    tmp_dir: Option<PathBuf>,
+    #[serde(rename = "mint")]
+    mints: Vec<FleetMint>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FleetMint {
+    name: String,
+    work_dir: PathBuf,
+    #[serde(default = "default_backend")]
+    backend: String,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    wipe: bool,
+    #[serde(default)]
+    fsync: bool,
+    post_sql: Option<PathBuf>,
+    wait_for_lock: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    chmod: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_owner")]
+    chown: Option<Owner>,
+    #[serde(skip)]
+    low_memory: bool,
+    #[serde(skip)]
+    tmp_dir: Option<PathBuf>,
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_backend() -> String {
+    "sqlite".to_string()
+}
+
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|mode| parse_mode(&mode))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_owner<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Owner>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|owner| parse_owner(&owner))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+impl FleetMint {
+    fn migrate_args(&self, attempt: u32) -> MigrateArgs {
+        MigrateArgs {
+            post_sql: self.post_sql.clone(),
+            force: self.force,
+            // A retry picks up the temporary database the failed attempt left behind
+            resume: attempt > 0 && !self.wipe,
+            wipe: self.wipe,
+            replace_section: None,
+            auth_only: false,
+            auth_redb: None,
+            auth_sqlite: None,
+            fsync: self.fsync,
+            salvage: false,
+            repair: false,
+            merge: false,
+            on_conflict: vec![],
+            smoke_test: false,
+            verify_after: false,
+            wait_for_lock: self.wait_for_lock,
+            low_memory: self.low_memory,
+            tmp_dir: self.tmp_dir.clone(),
+            chmod: self.chmod,
+            chown: self.chown,
+            status_addr: None,
+            shred_source: false,
+            output_name: None,
+            timestamped_output: false,
+            compact: false,
+            validate_proofs: false,
+            rate_limit: None,
+            skip_space_check: false,
+            progress_socket: None,
+            backup_encrypt: None,
+            source_archive: None,
+            compress_output: false,
+            strict: false,
+            deny: vec![],
+            allow: vec![],
+            output_format: OutputFormat::Sqlite,
+            audit_log: None,
+        }
+    }
+}
+
+pub async fn fleet(manifest_path: &Path) -> Result<()> {
+    let manifest = std::fs::read_to_string(manifest_path)
+        .map_err(|e| anyhow!("Could not read manifest {:?}: {}", manifest_path, e))?;
+    let mut manifest: Manifest = toml::from_str(&manifest)?;
+
+    if manifest.mints.is_empty() {
+        return Err(anyhow!("Manifest {:?} lists no mints", manifest_path));
+    }
+
+    for mint in &manifest.mints {
+        if mint.backend != "sqlite" {
+            return Err(anyhow!(
+                "Mint {} asks for backend {}, only sqlite is supported",
+                mint.name,
+                mint.backend
+            ));
+        }
+    }
+
+    // Mints are migrated one after the other on a constrained host
+    if manifest.low_memory {
+        manifest.concurrency = 1;
+        for mint in &mut manifest.mints {
+            mint.low_memory = true;
+        }
+    }
+
+    // SQLite's temporary files go to one place for the whole process, the
+    // mints each stage their databases under their own directory in it
+    if let Some(dir) = &manifest.tmp_dir {
+        crate::sqlite::set_temp_dir(dir)?;
+        for mint in &mut manifest.mints {
+            mint.tmp_dir = Some(dir.clone());
+        }
+    }
+
+    println!(
+        "Migrating {} mints, {} at a time",
+        manifest.mints.len(),
+        manifest.concurrency
+    );
+
+    let permits = Arc::new(Semaphore::new(manifest.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for (index, mint) in manifest.mints.into_iter().enumerate() {
+        let permits = permits.clone();
+        let retries = manifest.retries;
+
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await?;
+            let result = migrate_with_retries(&mint, retries).await;
+            Ok::<_, anyhow::Error>((index, mint, result))
+        });
+    }
+
+    let mut results = vec![];
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined??);
+    }
+    results.sort_by_key(|(index, _, _)| *index);
+
+    println!("\n=== Fleet Summary ===");
+    let mut failed = 0;
+    for (_, mint, (attempts, result)) in &results {
+        match result {
+            Ok(()) => say!(
+                "✅ {} ({:?}) after {} attempt(s)",
+                mint.name,
+                mint.work_dir,
+                attempts
+            ),
+            Err(err) => {
+                failed += 1;
+                say!(
+                    "❌ {} ({:?}) after {} attempt(s): {}",
+                    mint.name,
+                    mint.work_dir,
+                    attempts,
+                    err
+                );
+            }
+        }
+    }
+    println!(
+        "{} of {} mints migrated successfully",
+        results.len() - failed,
+        results.len()
+    );
+
+    if failed > 0 {
+        return Err(anyhow!("{} of {} migrations failed", failed, results.len()));
+    }
+
+    Ok(())
+}
+
+async fn migrate_with_retries(mint: &FleetMint, retries: u32) -> (u32, Result<()>) {
+    let mut attempt = 0;
+
+    loop {
+        say!("[{}] ⏳ Starting attempt {}", mint.name, attempt + 1);
+
+        let result = match tokio::spawn(crate::migrate(
+            mint.work_dir.clone(),
+            mint.migrate_args(attempt),
+        ))
+        .await
+        {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => Err(anyhow!(
+                "Migration panicked: {}",
+                panic_message(&*err.into_panic())
+            )),
+            Err(err) => Err(err.into()),
+        };
+
+        attempt += 1;
+
+        match result {
+            Ok(()) => {
+                say!("[{}] ✅ Migrated", mint.name);
+                return (attempt, Ok(()));
+            }
+            Err(err) if attempt > retries => {
+                say!("[{}] ❌ Failed: {}", mint.name, err);
+                return (attempt, Err(err));
+            }
+            Err(err) => {
+                say!("[{}] ⚠️  Attempt {} failed: {}", mint.name, attempt, err);
+                tokio::time::sleep(Duration::from_secs(attempt as u64 * 5)).await;
+            }
+        }
+    }
+}
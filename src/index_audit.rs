@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::output::say;
+use crate::sqlite;
+
+// Indexes created by the cdk-sqlite migrations. Some of them are lost when
+// later migrations recreate the proof and blind_signature tables, so a fresh
+// database does not necessarily have all of them.
+const EXPECTED_INDEXES: &[(&str, &str)] = &[
+    ("unit_index", "keyset(unit)"),
+    ("active_index", "keyset(active)"),
+    ("request_index", "mint_quote(request)"),
+    ("expiry_index", "mint_quote(expiry)"),
+    ("mint_quote_state_index", "mint_quote(state)"),
+    ("melt_quote_state_index", "melt_quote(state)"),
+    ("proof_keyset_id_index", "proof(keyset_id)"),
+    ("state_index", "proof(state)"),
+    ("secret_index", "proof(secret)"),
+    (
+        "blind_signature_keyset_id_index",
+        "blind_signature(keyset_id)",
+    ),
+];
+
+// Queries the mint runs on every swap, mint and melt
+const HOT_QUERIES: &[&str] = &[
+    "SELECT y, state FROM proof WHERE y IN (?)",
+    "SELECT * FROM proof WHERE keyset_id = ?",
+    "SELECT * FROM proof WHERE quote_id = ?",
+    "SELECT * FROM blind_signature WHERE y IN (?)",
+    "SELECT * FROM blind_signature WHERE keyset_id = ?",
+    "SELECT * FROM blind_signature WHERE quote_id = ?",
+    "SELECT * FROM mint_quote WHERE id = ?",
+    "SELECT * FROM mint_quote WHERE request = ?",
+    "SELECT * FROM mint_quote WHERE request_lookup_id = ?",
+    "SELECT * FROM melt_quote WHERE id = ?",
+    "SELECT * FROM melt_quote WHERE request_lookup_id = ?",
+];
+
+pub async fn audit_indexes(sql_db_path: &Path) -> Result<()> {
+    say!("\n🔎 Auditing indexes of {:?}", sql_db_path);
+
+    let pool = sqlite::connect(sql_db_path).await?;
+
+    let existing: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'index'")
+            .fetch_all(&pool)
+            .await?;
+
+    for (name, columns) in EXPECTED_INDEXES {
+        if existing.iter().any(|(existing,)| existing == name) {
+            say!("✅ {} on {}", name, columns);
+            continue;
+        }
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {}",
+            name, columns
+        ))
+        .execute(&pool)
+        .await?;
+        tracing::info!("Created missing index {} on {}", name, columns);
+        say!(
+            "🔧 {} on {} was missing and has been created",
+            name,
+            columns
+        );
+    }
+
+    say!("\nQuery plans of the mint's hottest queries:");
+    for query in HOT_QUERIES {
+        let plan: Vec<(i64, i64, i64, String)> =
+            sqlx::query_as(&format!("EXPLAIN QUERY PLAN {}", query))
+                .fetch_all(&pool)
+                .await?;
+
+        say!("{}", query);
+        for (_, _, _, detail) in plan {
+            let marker = if detail.starts_with("SCAN") {
+                "⚠️ "
+            } else {
+                "  "
+            };
+            say!("  {} {}", marker, detail);
+        }
+    }
+
+    pool.close().await;
+
+    Ok(())
+}
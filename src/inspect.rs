@@ -0,0 +1,720 @@
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use cdk_common::common::{PaymentProcessorKey, QuoteTTL};
+#[cfg(feature = "auth")]
+use cdk_common::database::MintAuthDatabase;
+use cdk_common::database::{
+    MintDatabase, MintKeysDatabase, MintProofsDatabase, MintQuotesDatabase, MintSignaturesDatabase,
+};
+use cdk_common::mint::{MeltQuote, MintKeySetInfo, MintQuote};
+#[cfg(feature = "auth")]
+use cdk_common::nuts::{AuthProof, AuthRequired, ProtectedEndpoint};
+use cdk_common::nuts::{Id, MeltRequest, MintInfo, State};
+use cdk_common::util::hex;
+use cdk_common::{BlindSignature, Proof, PublicKey};
+use cdk_sqlite::MintSqliteDatabase;
+#[cfg(feature = "auth")]
+use cdk_sqlite::mint::MintSqliteAuthDatabase;
+use redb::{MultimapTableHandle, ReadTransaction, ReadableTableMetadata, TableHandle, Value};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::cli::{Backend, InspectCommand};
+use crate::output::say;
+use crate::raw::{RedbReader, lookup, lookup_multimap, read_multimap_table, read_table};
+use crate::{shell, sqlite, table_names};
+
+pub async fn inspect(work_dir: PathBuf, command: InspectCommand) -> Result<()> {
+    match command {
+        InspectCommand::DumpRaw { auth, table } => {
+            dump_raw(&redb_path(&work_dir, auth), table.as_deref())
+        }
+        InspectCommand::Get {
+            table,
+            key,
+            backend,
+            auth,
+        } => {
+            let key = hex::decode(&key).map_err(|_| anyhow!("Key must be hex encoded"))?;
+
+            match backend {
+                Backend::Redb => get_redb_record(&redb_path(&work_dir, auth), &table, &key, auth),
+                Backend::Sqlite => {
+                    get_sqlite_record(&sqlite_path(&work_dir, auth), &table, &key, auth).await
+                }
+            }
+        }
+        InspectCommand::FindProof { y } => find_proof(&work_dir, &y).await,
+        InspectCommand::Quote { quote_id } => inspect_quote(&work_dir, &quote_id).await,
+        InspectCommand::Signature { blinded_message } => {
+            find_signature(&work_dir, &blinded_message).await
+        }
+        InspectCommand::Shell => shell::run(&work_dir).await,
+    }
+}
+
+pub fn redb_path(work_dir: &Path, auth: bool) -> PathBuf {
+    if auth {
+        work_dir.join("cdk-mintd-auth.redb")
+    } else {
+        work_dir.join("cdk-mintd.redb")
+    }
+}
+
+pub fn sqlite_path(work_dir: &Path, auth: bool) -> PathBuf {
+    if auth {
+        work_dir.join("cdk-mintd-auth.sqlite")
+    } else {
+        work_dir.join("cdk-mintd.sqlite")
+    }
+}
+
+/// Key and value types of the tables written by cdk-redb
+#[derive(Debug, Clone, Copy)]
+enum RawLayout {
+    StrStr,
+    Bytes16Str,
+    Bytes33Str,
+    Bytes33U64,
+    Bytes16StrPair,
+    Bytes16Bytes33Multimap,
+}
+
+// Tables of both the mint and the auth database, including the legacy tables
+// older cdk-redb versions left behind
+const KNOWN_TABLES: &[(&str, RawLayout)] = &[
+    ("config", RawLayout::StrStr),
+    ("active_keysets", RawLayout::StrStr),
+    ("active_keyset", RawLayout::StrStr),
+    ("keysets", RawLayout::StrStr),
+    ("endpoints", RawLayout::StrStr),
+    ("mint_quotes", RawLayout::Bytes16Str),
+    ("melt_quotes", RawLayout::Bytes16Str),
+    ("proofs", RawLayout::Bytes33Str),
+    ("proofs_state", RawLayout::Bytes33Str),
+    ("pending_proofs", RawLayout::Bytes33Str),
+    ("spent_proofs", RawLayout::Bytes33Str),
+    ("blinded_signatures", RawLayout::Bytes33Str),
+    ("proof_created_time", RawLayout::Bytes33U64),
+    ("blind_signature_created_time", RawLayout::Bytes33U64),
+    ("melt_requests", RawLayout::Bytes16StrPair),
+    ("quote_proofs", RawLayout::Bytes16Bytes33Multimap),
+    ("quote_signatures", RawLayout::Bytes16Bytes33Multimap),
+];
+
+impl RawLayout {
+    fn render_value(&self, bytes: &[u8]) -> String {
+        match self {
+            RawLayout::StrStr | RawLayout::Bytes16Str | RawLayout::Bytes33Str => {
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+            RawLayout::Bytes33U64 => <u64 as Value>::from_bytes(bytes).to_string(),
+            RawLayout::Bytes16StrPair => {
+                let (first, second) = <(&str, &str) as Value>::from_bytes(bytes);
+                format!("{} | {}", first, second)
+            }
+            RawLayout::Bytes16Bytes33Multimap => hex::encode(bytes),
+        }
+    }
+}
+
+fn known_layout(name: &str) -> Option<RawLayout> {
+    KNOWN_TABLES
+        .iter()
+        .find(|(table, _)| *table == table_names::canonical(name))
+        .map(|(_, layout)| *layout)
+}
+
+pub fn is_known_table(name: &str) -> bool {
+    KNOWN_TABLES.iter().any(|(table, _)| *table == name)
+}
+
+fn dump_raw(redb_path: &Path, only_table: Option<&str>) -> Result<()> {
+    println!("Dumping raw tables of {:?}", redb_path);
+
+    let db = RedbReader::open(redb_path)?;
+    let read_txn = db.begin_read()?;
+
+    let wanted = |name: &str| only_table.is_none_or(|only| only == name);
+    let tables: Vec<_> = read_txn
+        .list_tables()?
+        .filter(|t| wanted(t.name()))
+        .collect();
+    let multimap_tables: Vec<_> = read_txn
+        .list_multimap_tables()?
+        .filter(|t| wanted(t.name()))
+        .collect();
+
+    if let Some(only) = only_table
+        && tables.is_empty()
+        && multimap_tables.is_empty()
+    {
+        return Err(anyhow!("Table {} does not exist in {:?}", only, redb_path));
+    }
+
+    for table in tables {
+        let name = table.name().to_string();
+        match known_layout(&name) {
+            Some(layout) => dump_table(&read_txn, &name, layout)?,
+            None => {
+                let len = read_txn.open_untyped_table(table)?.len()?;
+                print_unknown_table(&name, len);
+            }
+        }
+    }
+
+    for table in multimap_tables {
+        let name = table.name().to_string();
+        match known_layout(&name) {
+            Some(layout) => dump_table(&read_txn, &name, layout)?,
+            None => {
+                let len = read_txn.open_untyped_multimap_table(table)?.len()?;
+                print_unknown_table(&name, len);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_unknown_table(name: &str, len: u64) {
+    println!("\n=== {} ({} entries) ===", name, len);
+    println!("Unknown table layout, values not shown");
+}
+
+fn dump_table(read_txn: &ReadTransaction, name: &str, layout: RawLayout) -> Result<()> {
+    let entries = match layout {
+        RawLayout::StrStr => read_table::<&str, &str>(read_txn, name)?,
+        RawLayout::Bytes16Str => read_table::<[u8; 16], &str>(read_txn, name)?,
+        RawLayout::Bytes33Str => read_table::<[u8; 33], &str>(read_txn, name)?,
+        RawLayout::Bytes33U64 => read_table::<[u8; 33], u64>(read_txn, name)?,
+        RawLayout::Bytes16StrPair => read_table::<[u8; 16], (&str, &str)>(read_txn, name)?,
+        RawLayout::Bytes16Bytes33Multimap => {
+            read_multimap_table::<[u8; 16], [u8; 33]>(read_txn, name)?
+        }
+    };
+
+    println!("\n=== {} ({} entries) ===", name, entries.len());
+    for (key, value) in entries {
+        println!("{} => {}", hex::encode(key), layout.render_value(&value));
+    }
+
+    Ok(())
+}
+
+fn get_redb_record(redb_path: &Path, table: &str, key: &[u8], auth: bool) -> Result<()> {
+    let layout = known_layout(table).ok_or(anyhow!("Unknown redb table {}", table))?;
+
+    let db = RedbReader::open(redb_path)?;
+    let read_txn = db.begin_read()?;
+
+    let values = match layout {
+        RawLayout::StrStr => lookup::<&str, &str>(&read_txn, table, key)?,
+        RawLayout::Bytes16Str => lookup::<[u8; 16], &str>(&read_txn, table, key)?,
+        RawLayout::Bytes33Str => lookup::<[u8; 33], &str>(&read_txn, table, key)?,
+        RawLayout::Bytes33U64 => lookup::<[u8; 33], u64>(&read_txn, table, key)?,
+        RawLayout::Bytes16StrPair => lookup::<[u8; 16], (&str, &str)>(&read_txn, table, key)?,
+        RawLayout::Bytes16Bytes33Multimap => {
+            lookup_multimap::<[u8; 16], [u8; 33]>(&read_txn, table, key)?
+        }
+    };
+
+    if values.is_empty() {
+        println!("No record with key {} in {}", hex::encode(key), table);
+        return Ok(());
+    }
+
+    for value in values {
+        println!("Raw:\n{}", layout.render_value(&value));
+
+        let parsed = parse_redb_value(table, key, &value, auth)
+            .unwrap_or_else(|err| format!("Could not parse record: {}", err));
+        println!("\nParsed:\n{}", parsed);
+    }
+
+    Ok(())
+}
+
+fn parse_redb_value(table: &str, key: &[u8], value: &[u8], auth: bool) -> Result<String> {
+    let text = || std::str::from_utf8(value);
+
+    let parsed = match table {
+        #[cfg(not(feature = "auth"))]
+        "proofs" | "endpoints" if auth => return Err(anyhow!("This build has no auth support")),
+        #[cfg(feature = "auth")]
+        "proofs" if auth => format!("{:#?}", serde_json::from_str::<AuthProof>(text()?)?),
+        "proofs" | "pending_proofs" | "spent_proofs" => {
+            format!("{:#?}", serde_json::from_str::<Proof>(text()?)?)
+        }
+        "proofs_state" => format!("{:#?}", serde_json::from_str::<State>(text()?)?),
+        "blinded_signatures" => {
+            format!("{:#?}", serde_json::from_str::<BlindSignature>(text()?)?)
+        }
+        "mint_quotes" => format!("{:#?}", serde_json::from_str::<MintQuote>(text()?)?),
+        "melt_quotes" => format!("{:#?}", serde_json::from_str::<MeltQuote>(text()?)?),
+        "keysets" => format!("{:#?}", serde_json::from_str::<MintKeySetInfo>(text()?)?),
+        "melt_requests" => {
+            let (melt_request, payment_key) = <(&str, &str) as Value>::from_bytes(value);
+            format!(
+                "{:#?}",
+                (
+                    serde_json::from_str::<MeltRequest<Uuid>>(melt_request)?,
+                    serde_json::from_str::<PaymentProcessorKey>(payment_key)?
+                )
+            )
+        }
+        "config" => match key {
+            b"mint_info" => format!("{:#?}", serde_json::from_str::<MintInfo>(text()?)?),
+            b"quote_ttl" => format!("{:#?}", serde_json::from_str::<QuoteTTL>(text()?)?),
+            _ => text()?.to_string(),
+        },
+        #[cfg(feature = "auth")]
+        "endpoints" => format!(
+            "{:#?}",
+            (
+                serde_json::from_slice::<ProtectedEndpoint>(key)?,
+                serde_json::from_str::<AuthRequired>(text()?)?
+            )
+        ),
+        "quote_proofs" | "quote_signatures" => format!(
+            "quote {} => {}",
+            Uuid::from_slice(key)?,
+            PublicKey::from_slice(value)?
+        ),
+        "proof_created_time" | "blind_signature_created_time" => {
+            format!("created at unix time {}", <u64 as Value>::from_bytes(value))
+        }
+        _ => return Err(anyhow!("No cdk type is known for table {}", table)),
+    };
+
+    Ok(parsed)
+}
+
+enum SqliteKey {
+    Blob(Vec<u8>),
+    Text(String),
+}
+
+// Maps a redb table and key onto the SQLite table, key column and key encoding
+// cdk-sqlite uses for the same record
+fn sqlite_location(
+    table: &str,
+    key: &[u8],
+    auth: bool,
+) -> Result<(&'static str, &'static str, SqliteKey)> {
+    let text = || -> Result<SqliteKey> { Ok(SqliteKey::Text(String::from_utf8(key.to_vec())?)) };
+
+    let location = match (table, auth) {
+        ("proofs" | "proofs_state", _) => ("proof", "y", SqliteKey::Blob(key.to_vec())),
+        ("blinded_signatures", _) => ("blind_signature", "y", SqliteKey::Blob(key.to_vec())),
+        ("keysets", _) => ("keyset", "id", text()?),
+        ("endpoints", true) => ("protected_endpoints", "endpoint", text()?),
+        ("config", false) => ("config", "id", text()?),
+        ("mint_quotes", false) => (
+            "mint_quote",
+            "id",
+            SqliteKey::Text(Uuid::from_slice(key)?.to_string()),
+        ),
+        ("melt_quotes", false) => (
+            "melt_quote",
+            "id",
+            SqliteKey::Text(Uuid::from_slice(key)?.to_string()),
+        ),
+        ("melt_requests", false) => ("melt_request", "id", SqliteKey::Blob(key.to_vec())),
+        _ => return Err(anyhow!("Table {} has no SQLite equivalent", table)),
+    };
+
+    Ok(location)
+}
+
+async fn get_sqlite_record(sqlite_path: &Path, table: &str, key: &[u8], auth: bool) -> Result<()> {
+    let (sqlite_table, column, sqlite_key) = sqlite_location(table, key, auth)?;
+
+    let pool = sqlite::connect(sqlite_path).await?;
+    let sql = format!("SELECT * FROM {} WHERE {} = ?", sqlite_table, column);
+    let query = match sqlite_key {
+        SqliteKey::Blob(blob) => sqlx::query(&sql).bind(blob),
+        SqliteKey::Text(text) => sqlx::query(&sql).bind(text),
+    };
+    let row = query.fetch_optional(&pool).await?;
+    pool.close().await;
+
+    let Some(row) = row else {
+        println!(
+            "No record with key {} in {}",
+            hex::encode(key),
+            sqlite_table
+        );
+        return Ok(());
+    };
+
+    println!(
+        "Raw:\n{}",
+        serde_json::to_string_pretty(&sqlite::row_to_json(&row)?)?
+    );
+
+    let parsed = if auth {
+        parse_sqlite_auth_record(sqlite_path, table, key).await
+    } else {
+        parse_sqlite_record(sqlite_path, table, key).await
+    }
+    .unwrap_or_else(|err| format!("Could not parse record: {}", err));
+    println!("\nParsed:\n{}", parsed);
+
+    Ok(())
+}
+
+async fn parse_sqlite_record(sqlite_path: &Path, table: &str, key: &[u8]) -> Result<String> {
+    let db = MintSqliteDatabase::new(sqlite_path).await?;
+
+    let parsed = match table {
+        "proofs" => format!(
+            "{:#?}",
+            db.get_proofs_by_ys(&[PublicKey::from_slice(key)?]).await?
+        ),
+        "proofs_state" => format!(
+            "{:#?}",
+            db.get_proofs_states(&[PublicKey::from_slice(key)?]).await?
+        ),
+        "blinded_signatures" => format!(
+            "{:#?}",
+            db.get_blind_signatures(&[PublicKey::from_slice(key)?])
+                .await?
+        ),
+        "keysets" => format!(
+            "{:#?}",
+            db.get_keyset_info(&Id::from_str(std::str::from_utf8(key)?)?)
+                .await?
+        ),
+        "mint_quotes" => format!("{:#?}", db.get_mint_quote(&Uuid::from_slice(key)?).await?),
+        "melt_quotes" => format!("{:#?}", db.get_melt_quote(&Uuid::from_slice(key)?).await?),
+        "melt_requests" => format!("{:#?}", db.get_melt_request(&Uuid::from_slice(key)?).await?),
+        "config" => match key {
+            b"mint_info" => format!("{:#?}", db.get_mint_info().await?),
+            b"quote_ttl" => format!("{:#?}", db.get_quote_ttl().await?),
+            _ => return Err(anyhow!("No cdk type is known for this config entry")),
+        },
+        _ => return Err(anyhow!("No cdk type is known for table {}", table)),
+    };
+
+    Ok(parsed)
+}
+
+#[cfg(not(feature = "auth"))]
+async fn parse_sqlite_auth_record(_: &Path, _: &str, _: &[u8]) -> Result<String> {
+    Err(anyhow!("This build has no auth support"))
+}
+
+#[cfg(feature = "auth")]
+async fn parse_sqlite_auth_record(sqlite_path: &Path, table: &str, key: &[u8]) -> Result<String> {
+    let db = MintSqliteAuthDatabase::new(sqlite_path).await?;
+
+    let parsed = match table {
+        "proofs_state" => format!(
+            "{:#?}",
+            db.get_proofs_states(&[PublicKey::from_slice(key)?]).await?
+        ),
+        "blinded_signatures" => format!(
+            "{:#?}",
+            db.get_blind_signatures(&[PublicKey::from_slice(key)?])
+                .await?
+        ),
+        "keysets" => format!(
+            "{:#?}",
+            db.get_keyset_info(&Id::from_str(std::str::from_utf8(key)?)?)
+                .await?
+        ),
+        "endpoints" => format!(
+            "{:#?}",
+            db.get_auth_for_endpoint(serde_json::from_slice(key)?)
+                .await?
+        ),
+        _ => return Err(anyhow!("cdk-sqlite has no reader for auth table {}", table)),
+    };
+
+    Ok(parsed)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ProofLocation {
+    state: String,
+    keyset_id: String,
+    amount: u64,
+}
+
+async fn find_proof(work_dir: &Path, y: &str) -> Result<()> {
+    let y = PublicKey::from_hex(y).map_err(|_| anyhow!("{} is not a valid Y public key", y))?;
+
+    say!("🔍 Searching for proof {}", y);
+
+    let redb_location = find_redb_proof(&redb_path(work_dir, false), &y)?;
+    print_proof_location("redb", redb_location.as_ref());
+
+    let sqlite_path = sqlite_path(work_dir, false);
+    if !sqlite_path.exists() {
+        println!(
+            "SQLite: no database at {:?}, migration has not run yet",
+            sqlite_path
+        );
+        return Ok(());
+    }
+
+    let sqlite_location = find_sqlite_proof(&sqlite_path, &y).await?;
+    print_proof_location("SQLite", sqlite_location.as_ref());
+
+    if redb_location != sqlite_location {
+        say!("⚠️  The proof differs between redb and SQLite");
+    }
+
+    Ok(())
+}
+
+fn find_redb_proof(redb_path: &Path, y: &PublicKey) -> Result<Option<ProofLocation>> {
+    let db = RedbReader::open(redb_path)?;
+    let read_txn = db.begin_read()?;
+    let key = y.to_bytes();
+
+    let Some(proof) = lookup::<[u8; 33], &str>(&read_txn, "proofs", &key)?.pop() else {
+        return Ok(None);
+    };
+    let proof: Proof = serde_json::from_slice(&proof)?;
+
+    // cdk-redb only writes a state once a proof leaves the unspent state
+    let state = match lookup::<[u8; 33], &str>(&read_txn, "proofs_state", &key)?.pop() {
+        Some(state) => serde_json::from_slice::<State>(&state)?,
+        None => State::Unspent,
+    };
+
+    Ok(Some(ProofLocation {
+        state: state.to_string(),
+        keyset_id: proof.keyset_id.to_string(),
+        amount: proof.amount.into(),
+    }))
+}
+
+async fn find_sqlite_proof(sqlite_path: &Path, y: &PublicKey) -> Result<Option<ProofLocation>> {
+    let pool = sqlite::connect(sqlite_path).await?;
+    let row = sqlx::query("SELECT state, keyset_id, amount FROM proof WHERE y = ?")
+        .bind(y.to_bytes().to_vec())
+        .fetch_optional(&pool)
+        .await?;
+    pool.close().await;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(ProofLocation {
+        state: row.try_get("state")?,
+        keyset_id: row.try_get("keyset_id")?,
+        amount: row.try_get::<i64, _>("amount")? as u64,
+    }))
+}
+
+fn print_proof_location(backend: &str, location: Option<&ProofLocation>) {
+    match location {
+        Some(location) => println!(
+            "{}: found, state {}, keyset {}, amount {}",
+            backend, location.state, location.keyset_id, location.amount
+        ),
+        None => println!("{}: not found", backend),
+    }
+}
+
+async fn inspect_quote(work_dir: &Path, quote_id: &str) -> Result<()> {
+    let quote_id =
+        Uuid::from_str(quote_id).map_err(|_| anyhow!("{} is not a valid quote id", quote_id))?;
+
+    say!("🔍 Searching for quote {}", quote_id);
+
+    println!("\n=== redb ===");
+    print_redb_quote(&redb_path(work_dir, false), &quote_id)?;
+
+    println!("\n=== SQLite ===");
+    let sqlite_path = sqlite_path(work_dir, false);
+    if sqlite_path.exists() {
+        print_sqlite_quote(&sqlite_path, &quote_id).await?;
+    } else {
+        println!(
+            "No database at {:?}, migration has not run yet",
+            sqlite_path
+        );
+    }
+
+    Ok(())
+}
+
+fn print_redb_quote(redb_path: &Path, quote_id: &Uuid) -> Result<()> {
+    let db = RedbReader::open(redb_path)?;
+    let read_txn = db.begin_read()?;
+    let key = quote_id.as_bytes();
+
+    let mint_quote = lookup::<[u8; 16], &str>(&read_txn, "mint_quotes", key)?
+        .pop()
+        .map(|quote| serde_json::from_slice::<MintQuote>(&quote))
+        .transpose()?;
+    let melt_quote = lookup::<[u8; 16], &str>(&read_txn, "melt_quotes", key)?
+        .pop()
+        .map(|quote| serde_json::from_slice::<MeltQuote>(&quote))
+        .transpose()?;
+    let melt_request =
+        match lookup::<[u8; 16], (&str, &str)>(&read_txn, "melt_requests", key)?.pop() {
+            Some(value) => {
+                let (melt_request, payment_key) = <(&str, &str) as Value>::from_bytes(&value);
+                Some((
+                    serde_json::from_str::<MeltRequest<Uuid>>(melt_request)?,
+                    serde_json::from_str::<PaymentProcessorKey>(payment_key)?,
+                ))
+            }
+            None => None,
+        };
+
+    print_quote(mint_quote, melt_quote, melt_request);
+
+    Ok(())
+}
+
+async fn print_sqlite_quote(sqlite_path: &Path, quote_id: &Uuid) -> Result<()> {
+    let db = MintSqliteDatabase::new(sqlite_path).await?;
+
+    let mint_quote = db.get_mint_quote(quote_id).await?;
+    let melt_quote = db.get_melt_quote(quote_id).await?;
+    let melt_request = db.get_melt_request(quote_id).await?;
+
+    print_quote(mint_quote, melt_quote, melt_request);
+
+    Ok(())
+}
+
+fn print_quote(
+    mint_quote: Option<MintQuote>,
+    melt_quote: Option<MeltQuote>,
+    melt_request: Option<(MeltRequest<Uuid>, PaymentProcessorKey)>,
+) {
+    if mint_quote.is_none() && melt_quote.is_none() {
+        println!("Quote not found");
+    }
+
+    if let Some(mint_quote) = mint_quote {
+        println!("Mint quote:\n{:#?}", mint_quote);
+    }
+
+    if let Some(melt_quote) = melt_quote {
+        println!("Melt quote:\n{:#?}", melt_quote);
+
+        match melt_request {
+            Some((melt_request, payment_key)) => {
+                println!("Melt request:\n{:#?}", melt_request);
+                println!("Payment processor:\n{:#?}", payment_key);
+            }
+            None => println!("No melt request stored"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SignatureLocation {
+    keyset_id: String,
+    amount: u64,
+    c: String,
+    has_dleq: bool,
+}
+
+async fn find_signature(work_dir: &Path, blinded_message: &str) -> Result<()> {
+    let blinded_message = PublicKey::from_hex(blinded_message)
+        .map_err(|_| anyhow!("{} is not a valid blinded message", blinded_message))?;
+
+    say!("🔍 Searching for signature on {}", blinded_message);
+
+    let redb_location = find_redb_signature(&redb_path(work_dir, false), &blinded_message)?;
+    print_signature_location("redb", redb_location.as_ref());
+
+    let sqlite_path = sqlite_path(work_dir, false);
+    if !sqlite_path.exists() {
+        println!(
+            "SQLite: no database at {:?}, migration has not run yet",
+            sqlite_path
+        );
+        return Ok(());
+    }
+
+    let sqlite_location = find_sqlite_signature(&sqlite_path, &blinded_message).await?;
+    print_signature_location("SQLite", sqlite_location.as_ref());
+
+    if redb_location != sqlite_location {
+        say!("⚠️  The signature differs between redb and SQLite");
+    }
+
+    Ok(())
+}
+
+fn find_redb_signature(
+    redb_path: &Path,
+    blinded_message: &PublicKey,
+) -> Result<Option<SignatureLocation>> {
+    let db = RedbReader::open(redb_path)?;
+    let read_txn = db.begin_read()?;
+
+    let Some(signature) =
+        lookup::<[u8; 33], &str>(&read_txn, "blinded_signatures", &blinded_message.to_bytes())?
+            .pop()
+    else {
+        return Ok(None);
+    };
+    let signature: BlindSignature = serde_json::from_slice(&signature)?;
+
+    Ok(Some(SignatureLocation {
+        keyset_id: signature.keyset_id.to_string(),
+        amount: signature.amount.into(),
+        c: signature.c.to_hex(),
+        has_dleq: signature.dleq.is_some(),
+    }))
+}
+
+async fn find_sqlite_signature(
+    sqlite_path: &Path,
+    blinded_message: &PublicKey,
+) -> Result<Option<SignatureLocation>> {
+    let pool = sqlite::connect(sqlite_path).await?;
+    let row =
+        sqlx::query("SELECT keyset_id, amount, c, dleq_e, dleq_s FROM blind_signature WHERE y = ?")
+            .bind(blinded_message.to_bytes().to_vec())
+            .fetch_optional(&pool)
+            .await?;
+    pool.close().await;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let dleq_e: Option<String> = row.try_get("dleq_e")?;
+    let dleq_s: Option<String> = row.try_get("dleq_s")?;
+
+    Ok(Some(SignatureLocation {
+        keyset_id: row.try_get("keyset_id")?,
+        amount: row.try_get::<i64, _>("amount")? as u64,
+        c: hex::encode(row.try_get::<Vec<u8>, _>("c")?),
+        has_dleq: dleq_e.is_some() && dleq_s.is_some(),
+    }))
+}
+
+fn print_signature_location(backend: &str, location: Option<&SignatureLocation>) {
+    match location {
+        Some(location) => println!(
+            "{}: found, keyset {}, amount {}, C {}, DLEQ {}",
+            backend,
+            location.keyset_id,
+            location.amount,
+            location.c,
+            if location.has_dleq {
+                "present"
+            } else {
+                "missing"
+            }
+        ),
+        None => println!("{}: not found", backend),
+    }
+}
@@ -0,0 +1,1567 @@
+use std::io::{IsTerminal, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use cdk_common::database::{
+    MintDatabase, MintKeysDatabase, MintProofsDatabase, MintQuotesDatabase, MintSignaturesDatabase,
+};
+use cdk_common::nuts::Id;
+use cdk_common::{Amount, State};
+use cdk_redb::MintRedbDatabase;
+use cdk_sqlite::MintSqliteDatabase;
+use clap::{CommandFactory, FromArgMatches};
+use futures::FutureExt;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::batch::{migrate_batch, panic_message, read_work_dir_list};
+use crate::checkpoint::Checkpoints;
+use crate::cli::{CLIArgs, Commands, MigrateArgs, OutputFormat};
+use crate::clone::clone;
+use crate::column_audit::audit_columns;
+use crate::export::{export, import};
+use crate::fleet::fleet;
+use crate::index_audit::audit_indexes;
+use crate::inspect::inspect;
+use crate::marker::MigratedMarker;
+use crate::output::say;
+use crate::paths::MigrationPaths;
+use crate::progress::{
+    NoProgress, Observers, Progress, ProgressRecorder, ProgressState, RaisedWarnings,
+};
+use crate::replace::replace_section;
+use crate::row_counts::SourceCounts;
+use crate::serve::serve;
+use crate::split::split;
+use crate::stats::stats;
+use crate::throttle::{Throttle, json_len};
+use crate::timestamp_audit::audit_timestamps;
+use crate::verify_blind_signatures::verify_blind_signatures;
+use crate::verify_migration::verify_migration;
+
+pub use crate::progress::{Phase, ProgressObserver, WarningCode};
+mod audit_log;
+#[cfg(feature = "auth")]
+mod auth;
+mod backup;
+mod batch;
+mod checkpoint;
+mod cleanup;
+mod cli;
+mod clone;
+mod column_audit;
+mod compact;
+mod compare;
+mod digest;
+mod disk_space;
+mod doctor;
+mod error_report;
+mod export;
+mod fleet;
+mod index_audit;
+mod inspect;
+mod lock;
+mod low_memory;
+mod marker;
+mod melt_quotes;
+mod merge;
+mod meta;
+mod metrics;
+mod mint_api;
+mod mint_info;
+mod nice;
+#[cfg(feature = "otel")]
+mod otel;
+mod output;
+mod output_sink;
+mod paths;
+mod payment_methods;
+mod permissions;
+mod pipeline;
+mod plan;
+mod progress;
+#[cfg(unix)]
+mod progress_socket;
+mod raw;
+mod redb_format;
+mod repair;
+mod replace;
+mod rollback;
+mod row_counts;
+mod salvage;
+mod self_test;
+mod serve;
+mod shell;
+mod smoke_test;
+mod source_archive;
+mod spending_conditions;
+mod split;
+mod sqlite;
+mod stats;
+mod stats_snapshot;
+mod status_page;
+mod table_hashes;
+mod table_names;
+mod throttle;
+mod timestamp_audit;
+mod validate_proofs;
+mod verify;
+mod verify_blind_signatures;
+mod verify_migration;
+#[cfg(feature = "wallet")]
+mod wallet;
+
+/// Runs the command line tool, the binary is only this
+#[doc(hidden)]
+pub async fn run_cli() -> Result<()> {
+    let default_filter = "debug";
+
+    let sqlx_filter = "sqlx=warn";
+    let hyper_filter = "hyper=warn";
+    let h2_filter = "h2=warn";
+    let tower_http = "tower_http=warn";
+    let tonic_filter = "tonic=warn";
+    let otel_filter = "opentelemetry_sdk=warn";
+
+    let env_filter = EnvFilter::new(format!(
+        "{default_filter},{sqlx_filter},{hyper_filter},{h2_filter},{tower_http},{tonic_filter},{otel_filter}"
+    ));
+
+    let matches = CLIArgs::command().get_matches();
+    let args = CLIArgs::from_arg_matches(&matches)?;
+
+    // Done first so the logs and everything else printed go to stderr
+    let digest = match args.json {
+        true => Some(digest::start(
+            matches.subcommand_name().unwrap_or("migrate"),
+        )?),
+        false => None,
+    };
+    let metrics = match &args.metrics_file {
+        Some(path) => Some(metrics::start(
+            path,
+            matches.subcommand_name().unwrap_or("migrate"),
+        )?),
+        None => None,
+    };
+
+    // Output captured by cron or CI is kept plain unless it goes to a terminal
+    let terminal = std::io::stdout().is_terminal();
+    let plain = args.plain || !terminal;
+    let color = !plain && !args.no_color && std::env::var_os("NO_COLOR").is_none();
+    output::set_plain(plain);
+
+    let (error_report, recorder) = match &args.error_report {
+        Some(destination) => {
+            let (report, recorder) =
+                error_report::install(error_report::Destination::parse(destination)?);
+            (Some(report), Some(recorder))
+        }
+        None => (None, None),
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(env_filter)
+        .with(tracing_subscriber::fmt::layer().with_ansi(color))
+        .with(recorder);
+
+    // Kept until main returns, so the last spans are sent before exiting
+    #[cfg(feature = "otel")]
+    let (_otlp_export, subscriber) = match &args.otlp_endpoint {
+        Some(endpoint) => {
+            let (export, layer) = otel::layer(endpoint)?;
+            (Some(export), subscriber.with(Some(layer)))
+        }
+        None => (None, subscriber.with(None)),
+    };
+
+    subscriber.init();
+
+    let outcome = AssertUnwindSafe(run(args)).catch_unwind().await;
+    if let Some(metrics) = metrics {
+        metrics.finish(matches!(outcome, Ok(Ok(()))));
+    }
+    if let Some(digest) = digest {
+        digest.finish(match &outcome {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(format!("{:#}", err)),
+            Err(panic) => Err(format!("panicked: {}", panic_message(&**panic))),
+        });
+    }
+    if let Some(report) = &error_report {
+        match &outcome {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => report.send(Some(err)).await,
+            // The panic hook already recorded it
+            Err(_) => report.send(None).await,
+        }
+    }
+
+    match outcome {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+async fn run(args: CLIArgs) -> Result<()> {
+    // Completions and man pages go to stdout as is, before anything about the
+    // work dir is printed
+    match &args.command {
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(
+                *shell,
+                &mut CLIArgs::command(),
+                env!("CARGO_PKG_NAME"),
+                &mut std::io::stdout(),
+            );
+            return Ok(());
+        }
+        Some(Commands::Mangen { out_dir }) => return mangen(out_dir.as_deref()),
+        #[cfg(feature = "wallet")]
+        Some(Commands::WalletToRedb { input, output }) => {
+            return wallet::wallet_to_redb(input, output).await;
+        }
+        Some(Commands::SelfTest) => return self_test::self_test().await,
+        _ => (),
+    }
+
+    if args.nice {
+        nice::lower_priority();
+    }
+
+    if let Some(path) = &args.table_names {
+        table_names::load(path)?;
+    }
+
+    if let Some(dir) = &args.migrate.tmp_dir {
+        sqlite::set_temp_dir(dir)?;
+    }
+
+    let mut work_dirs = args.work_dir;
+    if let Some(list) = &args.work_dir_list {
+        work_dirs.extend(read_work_dir_list(list)?);
+    }
+
+    if work_dirs.len() > 1 {
+        if args.command.is_some() {
+            return Err(anyhow!("Only migration accepts more than one work dir"));
+        }
+
+        return migrate_batch(work_dirs, args.migrate).await;
+    }
+
+    let work_dir = if let Some(work_dir) = work_dirs.pop() {
+        println!("Using work dir from cmd arg: {:?}", work_dir);
+        work_dir
+    } else {
+        work_dir()?
+    };
+
+    match args.command {
+        Some(Commands::Inspect(command)) => inspect(work_dir, command).await,
+        Some(Commands::Export {
+            format,
+            only,
+            auth,
+            output,
+            signing_key,
+        }) => export(work_dir, format, only, auth, &output, signing_key).await,
+        Some(Commands::Import {
+            only,
+            input,
+            pubkey,
+        }) => import(work_dir, only, &input, pubkey).await,
+        Some(Commands::Split { keyset, output }) => split(work_dir, &keyset, &output).await,
+        Some(Commands::Clone {
+            source,
+            output,
+            filters,
+        }) => clone(work_dir, source, &output, filters).await,
+        Some(Commands::Fleet { manifest }) => fleet(&manifest).await,
+        Some(Commands::Serve {
+            addr,
+            insecure,
+            root,
+        }) => serve(addr, &root, insecure).await,
+        Some(Commands::Completions { .. } | Commands::Mangen { .. } | Commands::SelfTest) => {
+            unreachable!("completions, man pages and the self-test are handled before")
+        }
+        #[cfg(feature = "wallet")]
+        Some(Commands::WalletToRedb { .. }) => {
+            unreachable!("wallet conversion is handled before")
+        }
+        Some(Commands::Cleanup {
+            archive,
+            accept_changes,
+        }) => cleanup::cleanup(work_dir, archive, accept_changes).await,
+        Some(Commands::Plan) => plan::plan(work_dir, &args.migrate).await,
+        Some(Commands::Rollback { discard_changes }) => {
+            rollback::rollback(work_dir, discard_changes).await
+        }
+        Some(Commands::Stats {
+            period,
+            save,
+            compare,
+        }) => {
+            stats(
+                work_dir,
+                period.period(),
+                save.as_deref(),
+                compare.as_deref(),
+            )
+            .await
+        }
+        Some(Commands::Verify {
+            restart,
+            mint_url,
+            check_states,
+            against: None,
+        }) => verify::verify(work_dir, restart, mint_url.as_deref(), check_states).await,
+        Some(Commands::Verify {
+            against: Some(other),
+            ..
+        }) => verify::verify_against(work_dir, &other).await,
+        Some(Commands::Doctor {
+            backend,
+            validate_proofs,
+        }) => doctor::doctor(work_dir, backend, validate_proofs).await,
+        None => migrate(work_dir, args.migrate).await,
+    }
+}
+
+async fn migrate(work_dir: PathBuf, args: MigrateArgs) -> Result<()> {
+    let mut observers: Vec<Progress> = vec![];
+    if let Some(recorder) = digest::recorder() {
+        observers.push(recorder);
+    }
+    let metrics = metrics::recorder();
+    if let Some(recorder) = &metrics {
+        observers.push(recorder.clone());
+    }
+
+    let _status_page = match args.status_addr {
+        Some(addr) => {
+            let progress = Arc::new(Mutex::new(ProgressState::default()));
+            let status_page =
+                status_page::spawn_status_page(addr, &work_dir, progress.clone()).await?;
+            observers.push(Arc::new(ProgressRecorder(progress)));
+            Some(status_page)
+        }
+        None => None,
+    };
+
+    #[cfg(unix)]
+    let progress_socket = match &args.progress_socket {
+        Some(path) => {
+            let socket = progress_socket::spawn_progress_socket(path).await?;
+            observers.push(socket.feed());
+            Some(socket)
+        }
+        None => None,
+    };
+    #[cfg(not(unix))]
+    if args.progress_socket.is_some() {
+        return Err(anyhow!("--progress-socket needs Unix domain sockets"));
+    }
+
+    let progress: Progress = match observers.len() {
+        0 => Arc::new(NoProgress),
+        1 => observers.remove(0),
+        _ => Arc::new(Observers(observers)),
+    };
+    let migrated = migrate_with_progress(work_dir.clone(), args, progress).await;
+    #[cfg(unix)]
+    if let Some(socket) = progress_socket {
+        socket.finish(&migrated).await;
+    }
+    migrated?;
+
+    // A source unpacked from an archive is gone by now
+    let redb = MigrationPaths::new(&work_dir).redb;
+    if let Some(recorder) = metrics
+        && redb.exists()
+    {
+        recorder.proofs(&stats::redb_proof_states(&redb)?);
+    }
+
+    Ok(())
+}
+
+/// Migrates the databases of `work_dir`, reporting each phase to `progress`
+async fn migrate_with_progress(
+    work_dir: PathBuf,
+    args: MigrateArgs,
+    progress: Progress,
+) -> Result<()> {
+    let span = tracing::info_span!("migration", work_dir = %work_dir.display());
+    migrate_work_dir(work_dir, args, progress)
+        .instrument(span)
+        .await
+}
+
+/// What [`migrate_if_needed`] found in the work dir
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationOutcome {
+    /// There is no redb database to migrate
+    NoRedb,
+    /// The redb database was migrated before, to `target`
+    AlreadyMigrated { target: PathBuf },
+    /// A SQLite database the redb was never migrated to is in the way, both
+    /// are left as they are
+    SqliteExists { sqlite: PathBuf },
+    /// The redb database was migrated to `target` and verified
+    Migrated { target: PathBuf },
+}
+
+/// Migrates the redb databases of a mintd work dir to SQLite unless that
+/// happened already.
+///
+/// Meant for cdk-mintd to call on startup when it is configured for SQLite
+/// but finds a redb database, so an upgrade converts the data without the
+/// operator running this tool. The redb databases are kept, and a migration
+/// that fails is started over on the next call.
+///
+/// Nothing is printed, the progress is logged through `tracing`, and a
+/// verification mismatch is returned as an error rather than a panic.
+pub async fn migrate_if_needed(work_dir: impl AsRef<Path>) -> Result<MigrationOutcome> {
+    migrate_if_needed_with_progress(work_dir, Arc::new(NoProgress)).await
+}
+
+/// [`migrate_if_needed`], reporting the phases, record counts and warnings
+/// of a migration to `observer`, e.g. for a mintd admin dashboard
+pub async fn migrate_if_needed_with_progress(
+    work_dir: impl AsRef<Path>,
+    observer: Arc<dyn ProgressObserver>,
+) -> Result<MigrationOutcome> {
+    output::set_log_only();
+    let work_dir = work_dir.as_ref().to_path_buf();
+    let paths = MigrationPaths::new(&work_dir);
+
+    if !paths.redb.exists() {
+        return Ok(MigrationOutcome::NoRedb);
+    }
+    if let Some(marker) = marker::read_marker(&paths.redb)? {
+        // The redb may still be there, the data is not
+        if !marker.target.exists() {
+            return Err(anyhow!(
+                "{:?} was migrated to {:?}, which is gone",
+                paths.redb,
+                marker.target
+            ));
+        }
+        return Ok(MigrationOutcome::AlreadyMigrated {
+            target: marker.target,
+        });
+    }
+    if paths.sqlite.exists() {
+        return Ok(MigrationOutcome::SqliteExists {
+            sqlite: paths.sqlite,
+        });
+    }
+
+    tracing::info!(
+        "Migrating {:?} to SQLite before the mint starts",
+        paths.redb
+    );
+    let args = MigrateArgs {
+        post_sql: None,
+        force: false,
+        resume: false,
+        // Whatever a previous startup left behind may not even verify
+        wipe: true,
+        replace_section: None,
+        auth_only: false,
+        auth_redb: None,
+        auth_sqlite: None,
+        fsync: true,
+        salvage: false,
+        repair: false,
+        merge: false,
+        on_conflict: vec![],
+        smoke_test: true,
+        verify_after: false,
+        wait_for_lock: None,
+        low_memory: false,
+        tmp_dir: None,
+        chmod: None,
+        chown: None,
+        status_addr: None,
+        shred_source: false,
+        output_name: None,
+        timestamped_output: false,
+        compact: false,
+        validate_proofs: false,
+        rate_limit: None,
+        skip_space_check: false,
+        progress_socket: None,
+        backup_encrypt: None,
+        source_archive: None,
+        compress_output: false,
+        strict: false,
+        deny: vec![],
+        allow: vec![],
+        output_format: OutputFormat::Sqlite,
+        audit_log: None,
+    };
+    // The comparisons assert, which must not take mintd down with them
+    AssertUnwindSafe(migrate_with_progress(work_dir, args, observer))
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| Err(anyhow!("Migration panicked: {}", panic_message(&*panic))))?;
+
+    Ok(MigrationOutcome::Migrated {
+        target: paths.sqlite,
+    })
+}
+
+async fn migrate_work_dir(work_dir: PathBuf, args: MigrateArgs, progress: Progress) -> Result<()> {
+    if let Some(section) = args.replace_section {
+        return replace_section(work_dir, section).await;
+    }
+    if args.auth_only {
+        #[cfg(feature = "auth")]
+        return auth::migrate_auth_only(&work_dir, &args, progress.as_ref()).await;
+        #[cfg(not(feature = "auth"))]
+        return Err(anyhow!(
+            "This build has no auth support. Build with the auth feature to migrate the auth database."
+        ));
+    }
+
+    let warnings = Arc::new(RaisedWarnings::default());
+    let progress: Progress = Arc::new(Observers(vec![progress, warnings.clone()]));
+
+    // A named output is linked into place as cdk-mintd.sqlite once verified
+    let links = MigrationPaths::new(&work_dir);
+    let output_name = args.output_name();
+    let mut paths = match &output_name {
+        Some(name) => links.named(name),
+        None => links.clone(),
+    };
+
+    // Kept until the migration is done, dropping it removes the unpacked files
+    let _extracted = match &args.source_archive {
+        Some(archive) => {
+            let extracted =
+                source_archive::extract(archive, args.tmp_dir.as_deref().unwrap_or(&work_dir))?;
+            paths.redb = extracted.redb.clone();
+            paths.auth_redb = extracted.auth_redb.clone();
+            Some(extracted)
+        }
+        None => None,
+    };
+    let mut redb_path = paths.redb.clone();
+    let sql_db_path = paths.sqlite.clone();
+
+    // Targets are built under temporary names and only renamed into place once
+    // verified, so the canonical paths never hold a half-built database
+    let mut building = paths.building(args.tmp_dir.as_deref());
+
+    say!("Starting database migration...");
+    say!("Source ReDB: {:?}", redb_path);
+    say!("Target SQLite: {:?}", sql_db_path);
+    if args.tmp_dir.is_some() {
+        say!("Building in: {:?}", building.sqlite);
+    }
+
+    if !redb_path.exists() {
+        return Err(anyhow!("No redb database found at {:?}", redb_path));
+    }
+    redb_format::check_format(&redb_path)?;
+    #[cfg(not(feature = "auth"))]
+    if paths.auth_redb.exists() {
+        return Err(anyhow!(
+            "Found an auth database at {:?}, but this build has no auth support. Build with the auth feature to migrate it.",
+            paths.auth_redb
+        ));
+    }
+    if paths.auth_redb.exists() {
+        redb_format::check_format(&paths.auth_redb)?;
+    }
+
+    let deadline = args
+        .wait_for_lock
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    lock::wait_for_lock(&redb_path, deadline)?;
+    if paths.auth_redb.exists() {
+        lock::wait_for_lock(&paths.auth_redb, deadline)?;
+    }
+
+    if let Some(marker) = marker::read_marker(&redb_path)? {
+        if !args.force {
+            return Err(anyhow!(
+                "{:?} was already migrated to {:?} at unix time {}. Use --force to migrate it again.",
+                redb_path,
+                marker.target,
+                marker.migrated_at
+            ));
+        }
+
+        say!(
+            "⚠️  Source was already migrated to {:?}, continuing because of --force",
+            marker.target
+        );
+    }
+
+    // Read the script up front so a typo in the path fails before anything is written
+    let post_sql = args
+        .post_sql
+        .as_ref()
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Could not read post migration SQL {:?}: {}", path, e))
+        })
+        .transpose()?;
+    if let Some(log) = &args.audit_log {
+        audit_log::check_writable(log)?;
+    }
+
+    // Check if SQLite database already exists
+    let merging = args.merge && sql_db_path.exists();
+    if merging {
+        say!("Merging into existing SQLite database");
+        merge::prepare_target(&sql_db_path, false).await?;
+        #[cfg(feature = "auth")]
+        if paths.auth_sqlite.exists() {
+            merge::prepare_target(&paths.auth_sqlite, true).await?;
+        }
+    } else if sql_db_path.exists() && output_name.is_some() {
+        return Err(anyhow!(
+            "SQLite database already exists at {:?}. Will not overwrite existing database. Choose another output name.",
+            sql_db_path
+        ));
+    } else if sql_db_path.exists() {
+        return Err(anyhow!(
+            "SQLite database already exists at {:?}. Will not overwrite existing database. Use --merge to merge into it.",
+            sql_db_path
+        ));
+    }
+    if output_name.is_some() {
+        if cfg!(not(unix)) {
+            return Err(anyhow!(
+                "Named outputs are linked into place, which needs symlinks"
+            ));
+        }
+        for link in [&links.sqlite, &links.auth_sqlite] {
+            if link.exists() && !link.is_symlink() {
+                return Err(anyhow!(
+                    "{:?} is a database, not a link to one. Move it out of the way to migrate to a named output.",
+                    link
+                ));
+            }
+        }
+    }
+
+    let resuming = if building.sqlite.exists() {
+        let status = meta::read_verification(&building.sqlite).await?;
+        let action = partial_target_action(&building.sqlite, status.as_deref(), &args)?;
+
+        // The auth database is small and always rebuilt from scratch
+        sqlite::remove_database(&building.auth_sqlite)?;
+
+        match action {
+            PartialTargetAction::Resume => {
+                say!("Resuming into existing SQLite database");
+                true
+            }
+            PartialTargetAction::Wipe => {
+                sqlite::remove_database(&building.sqlite)?;
+                say!("Removed incomplete SQLite database");
+                false
+            }
+        }
+    } else {
+        sqlite::remove_database(&building.auth_sqlite)?;
+        false
+    };
+
+    if !args.skip_space_check {
+        disk_space::check(&paths, &building, args.repair)?;
+    }
+    if let Some(recipient) = &args.backup_encrypt {
+        backup::backup_sources(&paths, recipient)?;
+    }
+    if let Some(staging) = building.sqlite.parent() {
+        std::fs::create_dir_all(staging)?;
+    }
+
+    let started_at = meta::unix_time();
+    // Hash before cdk-redb opens the file, opening may upgrade it in place
+    let source_sha256 = meta::sha256_file(&redb_path)?;
+
+    // Kept until the migration is done, dropping it removes the copies
+    let _snapshot = if args.repair {
+        let snapshot = repair::repaired_snapshot(
+            &paths.redb,
+            paths
+                .auth_redb
+                .exists()
+                .then_some(paths.auth_redb.as_path()),
+            building.sqlite.parent().unwrap_or(&work_dir),
+        )?;
+        redb_path = snapshot.redb.clone();
+        building.redb = snapshot.redb.clone();
+        if let Some(auth_redb) = &snapshot.auth_redb {
+            building.auth_redb = auth_redb.clone();
+        }
+        Some(snapshot)
+    } else {
+        None
+    };
+
+    let total_proofs = progress::count_records(&redb_path, "proofs");
+    let source_counts = SourceCounts::read(
+        &redb_path,
+        &[
+            Phase::Quotes,
+            Phase::Keysets,
+            Phase::Proofs,
+            Phase::BlindSignatures,
+        ],
+    );
+    // Duplicates collapsed by an earlier run are not known, so the proofs of
+    // a resumed compacting run cannot be counted
+    let check_proofs = !(resuming && args.compact);
+
+    let sqlite_db = MintSqliteDatabase::new(&building.sqlite).await?;
+    // A merge is logged as it writes to the target, not while it builds
+    if args.audit_log.is_some() && !merging {
+        audit_log::start(&building.sqlite).await?;
+    }
+    let throttle = Throttle::new(args.rate_limit);
+    let mut compacted = 0;
+    let migrated = async {
+        {
+            let redb_db = salvage::reading_sync(|| Ok(MintRedbDatabase::new(&redb_path)?))?;
+
+            progress.phase_started(Phase::MintInfo);
+            migrate_mint_info(&redb_db, &sqlite_db)
+                .instrument(Phase::MintInfo.span())
+                .await?;
+            progress.phase_started(Phase::Quotes);
+            migrate_quotes(&redb_db, &sqlite_db, progress.as_ref(), &throttle)
+                .instrument(Phase::Quotes.span())
+                .await?;
+            source_counts
+                .check_phase(Phase::Quotes, &building.sqlite, 0)
+                .await?;
+
+            progress.phase_started(Phase::Keysets);
+            let keyset_ids = async {
+                let keysets = salvage::reading(redb_db.get_keyset_infos()).await?;
+                let total_keysets = keysets.len() as u64;
+                let mut keyset_ids = vec![];
+
+                for keyset in keysets {
+                    // cdk-sqlite stores the fee as a signed integer, a larger one
+                    // would come back as a different fee
+                    if i64::try_from(keyset.input_fee_ppk).is_err() {
+                        return Err(anyhow!(
+                            "Keyset {} has an input fee of {} ppk, more than SQLite can store",
+                            keyset.id,
+                            keyset.input_fee_ppk
+                        ));
+                    }
+                    tracing::info!(
+                        "Keyset {} charges an input fee of {} ppk",
+                        keyset.id,
+                        keyset.input_fee_ppk
+                    );
+                    keyset_ids.push(keyset.id);
+                    sqlite_db.add_keyset_info(keyset).await?;
+                    progress.records(Phase::Keysets, keyset_ids.len() as u64, Some(total_keysets));
+                }
+
+                Ok::<_, anyhow::Error>(keyset_ids)
+            }
+            .instrument(Phase::Keysets.span())
+            .await?;
+            source_counts
+                .check_phase(Phase::Keysets, &building.sqlite, 0)
+                .await?;
+
+            if !args.low_memory {
+                progress.phase_started(Phase::Proofs);
+                compacted = migrate_proofs(
+                    keyset_ids,
+                    &redb_db,
+                    &sqlite_db,
+                    resuming,
+                    progress.as_ref(),
+                    total_proofs,
+                    args.compact,
+                    &throttle,
+                )
+                .instrument(Phase::Proofs.span())
+                .await?;
+                if check_proofs {
+                    source_counts
+                        .check_phase(Phase::Proofs, &building.sqlite, compacted)
+                        .await?;
+                }
+            }
+        }
+
+        if args.low_memory {
+            progress.phase_started(Phase::Proofs);
+            low_memory::migrate_proofs(
+                &redb_path,
+                &sqlite_db,
+                resuming,
+                progress.as_ref(),
+                &throttle,
+            )
+            .instrument(Phase::Proofs.span())
+            .await?;
+            source_counts
+                .check_phase(Phase::Proofs, &building.sqlite, 0)
+                .await?;
+            progress.phase_started(Phase::BlindSignatures);
+            low_memory::migrate_blind_signatures(
+                &redb_path,
+                &sqlite_db,
+                resuming,
+                progress.as_ref(),
+                &throttle,
+            )
+            .instrument(Phase::BlindSignatures.span())
+            .await?;
+        } else {
+            progress.phase_started(Phase::BlindSignatures);
+            migrate_blind_signatures(
+                &redb_path,
+                &sqlite_db,
+                resuming,
+                progress.as_ref(),
+                &throttle,
+            )
+            .instrument(Phase::BlindSignatures.span())
+            .await?;
+        }
+        source_counts
+            .check_phase(Phase::BlindSignatures, &building.sqlite, 0)
+            .await
+    };
+
+    // Only a failure to read the redb, including the panics redb raises on
+    // some damaged pages, can be helped by a salvage migration. A failed
+    // write or a wrong row count is passed on as is, salvaging would only
+    // hide it.
+    let salvage_report = match migrated.await {
+        Ok(()) => None,
+        Err(err) if !salvage::is_read_error(&err) => return Err(err),
+        Err(err) if args.salvage => {
+            progress.warning(
+                WarningCode::Salvage,
+                &format!(
+                    "Reading the redb failed ({}), falling back to a salvage migration",
+                    err
+                ),
+            );
+            say!(
+                "⚠️  [{}] Reading the redb failed ({}), falling back to a salvage migration",
+                WarningCode::Salvage,
+                err
+            );
+            // Start over, the failed run may have left part of the data behind
+            sqlite::clear_database(&building.sqlite).await?;
+
+            Some(salvage::salvage(&redb_path, &sqlite_db).await?)
+        }
+        Err(err) => {
+            return Err(err.context(
+                "Reading the redb failed, --salvage migrates whatever can still be read",
+            ));
+        }
+    };
+
+    if let Some(script) = post_sql {
+        tracing::info!("Running post migration SQL script...");
+        sqlite::run_script(&building.sqlite, &script).await?;
+        say!("Post migration SQL script applied");
+    }
+
+    meta::write_meta(&building.sqlite, &paths.redb, &source_sha256, started_at).await?;
+    if args.compact && salvage_report.is_none() {
+        meta::set_value(&building.sqlite, "compacted.proofs", &compacted.to_string()).await?;
+    }
+
+    say!("Migration completed! Starting verification...");
+
+    // Auth database migration
+    #[cfg(feature = "auth")]
+    if building.auth_redb.exists() {
+        say!("Auth database detected, migrating...");
+        progress.phase_started(Phase::Auth);
+        let auth_counts = SourceCounts::read(&building.auth_redb, &[Phase::Auth]);
+
+        auth::migrate_auth(
+            &building.auth_redb,
+            &building.auth_sqlite,
+            args.audit_log.is_some() && !(merging && paths.auth_sqlite.exists()),
+            progress.as_ref(),
+        )
+        .instrument(Phase::Auth.span())
+        .await?;
+        auth_counts
+            .check_phase(Phase::Auth, &building.auth_sqlite, 0)
+            .await?;
+        audit_log::finish(
+            &building.auth_sqlite,
+            &paths.auth_sqlite,
+            args.audit_log.as_deref(),
+        )
+        .await?;
+    }
+    // Also drops the triggers a resumed run's earlier attempt left
+    audit_log::finish(&building.sqlite, &sql_db_path, args.audit_log.as_deref()).await?;
+
+    // A salvaged database is known to differ from the source, comparing
+    // the two would only fail
+    if let Some(report) = &salvage_report {
+        for (table, counts) in &report.tables {
+            meta::set_value(
+                &building.sqlite,
+                &format!("salvage.{}", table),
+                &format!(
+                    "{} recovered, {} skipped, {} unreadable",
+                    counts.recovered, counts.skipped, counts.unreadable
+                ),
+            )
+            .await?;
+        }
+        meta::set_verification(&building.sqlite, "salvage").await?;
+    } else {
+        progress.phase_started(Phase::Verification);
+        let verified = async {
+            let mut checkpoints = Checkpoints::load(&building.sqlite).await?;
+            verify_blind_signatures(&building, &mut checkpoints).await?;
+            verify_migration(&building, args.compact, &mut checkpoints, progress.as_ref()).await?;
+            if args.validate_proofs {
+                validate_proofs::validate_proofs(&work_dir, &building.sqlite).await?;
+            }
+            Ok(())
+        }
+        .instrument(Phase::Verification.span())
+        .await;
+
+        match verified {
+            Ok(()) => {
+                checkpoint::clear(&building.sqlite).await?;
+                meta::set_verification(&building.sqlite, "verified").await?;
+                // A merged target holds more than this source
+                if !merging {
+                    table_hashes::report(&building.sqlite, &sql_db_path, true).await?;
+                }
+                if !(merging && paths.auth_sqlite.exists()) && building.auth_sqlite.exists() {
+                    table_hashes::report(&building.auth_sqlite, &paths.auth_sqlite, false).await?;
+                }
+            }
+            Err(err) => {
+                meta::set_verification(&building.sqlite, "failed").await?;
+                return Err(err);
+            }
+        }
+    }
+
+    drop(sqlite_db);
+    if merging {
+        if args.audit_log.is_some() {
+            audit_log::start(&sql_db_path).await?;
+        }
+        let merged = merge::merge_mint(&building.sqlite, &sql_db_path, &args.on_conflict).await?;
+        audit_log::finish(&sql_db_path, &sql_db_path, args.audit_log.as_deref()).await?;
+        merge::report_merge(&sql_db_path, &merged, progress.as_ref())?;
+        sqlite::remove_database(&building.sqlite)?;
+    } else {
+        sqlite::rename_database(&building.sqlite, &sql_db_path).await?;
+    }
+    if building.auth_sqlite.exists() {
+        if merging && paths.auth_sqlite.exists() {
+            if args.audit_log.is_some() {
+                audit_log::start(&paths.auth_sqlite).await?;
+            }
+            let merged =
+                merge::merge_auth(&building.auth_sqlite, &paths.auth_sqlite, &args.on_conflict)
+                    .await?;
+            audit_log::finish(
+                &paths.auth_sqlite,
+                &paths.auth_sqlite,
+                args.audit_log.as_deref(),
+            )
+            .await?;
+            merge::report_merge(&paths.auth_sqlite, &merged, progress.as_ref())?;
+            sqlite::remove_database(&building.auth_sqlite)?;
+        } else {
+            sqlite::rename_database(&building.auth_sqlite, &paths.auth_sqlite).await?;
+        }
+    }
+    if args.chmod.is_some() || args.chown.is_some() {
+        permissions::apply(&sql_db_path, args.chmod, args.chown)?;
+        permissions::apply(&paths.auth_sqlite, args.chmod, args.chown)?;
+    }
+    // Before anything points at the databases or the source is marked or
+    // shredded, so a failure leaves the work dir as it was before the run
+    if args.verify_after {
+        let in_place = MigrationPaths {
+            redb: building.redb.clone(),
+            sqlite: sql_db_path.clone(),
+            auth_redb: building.auth_redb.clone(),
+            auth_sqlite: paths.auth_sqlite.clone(),
+        };
+        let verified = AssertUnwindSafe(verify_in_place(&in_place, args.compact))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|panic| Err(anyhow!("{}", panic_message(&*panic))));
+        if let Err(err) = verified {
+            rollback::set_aside_failed(&[&sql_db_path, &paths.auth_sqlite]).await?;
+            return Err(err.context(
+                "Verifying the SQLite databases in place failed, they were set aside and the source is left as it was",
+            ));
+        }
+        say!("✅ The SQLite databases in place match the source");
+    }
+    if output_name.is_some() {
+        paths::link_database(&links.sqlite, &sql_db_path)?;
+        if paths.auth_sqlite.exists() {
+            paths::link_database(&links.auth_sqlite, &paths.auth_sqlite)?;
+        }
+        say!("Linked {:?} to {:?}", links.sqlite, sql_db_path);
+    }
+    if args.tmp_dir.is_some() {
+        // Only fails if something else was put there, which is left alone
+        if let Some(staging) = building.sqlite.parent() {
+            let _ = std::fs::remove_dir(staging);
+        }
+    }
+
+    if args.fsync {
+        tracing::info!("Syncing SQLite databases to disk...");
+        sqlite::sync_database(&sql_db_path)?;
+        sqlite::sync_database(&paths.auth_sqlite)?;
+        paths::sync_dir(&work_dir)?;
+    }
+
+    if args.smoke_test {
+        smoke_test::smoke_test(&paths).await?;
+    }
+
+    if let Some(report) = &salvage_report {
+        salvage::print_report(report);
+        salvage::write_report(report, &paths::with_suffix(&sql_db_path, ".salvage.json"))?;
+        // No marker, the source still holds data that was not migrated
+        audit_indexes(&sql_db_path).await?;
+        audit_timestamps(&sql_db_path, progress.as_ref()).await?;
+        audit_schema_coverage(&paths, progress.as_ref()).await?;
+        check_warnings(&args, &warnings)?;
+        if args.compress_output {
+            compress_outputs(&paths).await?;
+        }
+        write_outputs(&paths, args.output_format).await?;
+        return Ok(());
+    }
+
+    // A merged target also holds its own rows, only the source is shown
+    let (proof_states, proof_kinds, quote_states) = if merging {
+        (None, None, None)
+    } else {
+        (
+            Some(stats::sqlite_proof_states(&sql_db_path).await?),
+            Some(stats::sqlite_proof_kinds(&sql_db_path).await?),
+            Some(stats::sqlite_quote_states(&sql_db_path).await?),
+        )
+    };
+    stats::print_proof_states(
+        &stats::redb_proof_states(&redb_path)?,
+        proof_states.as_ref(),
+    );
+    stats::print_proof_kinds(&stats::redb_proof_kinds(&redb_path)?, proof_kinds.as_ref());
+    stats::print_quote_states(
+        &stats::redb_quote_states(&redb_path)?,
+        quote_states.as_ref(),
+    );
+
+    say!("\n🎉 Migration verification completed successfully!");
+    say!("All data matches between Redb and SQLite databases");
+
+    // The index audit creates missing indexes, which the checksums in the
+    // marker have to include
+    audit_indexes(&sql_db_path).await?;
+    audit_timestamps(&sql_db_path, progress.as_ref()).await?;
+    audit_schema_coverage(&paths, progress.as_ref()).await?;
+    // Before anything is shredded or marked, so the run can be investigated
+    // and repeated
+    check_warnings(&args, &warnings)?;
+
+    if args.shred_source {
+        cleanup::shred_sources(&paths, &source_sha256).await?;
+    }
+
+    let target_checksum = |path: &Path| -> Result<Option<String>> {
+        if merging || !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(meta::sha256_file(path)?))
+    };
+    marker::write_marker(
+        &paths.redb,
+        &MigratedMarker {
+            migrated_at: meta::unix_time(),
+            target: sql_db_path.clone(),
+            source_sha256,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            target_sha256: target_checksum(&sql_db_path)?,
+            auth_target_sha256: target_checksum(&paths.auth_sqlite)?,
+            merged: merging,
+        },
+    )?;
+
+    if args.compress_output {
+        compress_outputs(&paths).await?;
+    }
+    write_outputs(&paths, args.output_format).await?;
+
+    Ok(())
+}
+
+/// Compares the databases at their final paths with the source once more,
+/// from scratch
+async fn verify_in_place(paths: &MigrationPaths, compact: bool) -> Result<()> {
+    say!("\n=== Verifying the Databases in Place ===");
+    let mut checkpoints = Checkpoints::none();
+    verify_blind_signatures(paths, &mut checkpoints).await?;
+    verify_migration(paths, compact, &mut checkpoints, &NoProgress).await
+}
+
+/// Fails the migration on warnings that are denied, or not allowed under
+/// --strict
+fn check_warnings(args: &MigrateArgs, warnings: &RaisedWarnings) -> Result<()> {
+    let failing: Vec<String> = warnings
+        .counts()
+        .into_iter()
+        .filter(|(code, _)| args.deny.contains(code) || (args.strict && !args.allow.contains(code)))
+        .map(|(code, count)| format!("{} ({})", code, count))
+        .collect();
+    if !failing.is_empty() {
+        return Err(anyhow!(
+            "The migration raised warnings that fail it: {}. The SQLite databases are kept for investigation, the redb is not marked as migrated",
+            failing.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Writes each SQLite database in `format` next to it, replacing the output
+/// of an earlier run
+async fn write_outputs(paths: &MigrationPaths, format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Sqlite {
+        return Ok(());
+    }
+
+    for sqlite in [&paths.sqlite, &paths.auth_sqlite] {
+        if sqlite.exists() {
+            output_sink::write_output(sqlite, &sqlite.with_extension(format.extension()), format)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn compress_outputs(paths: &MigrationPaths) -> Result<()> {
+    for sqlite in [&paths.sqlite, &paths.auth_sqlite] {
+        if sqlite.exists() {
+            let compressed = sqlite::compress_database(sqlite).await?;
+            say!(
+                "✅ Wrote a compressed copy of {:?} to {:?}",
+                sqlite,
+                compressed
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn audit_schema_coverage(
+    paths: &MigrationPaths,
+    progress: &dyn ProgressObserver,
+) -> Result<()> {
+    audit_columns(&paths.sqlite, false, progress).await?;
+    if paths.auth_sqlite.exists() {
+        audit_columns(&paths.auth_sqlite, true, progress).await?;
+    }
+
+    Ok(())
+}
+
+enum PartialTargetAction {
+    Resume,
+    Wipe,
+}
+
+fn partial_target_action(
+    sql_db_path: &Path,
+    status: Option<&str>,
+    args: &MigrateArgs,
+) -> Result<PartialTargetAction> {
+    say!(
+        "⚠️  SQLite database at {:?} is from an incomplete run (verification: {})",
+        sql_db_path,
+        status.unwrap_or("never reached")
+    );
+
+    if args.resume {
+        return Ok(PartialTargetAction::Resume);
+    }
+    if args.wipe {
+        return Ok(PartialTargetAction::Wipe);
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "Incomplete SQLite database at {:?}. Use --resume to continue it or --wipe to start over.",
+            sql_db_path
+        ));
+    }
+
+    loop {
+        print!("[r]esume, [w]ipe and start over, or [a]bort? ");
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+
+        match answer.trim() {
+            "r" | "resume" => return Ok(PartialTargetAction::Resume),
+            "w" | "wipe" => return Ok(PartialTargetAction::Wipe),
+            "a" | "abort" | "" => return Err(anyhow!("Migration aborted")),
+            _ => continue,
+        }
+    }
+}
+
+async fn migrate_mint_info(
+    redb_db: &MintRedbDatabase,
+    sqlite_db: &MintSqliteDatabase,
+) -> Result<()> {
+    tracing::info!("Migrating mint info...");
+    let mint_info = salvage::reading(redb_db.get_mint_info()).await?;
+    let normalized = mint_info::normalize_mint_info(&mint_info)?;
+    if normalized != mint_info {
+        tracing::info!("Normalized the NUT settings of the mint info");
+    }
+    sqlite_db.set_mint_info(normalized).await?;
+
+    tracing::info!("Migrating quote TTL info...");
+    let quote_ttl_info = salvage::reading(redb_db.get_quote_ttl()).await?;
+    sqlite_db.set_quote_ttl(quote_ttl_info).await?;
+
+    tracing::info!("Mint info migration complete");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn migrate_proofs(
+    keysets: Vec<Id>,
+    redb_db: &MintRedbDatabase,
+    sqlite_db: &MintSqliteDatabase,
+    resuming: bool,
+    progress: &dyn ProgressObserver,
+    total_proofs: Option<u64>,
+    compact: bool,
+    throttle: &Throttle,
+) -> Result<u64> {
+    tracing::info!("Starting proofs migration for {} keysets...", keysets.len());
+    let total_keysets = keysets.len();
+    let mut reader = pipeline::read_proofs(redb_db.clone(), keysets, compact);
+    let mut invalid_secrets = vec![];
+    let mut migrated = 0;
+    let mut duplicates = 0;
+    let mut keyset = None;
+    let mut i = 0;
+
+    let written = async {
+        while let Some(batch) = reader.next().await? {
+            if keyset != Some(batch.keyset) {
+                keyset = Some(batch.keyset);
+                i += 1;
+                tracing::info!("Migrating proofs for keyset {}/{}", i, total_keysets);
+            }
+            let pipeline::ProofBatch {
+                proofs,
+                ys,
+                states,
+                invalid_secrets: invalid,
+                keyset: batch_keyset,
+                duplicates: dropped,
+            } = batch;
+            duplicates += dropped;
+            let count = proofs.len() as u64;
+            throttle.wait(count, || json_len(&proofs)).await;
+            let span = tracing::info_span!("batch", keyset = %batch_keyset, records = count);
+
+            async {
+                sqlite_db.add_proofs(proofs, None).await?;
+                invalid_secrets.extend(invalid);
+
+                // cdk-sqlite refuses to touch spent proofs again, so proofs an earlier
+                // run already moved to their final state are skipped
+                let mut target_states = vec![None; ys.len()];
+                if resuming {
+                    target_states = sqlite_db.get_proofs_states(&ys).await?;
+                }
+
+                let mut spent_ys = vec![];
+                let mut pending_ys = vec![];
+
+                for ((y, state), target_state) in ys.into_iter().zip(states).zip(target_states) {
+                    if state.is_some() && state == target_state {
+                        continue;
+                    }
+
+                    match state {
+                        Some(State::Spent) => spent_ys.push(y),
+                        Some(State::Pending) => pending_ys.push(y),
+                        _ => (),
+                    }
+                }
+
+                tracing::debug!(
+                    "Updating states - Spent: {}, Pending: {}",
+                    spent_ys.len(),
+                    pending_ys.len()
+                );
+                sqlite_db
+                    .update_proofs_states(&spent_ys, State::Spent)
+                    .await?;
+                sqlite_db
+                    .update_proofs_states(&pending_ys, State::Pending)
+                    .await?;
+
+                Ok::<_, anyhow::Error>(())
+            }
+            .instrument(span)
+            .await?;
+
+            migrated += count;
+            progress.records(Phase::Proofs, migrated, total_proofs);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    reader.close().await;
+    written?;
+
+    spending_conditions::print_invalid_secrets(&invalid_secrets);
+    spending_conditions::report_invalid_secrets(&invalid_secrets, progress);
+
+    if duplicates > 0 {
+        say!("🧹 Collapsed {} exact duplicate proofs", duplicates);
+    }
+
+    tracing::info!("Proofs migration complete");
+    Ok(duplicates)
+}
+
+async fn migrate_quotes(
+    redb_db: &MintRedbDatabase,
+    sqlite_db: &MintSqliteDatabase,
+    progress: &dyn ProgressObserver,
+    throttle: &Throttle,
+) -> Result<()> {
+    tracing::info!("Starting quotes migration...");
+    let melt_quotes = salvage::reading(redb_db.get_melt_quotes()).await?;
+    tracing::info!("Found {} melt quotes to migrate", melt_quotes.len());
+    let mint_quotes = salvage::reading(redb_db.get_mint_quotes()).await?;
+    let total = (melt_quotes.len() + mint_quotes.len()) as u64;
+
+    for (i, melt_quote) in melt_quotes.iter().enumerate() {
+        tracing::debug!(
+            "Processing melt quote {}/{} {}",
+            i + 1,
+            melt_quotes.len(),
+            melt_quote.id
+        );
+        if let Ok(Some((melt_request, payment_key))) =
+            salvage::reading(redb_db.get_melt_request(&melt_quote.id)).await
+        {
+            sqlite_db
+                .add_melt_request(melt_request, payment_key)
+                .await
+                .ok();
+        }
+
+        let converted = melt_quotes::convert_melt_quote(melt_quote);
+        if converted.amount != melt_quote.amount {
+            tracing::info!(
+                "Restored amount {} {} of amountless melt quote {} from msat_to_pay",
+                converted.amount,
+                converted.unit,
+                converted.id
+            );
+        } else if converted.amount == Amount::ZERO && melt_quotes::is_amountless(&converted) {
+            tracing::warn!(
+                "Amountless melt quote {} has no amount and no msat_to_pay, keeping it as is",
+                converted.id
+            );
+            progress.warning(
+                WarningCode::AmountlessQuote,
+                &format!(
+                    "Amountless melt quote {} has no amount and no msat_to_pay",
+                    converted.id
+                ),
+            );
+        }
+
+        throttle
+            .wait(1, || json_len(std::slice::from_ref(&converted)))
+            .await;
+        sqlite_db.add_melt_quote(converted).await?;
+        progress.records(Phase::Quotes, i as u64 + 1, Some(total));
+    }
+
+    tracing::info!("Found {} mint quotes to migrate", mint_quotes.len());
+
+    for (i, mint_quote) in mint_quotes.iter().enumerate() {
+        tracing::debug!(
+            "Processing mint quote {}/{} {}",
+            i + 1,
+            mint_quotes.len(),
+            mint_quote.id
+        );
+        throttle
+            .wait(1, || json_len(std::slice::from_ref(mint_quote)))
+            .await;
+        sqlite_db.add_mint_quote(mint_quote.clone()).await?;
+        progress.records(
+            Phase::Quotes,
+            (melt_quotes.len() + i + 1) as u64,
+            Some(total),
+        );
+    }
+
+    tracing::info!("Quotes migration complete");
+    Ok(())
+}
+
+async fn migrate_blind_signatures(
+    redb_path: &Path,
+    sqlite_db: &MintSqliteDatabase,
+    resuming: bool,
+    progress: &dyn ProgressObserver,
+    throttle: &Throttle,
+) -> Result<()> {
+    let total = progress::count_records(redb_path, "blinded_signatures");
+    let mut reader = pipeline::read_blind_signatures(redb_path.to_path_buf());
+    let mut migrated = 0;
+
+    let written = async {
+        while let Some(batch) = reader.next().await? {
+            let pipeline::SignatureBatch {
+                mut messages,
+                mut sigs,
+            } = batch;
+            migrated += messages.len() as u64;
+            throttle
+                .wait(messages.len() as u64, || json_len(&sigs))
+                .await;
+            let span = tracing::info_span!("batch", records = messages.len());
+
+            async {
+                // Unlike the other tables blind signatures are inserted without an
+                // upsert, so the ones an earlier run already wrote have to be left out
+                if resuming {
+                    let existing = sqlite_db.get_blind_signatures(&messages).await?;
+                    (messages, sigs) = messages
+                        .into_iter()
+                        .zip(sigs)
+                        .zip(existing)
+                        .filter(|(_, existing)| existing.is_none())
+                        .map(|(pair, _)| pair)
+                        .unzip();
+                }
+
+                sqlite_db
+                    .add_blind_signatures(&messages, &sigs, None)
+                    .await?;
+
+                Ok::<_, anyhow::Error>(())
+            }
+            .instrument(span)
+            .await?;
+            progress.records(Phase::BlindSignatures, migrated, total);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    reader.close().await;
+    written?;
+
+    tracing::info!("Blind signatures migration complete");
+    Ok(())
+}
+
+fn mangen(out_dir: Option<&Path>) -> Result<()> {
+    let command = CLIArgs::command().name(env!("CARGO_PKG_NAME"));
+
+    match out_dir {
+        Some(out_dir) => {
+            std::fs::create_dir_all(out_dir)?;
+            clap_mangen::generate_to(command, out_dir)?;
+            println!("Man pages written to {:?}", out_dir);
+        }
+        None => clap_mangen::Man::new(command).render(&mut std::io::stdout())?,
+    }
+
+    Ok(())
+}
+
+// cdk-mintd 0.10 keeps its data in ~/.cdk-mintd on every platform, so that
+// stays the default. Operators who moved mintd to the platform data directory
+// are picked up as long as ~/.cdk-mintd holds no database.
+fn work_dir() -> Result<PathBuf> {
+    let home_dir = home::home_dir().ok_or(anyhow!("Unknown home dir"))?;
+    let dir = home_dir.join(".cdk-mintd");
+
+    if !dir.join("cdk-mintd.redb").exists()
+        && let Some(platform_dir) = platform_work_dir(&home_dir)
+        && platform_dir.join("cdk-mintd.redb").exists()
+    {
+        println!(
+            "Using work dir from platform data directory: {:?}",
+            platform_dir
+        );
+        return Ok(platform_dir);
+    }
+
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+#[cfg(target_os = "windows")]
+fn platform_work_dir(_home_dir: &Path) -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|app_data| PathBuf::from(app_data).join("cdk-mintd"))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_work_dir(home_dir: &Path) -> Option<PathBuf> {
+    Some(home_dir.join("Library/Application Support/cdk-mintd"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_work_dir(home_dir: &Path) -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir.join(".local/share"));
+
+    Some(data_home.join("cdk-mintd"))
+}
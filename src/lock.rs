@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+
+use crate::output::say;
+use crate::raw;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Waits until no other process holds the redb, e.g. while mintd shuts down.
+/// Without a timeout a held lock fails right away.
+pub fn wait_for_lock(redb_path: &Path, deadline: Option<Instant>) -> Result<()> {
+    let mut announced = false;
+
+    loop {
+        // Anything but the lock, such as a file needing an upgrade, is left
+        // to the migration to report
+        if !raw::is_locked(redb_path) {
+            if announced {
+                say!("🔓 {:?} was released", redb_path);
+            }
+            return Ok(());
+        }
+
+        match deadline {
+            Some(deadline) if Instant::now() < deadline => {
+                if !announced {
+                    say!(
+                        "🔒 {:?} is locked by another process, waiting for it to be released...",
+                        redb_path
+                    );
+                    announced = true;
+                }
+                sleep(POLL_INTERVAL);
+            }
+            Some(_) => {
+                return Err(anyhow!(
+                    "{:?} is still locked by another process, giving up",
+                    redb_path
+                ));
+            }
+            None => {
+                return Err(anyhow!(
+                    "{:?} is locked by another process, is mintd still running? Use --wait-for-lock to wait for it.",
+                    redb_path
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use anyhow::Result;
+use cdk_common::database::{MintProofsDatabase, MintSignaturesDatabase};
+use cdk_common::{BlindSignature, Proof, PublicKey, State};
+use cdk_sqlite::MintSqliteDatabase;
+use redb::{ReadableTable, ReadableTableMetadata};
+
+use crate::progress::{Phase, ProgressObserver};
+use crate::raw::{self, RedbReader};
+use crate::salvage::reading_sync;
+use crate::spending_conditions::{self, InvalidSecret};
+use crate::throttle::{Throttle, json_len};
+
+// With --low-memory proofs and blind signatures are streamed from redb in
+// small batches instead of being loaded a keyset or a table at a time.
+pub const BATCH_SIZE: usize = 100;
+
+pub async fn migrate_proofs(
+    redb_path: &Path,
+    sqlite_db: &MintSqliteDatabase,
+    resuming: bool,
+    progress: &dyn ProgressObserver,
+    throttle: &Throttle,
+) -> Result<()> {
+    tracing::info!("Streaming proofs in batches of {}...", BATCH_SIZE);
+
+    let (proofs_table, states_table) = reading_sync(|| {
+        let read_txn = RedbReader::open(redb_path)?.begin_read()?;
+        Ok((
+            raw::open_table::<[u8; 33], &str>(&read_txn, "proofs")?,
+            raw::open_table::<[u8; 33], &str>(&read_txn, "proofs_state")?,
+        ))
+    })?;
+    let Some(proofs_table) = proofs_table else {
+        return Ok(());
+    };
+    let total = reading_sync(|| Ok(proofs_table.len()?))?;
+    let mut entries = reading_sync(|| Ok(proofs_table.iter()?))?;
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut invalid_secrets = vec![];
+    let mut migrated = 0;
+    // Only the reads are guarded, an error writing SQLite is not one reading the redb
+    while let Some(entry) = reading_sync(|| {
+        let Some(entry) = entries.next() else {
+            return Ok(None);
+        };
+        let (y, proof) = entry?;
+        let proof: Proof = serde_json::from_slice(proof.value())?;
+        // cdk-redb only writes a state once a proof leaves the unspent state
+        let state = match &states_table {
+            Some(states_table) => states_table
+                .get(y.value())?
+                .map(|state| serde_json::from_slice::<State>(state.value()))
+                .transpose()?,
+            None => None,
+        };
+        Ok(Some((proof, state)))
+    })? {
+        batch.push(entry);
+
+        if batch.len() == BATCH_SIZE {
+            migrated += batch.len();
+            throttle.wait(batch.len() as u64, || json_len(&batch)).await;
+            invalid_secrets
+                .extend(add_proofs(sqlite_db, std::mem::take(&mut batch), resuming).await?);
+            tracing::debug!("Migrated {} proofs", migrated);
+            progress.records(Phase::Proofs, migrated as u64, Some(total));
+        }
+    }
+    migrated += batch.len();
+    throttle.wait(batch.len() as u64, || json_len(&batch)).await;
+    invalid_secrets.extend(add_proofs(sqlite_db, batch, resuming).await?);
+    progress.records(Phase::Proofs, migrated as u64, Some(total));
+
+    spending_conditions::print_invalid_secrets(&invalid_secrets);
+    spending_conditions::report_invalid_secrets(&invalid_secrets, progress);
+
+    tracing::info!("Proofs migration complete, {} proofs", migrated);
+    Ok(())
+}
+
+#[tracing::instrument(name = "batch", skip_all, fields(records = batch.len()))]
+async fn add_proofs(
+    sqlite_db: &MintSqliteDatabase,
+    batch: Vec<(Proof, Option<State>)>,
+    resuming: bool,
+) -> Result<Vec<InvalidSecret>> {
+    let ys = batch
+        .iter()
+        .map(|(proof, _)| proof.y())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // cdk-sqlite refuses to touch spent proofs again, so proofs an earlier
+    // run already moved to their final state are skipped
+    let target_states = if resuming {
+        sqlite_db.get_proofs_states(&ys).await?
+    } else {
+        vec![None; ys.len()]
+    };
+
+    let (proofs, states): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+    let invalid_secrets = spending_conditions::invalid_secrets(&proofs, &states)?;
+    sqlite_db.add_proofs(proofs, None).await?;
+
+    let mut spent_ys = vec![];
+    let mut pending_ys = vec![];
+    for ((y, state), target_state) in ys.into_iter().zip(states).zip(target_states) {
+        if state.is_some() && state == target_state {
+            continue;
+        }
+
+        match state {
+            Some(State::Spent) => spent_ys.push(y),
+            Some(State::Pending) => pending_ys.push(y),
+            _ => (),
+        }
+    }
+
+    sqlite_db
+        .update_proofs_states(&spent_ys, State::Spent)
+        .await?;
+    sqlite_db
+        .update_proofs_states(&pending_ys, State::Pending)
+        .await?;
+
+    Ok(invalid_secrets)
+}
+
+pub async fn migrate_blind_signatures(
+    redb_path: &Path,
+    sqlite_db: &MintSqliteDatabase,
+    resuming: bool,
+    progress: &dyn ProgressObserver,
+    throttle: &Throttle,
+) -> Result<()> {
+    tracing::info!("Streaming blind signatures in batches of {}...", BATCH_SIZE);
+
+    let table = reading_sync(|| {
+        let read_txn = RedbReader::open(redb_path)?.begin_read()?;
+        raw::open_table::<[u8; 33], &str>(&read_txn, "blinded_signatures")
+    })?;
+    let Some(table) = table else {
+        return Ok(());
+    };
+    let total = reading_sync(|| Ok(table.len()?))?;
+    let mut entries = reading_sync(|| Ok(table.iter()?))?;
+
+    let mut messages = Vec::with_capacity(BATCH_SIZE);
+    let mut sigs = Vec::with_capacity(BATCH_SIZE);
+    let mut migrated = 0;
+    while let Some((message, sig)) = reading_sync(|| {
+        let Some(entry) = entries.next() else {
+            return Ok(None);
+        };
+        let (message, sig) = entry?;
+        Ok(Some((
+            PublicKey::from_slice(message.value())?,
+            serde_json::from_slice::<BlindSignature>(sig.value())?,
+        )))
+    })? {
+        messages.push(message);
+        sigs.push(sig);
+
+        if messages.len() == BATCH_SIZE {
+            migrated += messages.len();
+            throttle
+                .wait(messages.len() as u64, || json_len(&sigs))
+                .await;
+            add_blind_signatures(
+                sqlite_db,
+                std::mem::take(&mut messages),
+                std::mem::take(&mut sigs),
+                resuming,
+            )
+            .await?;
+            progress.records(Phase::BlindSignatures, migrated as u64, Some(total));
+        }
+    }
+    migrated += messages.len();
+    throttle
+        .wait(messages.len() as u64, || json_len(&sigs))
+        .await;
+    add_blind_signatures(sqlite_db, messages, sigs, resuming).await?;
+    progress.records(Phase::BlindSignatures, migrated as u64, Some(total));
+
+    tracing::info!(
+        "Blind signatures migration complete, {} signatures",
+        migrated
+    );
+    Ok(())
+}
+
+#[tracing::instrument(name = "batch", skip_all, fields(records = messages.len()))]
+async fn add_blind_signatures(
+    sqlite_db: &MintSqliteDatabase,
+    mut messages: Vec<PublicKey>,
+    mut sigs: Vec<BlindSignature>,
+    resuming: bool,
+) -> Result<()> {
+    // Unlike the other tables blind signatures are inserted without an upsert,
+    // so the ones an earlier run already wrote have to be left out
+    if resuming {
+        let existing = sqlite_db.get_blind_signatures(&messages).await?;
+        (messages, sigs) = messages
+            .into_iter()
+            .zip(sigs)
+            .zip(existing)
+            .filter(|(_, existing)| existing.is_none())
+            .map(|(pair, _)| pair)
+            .unzip();
+    }
+
+    if !messages.is_empty() {
+        sqlite_db
+            .add_blind_signatures(&messages, &sigs, None)
+            .await?;
+    }
+
+    Ok(())
+}
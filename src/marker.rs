@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::paths::with_suffix;
+
+/// Sidecar written next to the source redb after a verified migration
+///
+/// A sidecar is used instead of a record inside the redb so the source stays
+/// byte for byte what the mint last wrote.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MigratedMarker {
+    pub migrated_at: u64,
+    pub target: PathBuf,
+    pub source_sha256: String,
+    pub tool_version: String,
+    // Of the SQLite databases as the migration left them, so a rollback can
+    // tell they were not written to since. Not set for a merge, whose target
+    // held data of its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_target_sha256: Option<String>,
+    // The source was merged into an existing database, which cannot be
+    // rolled back by removing it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub merged: bool,
+}
+
+pub fn marker_path(redb_path: &Path) -> PathBuf {
+    with_suffix(redb_path, ".migrated")
+}
+
+pub fn read_marker(redb_path: &Path) -> Result<Option<MigratedMarker>> {
+    let path = marker_path(redb_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+}
+
+pub fn remove_marker(redb_path: &Path) -> Result<()> {
+    match std::fs::remove_file(marker_path(redb_path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+pub fn write_marker(redb_path: &Path, marker: &MigratedMarker) -> Result<()> {
+    std::fs::write(
+        marker_path(redb_path),
+        serde_json::to_string_pretty(marker)?,
+    )?;
+
+    Ok(())
+}
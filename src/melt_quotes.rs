@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use cdk_common::mint::MeltQuote;
+use cdk_common::{Amount, Bolt11Invoice, CurrencyUnit, MeltQuoteState};
+use lightning::offers::offer::Offer;
+
+// Melt quotes for an amountless invoice or offer take their amount from the melt
+// options, kept as msat_to_pay. Some mints stored such quotes with a zero
+// amount, which cdk-sqlite would carry over as a quote paying nothing, so
+// the amount is restored from msat_to_pay instead.
+
+/// Whether the quote pays a bolt11 invoice or bolt12 offer that does not fix an amount
+pub fn is_amountless(quote: &MeltQuote) -> bool {
+    if let Ok(invoice) = Bolt11Invoice::from_str(&quote.request) {
+        return invoice.amount_milli_satoshis().is_none();
+    }
+
+    Offer::from_str(&quote.request)
+        .map(|offer| offer.amount().is_none())
+        .unwrap_or(false)
+}
+
+/// The melt quote as it is written to SQLite
+pub fn convert_melt_quote(quote: &MeltQuote) -> MeltQuote {
+    let mut quote = quote.clone();
+
+    if !is_amountless(&quote) || quote.amount != Amount::ZERO {
+        return quote;
+    }
+
+    if let Some(amount) = quote
+        .msat_to_pay
+        .and_then(|msat| msat_to_unit(msat, &quote.unit))
+    {
+        quote.amount = amount;
+    }
+
+    quote
+}
+
+/// Describes how the amount of a quote disagrees with its msat_to_pay, if it does
+pub fn msat_mismatch(quote: &MeltQuote) -> Option<String> {
+    let msat = quote.msat_to_pay?;
+    let expected = msat_to_unit(msat, &quote.unit)?;
+
+    // Either rounding direction is fine for a sat quote
+    let floor = match quote.unit {
+        CurrencyUnit::Sat => Amount::from(u64::from(msat) / 1000),
+        _ => expected,
+    };
+    if quote.amount == expected || quote.amount == floor {
+        return None;
+    }
+
+    let description = if quote.unit == CurrencyUnit::Sat && quote.amount == msat {
+        format!(
+            "amount {} sat equals msat_to_pay, it looks like msat",
+            quote.amount
+        )
+    } else if quote.unit == CurrencyUnit::Sat
+        && u64::from(quote.amount) / 1000 == u64::from(expected)
+    {
+        format!(
+            "amount {} sat is a thousand times msat_to_pay {} msat, it looks converted twice",
+            quote.amount, msat
+        )
+    } else {
+        format!(
+            "amount {} {} does not match msat_to_pay {} msat",
+            quote.amount, quote.unit, msat
+        )
+    };
+
+    Some(description)
+}
+
+/// Fee reserve per unit held by all quotes and by pending quotes
+pub fn fee_reserves(quotes: &[MeltQuote]) -> BTreeMap<String, (u64, u64)> {
+    let mut reserves: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+
+    for quote in quotes {
+        let (total, pending) = reserves.entry(quote.unit.to_string()).or_default();
+        *total += u64::from(quote.fee_reserve);
+        if quote.state == MeltQuoteState::Pending {
+            *pending += u64::from(quote.fee_reserve);
+        }
+    }
+
+    reserves
+}
+
+// Rounds up, a quote never pays less than the melt options asked for
+fn msat_to_unit(msat: Amount, unit: &CurrencyUnit) -> Option<Amount> {
+    let msat = u64::from(msat);
+    match unit {
+        CurrencyUnit::Sat => Some(Amount::from(msat.div_ceil(1000))),
+        CurrencyUnit::Msat => Some(Amount::from(msat)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Invoices from the BOLT 11 test vectors
+    const AMOUNTLESS_INVOICE: &str = "lnbc1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6twvus8g6rfwvs8qun0dfjkxaq9qrsgq357wnc5r2ueh7ck6q93dj32dlqnls087fxdwk8qakdyafkq3yap9us6v52vjjsrvywa6rt52cm9r9zqt8r2t7mlcwspyetp5h2tztugp9lfyql";
+    const FIXED_INVOICE: &str = "lnbc20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygshp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqfp4qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q9qrsgq9vlvyj8cqvq6ggvpwd53jncp9nwc47xlrsnenq2zp70fq83qlgesn4u3uyf4tesfkkwwfg3qs54qe426hp3tz7z6sweqdjg05axsrjqp9yrrwc";
+
+    // Offers from the BOLT 12 test vectors
+    const AMOUNTLESS_OFFER: &str =
+        "lno1pgx9getnwss8vetrw3hhyuckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg";
+    const FIXED_OFFER: &str =
+        "lno1pqpzwyq2p32x2um5ypmx2cm5dae8x93pqthvwfzadd7jejes8q9lhc4rvjxd022zv5l44g6qah82ru5rdpnpj";
+
+    fn quote(request: &str, amount: u64, msat_to_pay: Option<u64>) -> MeltQuote {
+        MeltQuote::new(
+            request.to_string(),
+            CurrencyUnit::Sat,
+            Amount::from(amount),
+            Amount::from(10),
+            0,
+            "lookup".to_string(),
+            msat_to_pay.map(Amount::from),
+        )
+    }
+
+    #[test]
+    fn amountless_quote_takes_its_amount_from_msat_to_pay() {
+        let zero = quote(AMOUNTLESS_INVOICE, 0, Some(1_500_500));
+        assert!(is_amountless(&zero));
+
+        let converted = convert_melt_quote(&zero);
+        assert_eq!(converted.amount, Amount::from(1501));
+        assert_eq!(converted.msat_to_pay, zero.msat_to_pay);
+    }
+
+    #[test]
+    fn amountless_quote_without_msat_to_pay_is_left_as_is() {
+        let zero = quote(AMOUNTLESS_INVOICE, 0, None);
+        assert!(is_amountless(&zero));
+
+        assert_eq!(convert_melt_quote(&zero), zero);
+    }
+
+    #[test]
+    fn fixed_amount_quote_is_left_untouched() {
+        for fixed in [
+            quote(FIXED_INVOICE, 200_000, None),
+            quote(FIXED_INVOICE, 0, Some(1_500_500)),
+            quote(FIXED_OFFER, 0, Some(1_500_500)),
+        ] {
+            assert!(!is_amountless(&fixed));
+            assert_eq!(convert_melt_quote(&fixed), fixed);
+        }
+    }
+
+    #[test]
+    fn amountless_offer_takes_its_amount_from_msat_to_pay() {
+        let zero = quote(AMOUNTLESS_OFFER, 0, Some(21_000));
+        assert!(is_amountless(&zero));
+
+        assert_eq!(convert_melt_quote(&zero).amount, Amount::from(21));
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use cdk_sqlite::MintSqliteDatabase;
+#[cfg(feature = "auth")]
+use cdk_sqlite::mint::MintSqliteAuthDatabase;
+use serde::Serialize;
+use sqlx::{Row, SqliteConnection};
+
+use crate::output::say;
+use crate::paths::with_suffix;
+use crate::progress::{ProgressObserver, WarningCode};
+use crate::sqlite;
+
+// A merge copies the freshly migrated database into one that already holds
+// data, e.g. a mint that started over on SQLite and now imports its redb
+// history. Rows equal to one already in the target are left out, keysets
+// derived from the same seed show up on both sides. A row whose key is taken
+// by a different row is a conflict, resolved per table by --on-conflict.
+
+// Tables and their primary keys
+const MINT_TABLES: &[(&str, &str)] = &[
+    ("keyset", "id"),
+    ("mint_quote", "id"),
+    ("melt_quote", "id"),
+    ("melt_request", "id"),
+    ("proof", "y"),
+    ("blind_signature", "y"),
+];
+
+const AUTH_TABLES: &[(&str, &str)] = &[
+    ("keyset", "id"),
+    ("proof", "y"),
+    ("blind_signature", "y"),
+    ("protected_endpoints", "endpoint"),
+];
+
+// redb keeps no creation time for proofs and signatures, cdk-sqlite fills in
+// the time they were migrated, so it does not take part in the comparison
+const NOT_COMPARED: &[&str] = &["created_time"];
+
+// Keys listed in a conflict error, the rest are counted
+const MAX_CONFLICTS_SHOWN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Keep the row already in the target
+    Skip,
+    /// Replace the row in the target with the migrated one
+    Overwrite,
+    /// Abort the merge, leaving the target as it was
+    Fail,
+}
+
+impl fmt::Display for OnConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OnConflict::Skip => "skip",
+            OnConflict::Overwrite => "overwrite",
+            OnConflict::Fail => "fail",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One `--on-conflict` value, for every table or for the named one
+#[derive(Debug, Clone)]
+pub struct ConflictRule {
+    pub table: Option<String>,
+    pub strategy: OnConflict,
+}
+
+pub fn parse_conflict_rule(rule: &str) -> Result<ConflictRule, String> {
+    let (table, strategy) = match rule.split_once('=') {
+        Some((table, strategy)) => (Some(table), strategy),
+        None => (None, rule),
+    };
+
+    let strategy = match strategy {
+        "skip" => OnConflict::Skip,
+        "overwrite" => OnConflict::Overwrite,
+        "fail" => OnConflict::Fail,
+        _ => {
+            return Err(format!(
+                "{} is not a conflict strategy, use skip, overwrite or fail",
+                strategy
+            ));
+        }
+    };
+
+    if let Some(table) = table {
+        let known = MINT_TABLES
+            .iter()
+            .chain(AUTH_TABLES)
+            .any(|(name, _)| *name == table);
+        if !known {
+            return Err(format!("{} is not a table that is merged", table));
+        }
+    }
+
+    Ok(ConflictRule {
+        table: table.map(str::to_string),
+        strategy,
+    })
+}
+
+// A rule for the table wins over one for every table, and a later rule over
+// an earlier one. Without any a conflict fails the merge.
+fn strategy(rules: &[ConflictRule], table: &str) -> OnConflict {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.table.as_deref() == Some(table))
+        .or_else(|| rules.iter().rev().find(|rule| rule.table.is_none()))
+        .map_or(OnConflict::Fail, |rule| rule.strategy)
+}
+
+/// Rows of one table copied into the target, rows it already held and the
+/// keys that were taken by a different row
+#[derive(Debug, Serialize)]
+pub struct MergedTable {
+    pub table: &'static str,
+    pub inserted: u64,
+    pub identical: u64,
+    pub on_conflict: OnConflict,
+    pub conflicts: Vec<String>,
+}
+
+/// Brings an existing target to the schema of the linked cdk-sqlite, so the
+/// migrated rows fit and a target from a newer mintd fails before anything
+/// is migrated
+pub async fn prepare_target(target: &Path, auth: bool) -> Result<()> {
+    let prepared = match auth {
+        #[cfg(feature = "auth")]
+        true => MintSqliteAuthDatabase::new(target).await.map(drop),
+        #[cfg(not(feature = "auth"))]
+        true => return Err(anyhow!("This build has no auth support")),
+        false => MintSqliteDatabase::new(target).await.map(drop),
+    };
+
+    prepared.map_err(|e| anyhow!("Could not merge into {:?}: {}", target, e))
+}
+
+pub async fn merge_mint(
+    source: &Path,
+    target: &Path,
+    rules: &[ConflictRule],
+) -> Result<Vec<MergedTable>> {
+    merge(source, target, MINT_TABLES, rules).await
+}
+
+pub async fn merge_auth(
+    source: &Path,
+    target: &Path,
+    rules: &[ConflictRule],
+) -> Result<Vec<MergedTable>> {
+    merge(source, target, AUTH_TABLES, rules).await
+}
+
+// Every table is merged in one transaction, a failed merge leaves the target
+// as it was
+async fn merge(
+    source: &Path,
+    target: &Path,
+    tables: &[(&'static str, &'static str)],
+    rules: &[ConflictRule],
+) -> Result<Vec<MergedTable>> {
+    let pool = sqlite::connect(target).await?;
+    sqlx::query("ATTACH DATABASE ? AS source")
+        .bind(source.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+
+    let mut tx = pool.begin().await?;
+    let mut merged = vec![];
+    for (table, key) in tables {
+        let columns = columns(&mut tx, table).await?;
+        let compared = columns
+            .iter()
+            .filter(|column| !NOT_COMPARED.contains(&column.as_str()))
+            .map(|column| sqlite::quote_identifier(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let columns = columns
+            .iter()
+            .map(|column| sqlite::quote_identifier(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let conflicting = format!(
+            "SELECT {key} FROM (SELECT {compared} FROM source.{table}
+                EXCEPT SELECT {compared} FROM main.{table})
+            WHERE {key} IN (SELECT {key} FROM main.{table})"
+        );
+        let conflicts: Vec<String> =
+            sqlx::query(&format!("SELECT quote({key}) FROM ({conflicting})"))
+                .fetch_all(&mut *tx)
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+
+        let on_conflict = strategy(rules, table);
+        if !conflicts.is_empty() {
+            match on_conflict {
+                OnConflict::Fail => {
+                    return Err(anyhow!(
+                        "{} row(s) of {} differ from rows with the same {} in {:?}: {}. Use --on-conflict to skip or overwrite them.",
+                        conflicts.len(),
+                        table,
+                        key,
+                        target,
+                        listed(&conflicts)
+                    ));
+                }
+                // The insert below takes the place of the removed rows
+                OnConflict::Overwrite => {
+                    sqlx::query(&format!(
+                        "DELETE FROM main.{table} WHERE {key} IN ({conflicting})"
+                    ))
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                OnConflict::Skip => (),
+            }
+        }
+
+        let present = sqlx::query(&format!(
+            "SELECT count(*) FROM source.{table} WHERE {key} IN (SELECT {key} FROM main.{table})"
+        ))
+        .fetch_one(&mut *tx)
+        .await?
+        .get::<i64, _>(0) as u64;
+
+        let inserted = sqlx::query(&format!(
+            "INSERT INTO main.{table} ({columns}) SELECT {columns} FROM source.{table}
+            WHERE {key} NOT IN (SELECT {key} FROM main.{table})"
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!("Could not merge {} into {:?}: {}", table, target, e))?
+        .rows_affected();
+
+        let overwritten = match on_conflict {
+            OnConflict::Overwrite => conflicts.len() as u64,
+            _ => 0,
+        };
+        let skipped = conflicts.len() as u64 - overwritten;
+        tracing::info!(
+            "Merged {}: {} inserted, {} overwritten, {} skipped",
+            table,
+            inserted - overwritten,
+            overwritten,
+            skipped
+        );
+        merged.push(MergedTable {
+            table,
+            inserted: inserted - overwritten,
+            identical: present - skipped,
+            on_conflict,
+            conflicts,
+        });
+    }
+    tx.commit().await?;
+
+    sqlx::query("DETACH DATABASE source").execute(&pool).await?;
+    pool.close().await;
+
+    Ok(merged)
+}
+
+// Columns are named so the order they were added in on either side does not
+// matter
+async fn columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<String>> {
+    let columns: Vec<String> = sqlx::query(&format!("PRAGMA source.table_info({})", table))
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get::<String, _>("name"))
+        .collect();
+
+    if columns.is_empty() {
+        return Err(anyhow!("Migrated database has no table {}", table));
+    }
+
+    Ok(columns)
+}
+
+fn listed(keys: &[String]) -> String {
+    let mut listed = keys
+        .iter()
+        .take(MAX_CONFLICTS_SHOWN)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if keys.len() > MAX_CONFLICTS_SHOWN {
+        listed.push_str(&format!(" and {} more", keys.len() - MAX_CONFLICTS_SHOWN));
+    }
+
+    listed
+}
+
+/// Prints how the tables were merged and, if there were conflicts, writes
+/// every conflicting key next to the target
+pub fn report_merge(
+    target: &Path,
+    merged: &[MergedTable],
+    progress: &dyn ProgressObserver,
+) -> Result<()> {
+    println!("\nMerged into {:?}:", target);
+    println!(
+        "{:<20} {:>10} {:>10} {:>10}  resolution",
+        "table", "inserted", "identical", "conflicts"
+    );
+    for table in merged {
+        println!(
+            "{:<20} {:>10} {:>10} {:>10}  {}",
+            table.table,
+            table.inserted,
+            table.identical,
+            table.conflicts.len(),
+            table.on_conflict
+        );
+    }
+
+    let conflicts: Vec<String> = merged
+        .iter()
+        .flat_map(|table| {
+            table
+                .conflicts
+                .iter()
+                .map(move |key| format!("{} {}", table.table, key))
+        })
+        .collect();
+    let path = with_suffix(target, ".merge.json");
+    if conflicts.is_empty() {
+        // A report left by an earlier merge would no longer be true
+        let _ = std::fs::remove_file(&path);
+        return Ok(());
+    }
+
+    progress.warning(
+        WarningCode::MergeConflict,
+        &format!("Conflicting rows: {}", listed(&conflicts)),
+    );
+    say!(
+        "⚠️  [{}] Conflicting rows: {}",
+        WarningCode::MergeConflict,
+        listed(&conflicts)
+    );
+    std::fs::write(&path, serde_json::to_string_pretty(merged)?)
+        .map_err(|e| anyhow!("Could not write merge report {:?}: {}", path, e))?;
+    say!("📋 Merge report written to {:?}", path);
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use cdk_common::bitcoin::hashes::{Hash, HashEngine, sha256};
+use sqlx::SqlitePool;
+
+use crate::sqlite;
+
+pub const META_TABLE: &str = "_migration_meta";
+
+// Tables whose row counts are recorded, the same ones verification compares
+const COUNTED_TABLES: &[&str] = &[
+    "keyset",
+    "proof",
+    "blind_signature",
+    "mint_quote",
+    "melt_quote",
+    "melt_request",
+    "config",
+];
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut engine = sha256::HashEngine::default();
+    let mut buf = vec![0u8; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        engine.input(&buf[..read]);
+    }
+
+    Ok(sha256::Hash::from_engine(engine).to_string())
+}
+
+/// Records how the database was produced, verification starts out as pending
+pub async fn write_meta(
+    sql_db_path: &Path,
+    redb_path: &Path,
+    source_sha256: &str,
+    started_at: u64,
+) -> Result<()> {
+    let pool = sqlite::connect(sql_db_path).await?;
+
+    sqlx::query(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+        META_TABLE
+    ))
+    .execute(&pool)
+    .await?;
+
+    let entries = [
+        ("tool_version", env!("CARGO_PKG_VERSION").to_string()),
+        ("source_path", redb_path.to_string_lossy().to_string()),
+        ("source_sha256", source_sha256.to_string()),
+        ("started_at", started_at.to_string()),
+        ("loaded_at", unix_time().to_string()),
+        ("verification", "pending".to_string()),
+    ];
+
+    for (key, value) in entries {
+        set(&pool, key, &value).await?;
+    }
+
+    for table in COUNTED_TABLES {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&pool)
+            .await?;
+        set(&pool, &format!("row_count.{}", table), &count.to_string()).await?;
+    }
+
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Verification status of an earlier run, `None` if it never got as far as writing metadata
+pub async fn read_verification(sql_db_path: &Path) -> Result<Option<String>> {
+    let pool = sqlite::connect(sql_db_path).await?;
+
+    let has_meta: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(META_TABLE)
+            .fetch_optional(&pool)
+            .await?;
+
+    let status = match has_meta {
+        Some(_) => {
+            sqlx::query_scalar(&format!(
+                "SELECT value FROM {} WHERE key = 'verification'",
+                META_TABLE
+            ))
+            .fetch_optional(&pool)
+            .await?
+        }
+        None => None,
+    };
+
+    pool.close().await;
+
+    Ok(status)
+}
+
+/// Every entry of the metadata table, empty if the database has none
+pub async fn read_meta(sql_db_path: &Path) -> Result<Vec<(String, String)>> {
+    let pool = sqlite::connect(sql_db_path).await?;
+
+    let has_meta: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(META_TABLE)
+            .fetch_optional(&pool)
+            .await?;
+
+    let entries = match has_meta {
+        Some(_) => {
+            sqlx::query_as(&format!(
+                "SELECT key, value FROM {} ORDER BY key",
+                META_TABLE
+            ))
+            .fetch_all(&pool)
+            .await?
+        }
+        None => vec![],
+    };
+
+    pool.close().await;
+
+    Ok(entries)
+}
+
+pub async fn set_verification(sql_db_path: &Path, status: &str) -> Result<()> {
+    let pool = sqlite::connect(sql_db_path).await?;
+
+    set(&pool, "verification", status).await?;
+    set(&pool, "verified_at", &unix_time().to_string()).await?;
+
+    pool.close().await;
+
+    Ok(())
+}
+
+pub async fn set_value(sql_db_path: &Path, key: &str, value: &str) -> Result<()> {
+    let pool = sqlite::connect(sql_db_path).await?;
+
+    sqlx::query(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+        META_TABLE
+    ))
+    .execute(&pool)
+    .await?;
+    set(&pool, key, value).await?;
+
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Removes the entries whose key starts with `prefix`
+pub async fn remove_prefix(sql_db_path: &Path, prefix: &str) -> Result<()> {
+    if read_meta(sql_db_path).await?.is_empty() {
+        return Ok(());
+    }
+
+    let pool = sqlite::connect(sql_db_path).await?;
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE instr(key, ?) = 1",
+        META_TABLE
+    ))
+    .bind(prefix)
+    .execute(&pool)
+    .await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+async fn set(pool: &SqlitePool, key: &str, value: &str) -> Result<()> {
+    sqlx::query(&format!(
+        "INSERT OR REPLACE INTO {} (key, value) VALUES (?, ?)",
+        META_TABLE
+    ))
+    .bind(key)
+    .bind(value)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
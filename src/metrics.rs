@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+
+use crate::meta::unix_time;
+use crate::progress::{Phase, ProgressObserver};
+use crate::stats::ProofStates;
+
+// `--metrics-file` writes the result of a run in the text format of
+// node_exporter's textfile collector, so it shows up on the dashboards of the
+// host without a pushgateway. The file is written once the run ended, to a
+// temporary file next to it that is renamed over it, so the collector never
+// reads half of it.
+
+const PREFIX: &str = "cdk_redb_migration";
+
+#[derive(Default)]
+struct Recorded {
+    records: BTreeMap<String, u64>,
+    phase_durations: BTreeMap<String, Duration>,
+    // Phase running since when
+    current: Option<(String, Instant)>,
+    // Count and amount of the migrated proofs per state
+    proofs: BTreeMap<String, (u64, u64)>,
+}
+
+impl Recorded {
+    fn end_phase(&mut self) {
+        if let Some((phase, started)) = self.current.take() {
+            *self.phase_durations.entry(phase).or_default() += started.elapsed();
+        }
+    }
+}
+
+/// Collects the record counts and phase durations of a migration
+#[derive(Default)]
+pub struct MetricsRecorder {
+    recorded: Mutex<Recorded>,
+}
+
+impl MetricsRecorder {
+    fn recorded(&self) -> std::sync::MutexGuard<'_, Recorded> {
+        self.recorded.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the proofs the migration carried over
+    pub fn proofs(&self, proof_states: &ProofStates) {
+        let mut recorded = self.recorded();
+        for (state, bucket) in proof_states.values().flatten() {
+            let (count, amount) = recorded.proofs.entry(state.clone()).or_default();
+            *count += bucket.count;
+            *amount += bucket.amount;
+        }
+    }
+}
+
+impl ProgressObserver for MetricsRecorder {
+    fn phase_started(&self, phase: Phase) {
+        let mut recorded = self.recorded();
+        recorded.end_phase();
+        recorded.current = Some((label(phase), Instant::now()));
+    }
+
+    fn records(&self, phase: Phase, done: u64, _total: Option<u64>) {
+        self.recorded().records.insert(label(phase), done);
+    }
+}
+
+fn label(phase: Phase) -> String {
+    phase.to_string().replace(' ', "_")
+}
+
+static RECORDER: OnceLock<Arc<MetricsRecorder>> = OnceLock::new();
+
+/// Observer to hand to a migration when metrics are written
+pub fn recorder() -> Option<Arc<MetricsRecorder>> {
+    RECORDER.get().cloned()
+}
+
+pub struct MetricsFile {
+    path: PathBuf,
+    command: String,
+    started: Instant,
+}
+
+pub fn start(path: &Path, command: &str) -> Result<MetricsFile> {
+    if path.extension().is_none_or(|extension| extension != "prom") {
+        return Err(anyhow!(
+            "{:?} does not end in .prom, the textfile collector would not read it",
+            path
+        ));
+    }
+
+    let _ = RECORDER.set(Arc::default());
+
+    Ok(MetricsFile {
+        path: path.to_path_buf(),
+        command: command.to_string(),
+        started: Instant::now(),
+    })
+}
+
+impl MetricsFile {
+    /// Writes the metrics of a run that ended as `success` says
+    pub fn finish(self, success: bool) {
+        if let Err(err) = self.write(success) {
+            tracing::warn!("Could not write the metrics to {:?}: {}", self.path, err);
+        }
+    }
+
+    fn write(&self, success: bool) -> Result<()> {
+        let mut recorded = recorder()
+            .map(|recorder| std::mem::take(&mut *recorder.recorded()))
+            .unwrap_or_default();
+        recorded.end_phase();
+
+        let command = format!("command=\"{}\"", self.command);
+        let mut text = String::new();
+        metric(
+            &mut text,
+            "success",
+            "Whether the last run succeeded",
+            [(command.clone(), u8::from(success).to_string())],
+        );
+        metric(
+            &mut text,
+            "duration_seconds",
+            "How long the last run took",
+            [(
+                command.clone(),
+                self.started.elapsed().as_secs_f64().to_string(),
+            )],
+        );
+        metric(
+            &mut text,
+            "last_run_timestamp_seconds",
+            "When the last run ended",
+            [(command, unix_time().to_string())],
+        );
+        metric(
+            &mut text,
+            "records",
+            "Records written per phase of the migration",
+            recorded
+                .records
+                .iter()
+                .map(|(phase, count)| (format!("phase=\"{}\"", phase), count.to_string())),
+        );
+        metric(
+            &mut text,
+            "phase_duration_seconds",
+            "Time spent in each phase of the migration",
+            recorded.phase_durations.iter().map(|(phase, duration)| {
+                (
+                    format!("phase=\"{}\"", phase),
+                    duration.as_secs_f64().to_string(),
+                )
+            }),
+        );
+        metric(
+            &mut text,
+            "proofs",
+            "Proofs migrated per state",
+            recorded
+                .proofs
+                .iter()
+                .map(|(state, (count, _))| (format!("state=\"{}\"", state), count.to_string())),
+        );
+        metric(
+            &mut text,
+            "proofs_amount",
+            "Amount of the proofs migrated per state, summed over all keysets",
+            recorded
+                .proofs
+                .iter()
+                .map(|(state, (_, amount))| (format!("state=\"{}\"", state), amount.to_string())),
+        );
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, text)?;
+        std::fs::rename(&temporary, &self.path)?;
+        tracing::info!("Wrote the metrics of the run to {:?}", self.path);
+
+        Ok(())
+    }
+}
+
+/// Appends a gauge with one sample per `(labels, value)`, or nothing if there
+/// are none
+fn metric(
+    text: &mut String,
+    name: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (String, String)>,
+) {
+    let mut samples = samples.into_iter().peekable();
+    if samples.peek().is_none() {
+        return;
+    }
+
+    let _ = writeln!(text, "# HELP {}_{} {}", PREFIX, name, help);
+    let _ = writeln!(text, "# TYPE {}_{} gauge", PREFIX, name);
+    for (labels, value) in samples {
+        let _ = writeln!(text, "{}_{}{{{}}} {}", PREFIX, name, labels, value);
+    }
+}
@@ -0,0 +1,287 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use cdk_common::PublicKey;
+use cdk_common::database::{MintDatabase, MintKeysDatabase};
+use cdk_common::nuts::{CheckStateRequest, CheckStateResponse, KeysetResponse, State};
+use cdk_sqlite::MintSqliteDatabase;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::output::say;
+use crate::raw::RedbReader;
+
+// `verify --mint-url` asks the mint that was switched over to the SQLite
+// database what it serves, and checks that against the migrated data: every
+// keyset with its unit, state and fee, and the mint info. A mint that answers
+// with other keysets or another pubkey runs on some other database. Both
+// the mint's public https URL and mintd's plain http listen address on the
+// host itself work. With --check-states a sample of proofs is also looked up
+// with NUT-07 checkstate, and has to be in the state the redb held.
+
+// Ys per checkstate request, well below what mints accept
+const CHECKSTATE_BATCH: usize = 100;
+
+// A mint that accepts the connection but never answers fails the check
+// instead of hanging it
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Set by mintd when it answers, never stored
+const VOLATILE_INFO: &[&str] = &["time"];
+
+pub async fn cross_check(mint_url: &str, sql_db_path: &Path) -> Result<()> {
+    say!("\n=== Comparing with the mint at {} ===", mint_url);
+    let sqlite_db = MintSqliteDatabase::new(sql_db_path).await?;
+    let mut problems = 0;
+
+    say!("📋 Checking the keysets the mint serves...");
+    let served: KeysetResponse = get(mint_url, "/v1/keysets").await?;
+    let stored = sqlite_db.get_keyset_infos().await?;
+    for keyset in &served.keysets {
+        let Some(stored) = stored.iter().find(|stored| stored.id == keyset.id) else {
+            problems += 1;
+            say!(
+                "❌ Keyset {} is served but not in the migrated database",
+                keyset.id
+            );
+            continue;
+        };
+        for (field, served, stored) in [
+            ("unit", keyset.unit.to_string(), stored.unit.to_string()),
+            (
+                "active",
+                keyset.active.to_string(),
+                stored.active.to_string(),
+            ),
+            (
+                "input_fee_ppk",
+                keyset.input_fee_ppk.to_string(),
+                stored.input_fee_ppk.to_string(),
+            ),
+        ] {
+            if served != stored {
+                problems += 1;
+                say!(
+                    "❌ Keyset {} is served with {} {}, migrated with {}",
+                    keyset.id,
+                    field,
+                    served,
+                    stored
+                );
+            }
+        }
+    }
+    for keyset in &stored {
+        if !served.keysets.iter().any(|served| served.id == keyset.id) {
+            problems += 1;
+            say!(
+                "❌ Keyset {} was migrated but is not served by the mint",
+                keyset.id
+            );
+        }
+    }
+    if problems == 0 {
+        say!(
+            "✅ The mint serves the {} migrated keysets",
+            served.keysets.len()
+        );
+    }
+
+    say!("📋 Checking the mint info...");
+    let served: Value = get(mint_url, "/v1/info").await?;
+    let stored = serde_json::to_value(sqlite_db.get_mint_info().await?)?;
+    if served.get("pubkey") != stored.get("pubkey") {
+        problems += 1;
+        say!(
+            "❌ The mint serves pubkey {}, the migrated mint info has {}",
+            describe(served.get("pubkey")),
+            describe(stored.get("pubkey"))
+        );
+    }
+    // mintd stores its configured info on startup, so other differences
+    // are left to the operator to judge
+    let mut fields: Vec<&String> = served
+        .as_object()
+        .into_iter()
+        .chain(stored.as_object())
+        .flat_map(|object| object.keys())
+        .collect();
+    fields.sort();
+    fields.dedup();
+    let mut differing = 0;
+    for field in fields {
+        if field == "pubkey" || VOLATILE_INFO.contains(&field.as_str()) {
+            continue;
+        }
+        if served.get(field) != stored.get(field) {
+            differing += 1;
+            say!(
+                "⚠️  Mint info {} is served as {}, migrated as {}",
+                field,
+                describe(served.get(field)),
+                describe(stored.get(field))
+            );
+        }
+    }
+    if differing == 0 && problems == 0 {
+        say!("✅ The mint serves the migrated mint info");
+    }
+
+    if problems > 0 {
+        return Err(anyhow!(
+            "The mint at {} does not serve the migrated data, {} problem(s) found",
+            mint_url,
+            problems
+        ));
+    }
+    say!("✅ The mint at {} serves the migrated data", mint_url);
+
+    Ok(())
+}
+
+/// Asks the mint for the state of `sample` proofs of the redb, spread over
+/// all of them, and compares it with the state the redb held
+pub async fn check_states(mint_url: &str, redb_path: &Path, sample: usize) -> Result<()> {
+    say!("📋 Checking proof states with the mint...");
+    // Read without cdk-redb, which may upgrade the source in place, and the
+    // source has to keep the checksum cleanup compares it with
+    let mut held: Vec<(PublicKey, State)> = RedbReader::open(redb_path)?
+        .proofs_with_states()?
+        .into_iter()
+        .map(|(y, _, state)| (y, state))
+        .collect();
+    if held.is_empty() {
+        say!("✅ The redb holds no proofs to check");
+        return Ok(());
+    }
+
+    // Ys are hashes, so every n-th in their order is a sample across
+    // keysets and states
+    held.sort_by_key(|(y, _)| y.to_bytes());
+    let step = (held.len() / sample.max(1)).max(1);
+    let sampled: Vec<(PublicKey, State)> = held.into_iter().step_by(step).take(sample).collect();
+
+    let mut mismatches = vec![];
+    let mut moved_on = 0;
+    for batch in sampled.chunks(CHECKSTATE_BATCH) {
+        let response: CheckStateResponse = post(
+            mint_url,
+            "/v1/checkstate",
+            &CheckStateRequest {
+                ys: batch.iter().map(|(y, _)| *y).collect(),
+            },
+        )
+        .await?;
+        for (y, held) in batch {
+            let served = response
+                .states
+                .iter()
+                .find(|served| served.y == *y)
+                .map(|served| served.state);
+            // The mint keeps spending after the switch, and pending proofs of
+            // a failed melt are released again, but a spent proof never
+            // becomes spendable
+            if served.is_some() && served != Some(*held) && *held != State::Spent {
+                moved_on += 1;
+            } else if served != Some(*held) {
+                mismatches.push(format!(
+                    "{}: {} in the redb, {} at the mint",
+                    y,
+                    held,
+                    served.map_or("no state".to_string(), |state| state.to_string())
+                ));
+            }
+        }
+    }
+
+    if !mismatches.is_empty() {
+        for mismatch in mismatches.iter().take(10) {
+            say!("❌ Proof {}", mismatch);
+        }
+        return Err(anyhow!(
+            "The mint at {} reports another state for {} of {} sampled proof(s)",
+            mint_url,
+            mismatches.len(),
+            sampled.len()
+        ));
+    }
+    say!(
+        "✅ The mint reports the redb's state for {} of {} sampled proof(s), {} changed state since the switch",
+        sampled.len() - moved_on,
+        sampled.len(),
+        moved_on
+    );
+
+    Ok(())
+}
+
+fn describe(value: Option<&Value>) -> String {
+    value.map_or("nothing".to_string(), Value::to_string)
+}
+
+/// Fetches and parses a JSON endpoint of the mint
+async fn get<T: DeserializeOwned>(mint_url: &str, endpoint: &str) -> Result<T> {
+    let client = client()?;
+    request(mint_url, endpoint, client.get(url(mint_url, endpoint))).await
+}
+
+/// Posts `body` as JSON to an endpoint of the mint and parses the answer
+async fn post<T: DeserializeOwned>(
+    mint_url: &str,
+    endpoint: &str,
+    body: &impl Serialize,
+) -> Result<T> {
+    let client = client()?;
+    request(
+        mint_url,
+        endpoint,
+        client.post(url(mint_url, endpoint)).json(body),
+    )
+    .await
+}
+
+fn client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .connect_timeout(TIMEOUT)
+        .timeout(TIMEOUT)
+        .build()?)
+}
+
+fn url(mint_url: &str, endpoint: &str) -> String {
+    format!("{}{}", mint_url.trim_end_matches('/'), endpoint)
+}
+
+async fn request<T: DeserializeOwned>(
+    mint_url: &str,
+    endpoint: &str,
+    request: reqwest::RequestBuilder,
+) -> Result<T> {
+    let response = request
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await
+        .map_err(|e| match e.is_timeout() {
+            true => anyhow!(
+                "The mint at {} did not answer {} in time",
+                mint_url,
+                endpoint
+            ),
+            false => {
+                anyhow::Error::new(e).context(format!("Could not reach the mint at {}", mint_url))
+            }
+        })?;
+    let url = response.url().clone();
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("{} answered {}", url, status));
+    }
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| anyhow!("Reading the answer of {} failed: {}", url, e))?;
+    serde_json::from_slice(&body)
+        .map_err(|e| anyhow!("{} answered with unexpected JSON: {}", url, e))
+}
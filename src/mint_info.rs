@@ -0,0 +1,79 @@
+use anyhow::Result;
+use cdk_common::nuts::MintInfo;
+use serde_json::Value;
+
+// The NUT settings in the mint info are lists of methods, commands and
+// endpoints whose order carries no meaning, and cdk versions differ in how
+// they serialize some of them, e.g. a bolt11 method without description
+// support is written with `"options": {"description": false}` by some and
+// without options by others. The mint info is migrated with its NUT settings
+// normalized, sorted and round-tripped through the current serialization,
+// and verification compares the normalized settings, so only differences
+// that change what the mint supports are reported.
+
+/// The mint info as it is written to SQLite
+pub fn normalize_mint_info(mint_info: &MintInfo) -> Result<MintInfo> {
+    Ok(serde_json::from_value(normalized_value(mint_info)?)?)
+}
+
+/// Fields in which the normalized mint infos differ, e.g. `nuts.4.methods`
+pub fn mint_info_differences(a: &MintInfo, b: &MintInfo) -> Result<Vec<String>> {
+    let mut differences = vec![];
+    diff(
+        "",
+        &normalized_value(a)?,
+        &normalized_value(b)?,
+        &mut differences,
+    );
+
+    Ok(differences)
+}
+
+fn normalized_value(mint_info: &MintInfo) -> Result<Value> {
+    let mut value = serde_json::to_value(mint_info)?;
+    if let Some(nuts) = value.get_mut("nuts") {
+        sort_lists(nuts);
+    }
+
+    Ok(value)
+}
+
+/// Sorts every list below `value` and drops repeated entries
+fn sort_lists(value: &mut Value) {
+    match value {
+        Value::Array(items) => {
+            items.iter_mut().for_each(sort_lists);
+            items.sort_by_key(Value::to_string);
+            items.dedup();
+        }
+        Value::Object(fields) => fields.values_mut().for_each(sort_lists),
+        _ => (),
+    }
+}
+
+fn diff(path: &str, a: &Value, b: &Value, differences: &mut Vec<String>) {
+    if a == b {
+        return;
+    }
+
+    match (a, b) {
+        (Value::Object(a_fields), Value::Object(b_fields)) => {
+            let mut keys: Vec<&String> = a_fields.keys().chain(b_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = match path {
+                    "" => key.to_string(),
+                    _ => format!("{}.{}", path, key),
+                };
+                diff(
+                    &path,
+                    a_fields.get(key).unwrap_or(&Value::Null),
+                    b_fields.get(key).unwrap_or(&Value::Null),
+                    differences,
+                );
+            }
+        }
+        _ => differences.push(path.to_string()),
+    }
+}
@@ -0,0 +1,80 @@
+// `--nice` lowers the scheduling priority of the whole run for background
+// migrations on a host shared with mintd: the lowest CPU priority on every
+// unix, and on Linux also the idle I/O class, which only gets the disk when
+// nothing else asks for it. Linux keeps both per thread, so every thread
+// running so far is lowered and the ones started later inherit it.
+
+// Values from linux/ioprio.h
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+// Lowest priority nice accepts
+#[cfg(unix)]
+const NICE_LOWEST: libc::c_int = 19;
+
+#[cfg(target_os = "linux")]
+pub fn lower_priority() {
+    let threads = match std::fs::read_dir("/proc/self/task") {
+        Ok(threads) => threads,
+        Err(err) => {
+            tracing::warn!("Cannot list the threads to lower their priority: {}", err);
+            return;
+        }
+    };
+
+    let mut lowered = 0;
+    for thread in threads.flatten() {
+        let Some(tid) = thread.file_name().to_str().and_then(|tid| tid.parse().ok()) else {
+            continue;
+        };
+
+        // Both calls only read their plain integer arguments
+        let cpu = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, NICE_LOWEST) };
+        let io = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                tid,
+                IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+            )
+        };
+        if cpu != 0 || io != 0 {
+            tracing::warn!(
+                "Could not lower the priority of thread {}: {}",
+                tid,
+                std::io::Error::last_os_error()
+            );
+        } else {
+            lowered += 1;
+        }
+    }
+
+    tracing::info!(
+        "Running {} thread(s) at the lowest CPU priority and in the idle I/O class",
+        lowered
+    );
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn lower_priority() {
+    // Outside Linux the nice value belongs to the process, there is no I/O
+    // priority to set
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, NICE_LOWEST) } != 0 {
+        tracing::warn!(
+            "Could not lower the CPU priority: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+
+    tracing::info!("Running at the lowest CPU priority, I/O priority is not supported here");
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority() {
+    tracing::warn!("--nice is not supported on this platform, running at normal priority");
+}
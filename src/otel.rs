@@ -0,0 +1,60 @@
+use anyhow::{Result, anyhow};
+use opentelemetry::KeyValue;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::TracerProvider;
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+// With --otlp-endpoint the spans of a run, one per migration with one per
+// phase and per batch below it, are sent to an OpenTelemetry collector next
+// to the log output.
+
+/// Keeps the export running, dropping it sends the spans still queued
+pub struct OtlpExport {
+    provider: TracerProvider,
+}
+
+impl Drop for OtlpExport {
+    fn drop(&mut self) {
+        // The batch exporter runs on the tokio runtime, which has to keep
+        // going while this thread waits for it
+        let shutdown = || self.provider.shutdown();
+        let result = match tokio::runtime::Handle::try_current() {
+            Ok(_) => tokio::task::block_in_place(shutdown),
+            Err(_) => shutdown(),
+        };
+
+        if let Err(err) = result {
+            eprintln!("Could not send the remaining spans: {}", err);
+        }
+    }
+}
+
+pub fn layer<S>(endpoint: &str) -> Result<(OtlpExport, impl Layer<S>)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| anyhow!("Could not export spans to {}: {}", endpoint, e))?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, Tokio)
+        .with_resource(Resource::new([
+            KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+    Ok((
+        OtlpExport { provider },
+        tracing_opentelemetry::layer().with_tracer(tracer),
+    ))
+}
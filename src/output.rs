@@ -0,0 +1,108 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The console output is decorated with emoji for interactive terminals. In
+// plain mode they are swapped for ASCII so logs captured by cron or CI stay
+// readable.
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+// Set when another program runs the migration, e.g. mintd through
+// migrate_if_needed. Its stdout is not ours to write to, so the lines are
+// logged instead.
+static LOG_ONLY: AtomicBool = AtomicBool::new(false);
+
+// Warning lines printed so far, kept for the --json digest once recording
+static WARNINGS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+// Status emoji keep their meaning as a tag, the decorative ones are dropped
+const REPLACEMENTS: &[(&str, &str)] = &[
+    ("✅", "[OK]"),
+    ("✓", "[OK]"),
+    ("❌", "[FAIL]"),
+    ("⚠️", "[WARN]"),
+    ("🎉", ""),
+    ("📋", ""),
+    ("📝", ""),
+    ("🔍", ""),
+    ("🔎", ""),
+    ("🔑", ""),
+    ("🔧", ""),
+    ("🔒", ""),
+    ("🔓", ""),
+    ("🕒", ""),
+    ("⏳", ""),
+    ("📡", ""),
+    ("🛰️", ""),
+    ("🧪", ""),
+    ("🧹", ""),
+    ("🩺", ""),
+];
+
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+pub fn set_log_only() {
+    LOG_ONLY.store(true, Ordering::Relaxed);
+}
+
+pub fn record_warnings() {
+    *WARNINGS.lock().unwrap_or_else(|e| e.into_inner()) = Some(vec![]);
+}
+
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default()
+}
+
+/// The line as it should be printed in the current output mode
+pub fn render(line: String) -> String {
+    if let Some(warnings) = WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).as_mut()
+        && let Some((_, warning)) = line.split_once("⚠️")
+    {
+        warnings.push(warning.trim().to_string());
+    }
+
+    if !PLAIN.load(Ordering::Relaxed) && !LOG_ONLY.load(Ordering::Relaxed) {
+        return line;
+    }
+
+    let mut line = line;
+    for (emoji, replacement) in REPLACEMENTS {
+        if replacement.is_empty() {
+            // Take the padding after a dropped emoji along with it
+            line = line
+                .replace(&format!("{}  ", emoji), "")
+                .replace(&format!("{} ", emoji), "")
+                .replace(emoji, "");
+        } else {
+            line = line.replace(emoji, replacement);
+        }
+    }
+
+    line
+}
+
+/// Prints a rendered line, or logs it when the stdout is not ours
+pub fn emit(line: String) {
+    if !LOG_ONLY.load(Ordering::Relaxed) {
+        println!("{}", line);
+        return;
+    }
+
+    for line in line.lines().filter(|line| !line.trim().is_empty()) {
+        tracing::info!("{}", line.trim_end());
+    }
+}
+
+/// `println!` for console output, which may be decorated with emoji
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::output::emit($crate::output::render(format!($($arg)*)))
+    };
+}
+
+pub(crate) use say;
@@ -0,0 +1,459 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use cdk_common::util::hex;
+use futures::TryStreamExt;
+use serde_json::{Map, Value};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, Sqlite, SqlitePool, Transaction, TypeInfo, ValueRef};
+
+use crate::cli::OutputFormat;
+use crate::output::say;
+use crate::paths::with_suffix;
+use crate::sqlite::{self, quote_identifier};
+
+// A migration always builds and verifies a SQLite database, which is then
+// written out in the format the operator's infrastructure takes. The tables
+// of the verified database are read once and fed row by row to an
+// [`OutputSink`]: a fresh SQLite file, a SQL dump like sqlite3's `.dump`,
+// COPY blocks for psql, or JSON. Each output is written next to its final
+// path and renamed into place once complete.
+
+/// A value as SQLite stored it
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// Receives the tables of a database, each followed by its rows
+pub trait OutputSink {
+    /// Starts a table with its column names and the statement creating it
+    async fn begin_table(&mut self, name: &str, columns: &[String], sql: &str) -> Result<()>;
+
+    async fn row(&mut self, values: &[SqlValue]) -> Result<()>;
+
+    async fn end_table(&mut self) -> Result<()>;
+
+    /// Completes the output, `schema` holds the statements creating the
+    /// indexes, triggers and views that follow the rows
+    async fn finish(self, schema: &[String]) -> Result<()>;
+}
+
+impl OutputFormat {
+    /// Extension of the file written next to a migrated database
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Sql => "sql",
+            OutputFormat::Postgres => "pgcopy.sql",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// Writes the SQLite database at `sql_db_path` to `output` in `format`
+pub async fn write_output(sql_db_path: &Path, output: &Path, format: OutputFormat) -> Result<()> {
+    let partial = with_suffix(output, ".partial");
+    if partial.exists() {
+        std::fs::remove_file(&partial)?;
+    }
+
+    let written = match format {
+        OutputFormat::Sqlite => copy_database(sql_db_path, SqliteSink::new(&partial).await?).await,
+        OutputFormat::Sql => {
+            copy_database(sql_db_path, SqlDumpSink::new(&partial, sql_db_path)?).await
+        }
+        OutputFormat::Postgres => {
+            copy_database(sql_db_path, PostgresCopySink::new(&partial, sql_db_path)?).await
+        }
+        OutputFormat::Json => copy_database(sql_db_path, JsonSink::new(&partial)?).await,
+    };
+    let (tables, rows) = match written {
+        Ok(counts) => counts,
+        Err(err) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(err);
+        }
+    };
+    File::open(&partial)?.sync_all()?;
+    std::fs::rename(&partial, output)?;
+
+    say!(
+        "✅ Wrote {} rows of {} tables from {:?} to {:?}",
+        rows,
+        tables,
+        sql_db_path,
+        output
+    );
+
+    Ok(())
+}
+
+/// Feeds every table of the database to `sink`, returning the number of
+/// tables and rows
+async fn copy_database(sql_db_path: &Path, mut sink: impl OutputSink) -> Result<(u64, u64)> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let schema: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT type, name, sql FROM sqlite_master WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' ORDER BY rowid",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut tables = 0;
+    let mut rows = 0;
+    for (_, name, sql) in schema.iter().filter(|(kind, ..)| kind == "table") {
+        let table = quote_identifier(name);
+        let columns: Vec<String> = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&pool)
+            .await?
+            .iter()
+            .map(|column| column.get("name"))
+            .collect();
+        sink.begin_table(name, &columns, sql).await?;
+        tables += 1;
+
+        let select = format!("SELECT * FROM {}", table);
+        let mut stream = sqlx::query(&select).fetch(&pool);
+        while let Some(row) = stream.try_next().await? {
+            let values = sql_values(&row)
+                .map_err(|e| anyhow!("Could not read a row of table {}: {}", name, e))?;
+            sink.row(&values).await?;
+            rows += 1;
+        }
+        sink.end_table().await?;
+    }
+    let rest: Vec<String> = schema
+        .into_iter()
+        .filter(|(kind, ..)| kind != "table")
+        .map(|(_, _, sql)| sql)
+        .collect();
+    pool.close().await;
+    sink.finish(&rest).await?;
+
+    Ok((tables, rows))
+}
+
+fn sql_values(row: &SqliteRow) -> Result<Vec<SqlValue>> {
+    let mut values = vec![];
+
+    for ordinal in 0..row.len() {
+        let raw = row.try_get_raw(ordinal)?;
+        let value = if raw.is_null() {
+            SqlValue::Null
+        } else {
+            match raw.type_info().name() {
+                "INTEGER" | "BOOLEAN" => SqlValue::Integer(row.try_get(ordinal)?),
+                "REAL" => SqlValue::Real(row.try_get(ordinal)?),
+                "BLOB" => SqlValue::Blob(row.try_get(ordinal)?),
+                _ => SqlValue::Text(row.try_get(ordinal)?),
+            }
+        };
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path).map_err(|e| {
+        anyhow!("Could not create {:?}: {}", path, e)
+    })?))
+}
+
+/// A new SQLite database with the same schema, e.g. to rebuild a database
+/// with all its pages written afresh
+pub struct SqliteSink {
+    pool: SqlitePool,
+    tx: Transaction<'static, Sqlite>,
+    insert: String,
+}
+
+impl SqliteSink {
+    async fn new(path: &Path) -> Result<Self> {
+        // No WAL, so the file is complete on its own once committed
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Delete);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        let tx = pool.begin().await?;
+
+        Ok(Self {
+            pool,
+            tx,
+            insert: String::new(),
+        })
+    }
+}
+
+impl OutputSink for SqliteSink {
+    async fn begin_table(&mut self, name: &str, columns: &[String], sql: &str) -> Result<()> {
+        sqlx::query(sql).execute(&mut *self.tx).await?;
+        self.insert = format!(
+            "INSERT INTO {} VALUES({})",
+            quote_identifier(name),
+            vec!["?"; columns.len()].join(",")
+        );
+        Ok(())
+    }
+
+    async fn row(&mut self, values: &[SqlValue]) -> Result<()> {
+        let mut query = sqlx::query(&self.insert);
+        for value in values {
+            query = match value {
+                SqlValue::Null => query.bind(None::<i64>),
+                SqlValue::Integer(value) => query.bind(*value),
+                SqlValue::Real(value) => query.bind(*value),
+                SqlValue::Text(value) => query.bind(value.as_str()),
+                SqlValue::Blob(value) => query.bind(value.as_slice()),
+            };
+        }
+        query.execute(&mut *self.tx).await?;
+        Ok(())
+    }
+
+    async fn end_table(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn finish(mut self, schema: &[String]) -> Result<()> {
+        for sql in schema {
+            sqlx::query(sql).execute(&mut *self.tx).await?;
+        }
+        self.tx.commit().await?;
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+/// INSERT statements after the schema as cdk-sqlite created it, including
+/// its `_sqlx_migrations` table so cdk-sqlite opens the loaded database as is
+pub struct SqlDumpSink {
+    file: BufWriter<File>,
+    table: String,
+}
+
+impl SqlDumpSink {
+    fn new(path: &Path, sql_db_path: &Path) -> Result<Self> {
+        let mut file = create_file(path)?;
+        writeln!(
+            file,
+            "-- {:?} dumped by cdk-convert-redb-to-sqlite {}",
+            sql_db_path,
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(file, "PRAGMA foreign_keys=OFF;")?;
+        writeln!(file, "BEGIN TRANSACTION;")?;
+
+        Ok(Self {
+            file,
+            table: String::new(),
+        })
+    }
+}
+
+impl OutputSink for SqlDumpSink {
+    async fn begin_table(&mut self, name: &str, _columns: &[String], sql: &str) -> Result<()> {
+        writeln!(self.file, "{};", sql)?;
+        self.table = quote_identifier(name);
+        Ok(())
+    }
+
+    async fn row(&mut self, values: &[SqlValue]) -> Result<()> {
+        let values: Vec<String> = values
+            .iter()
+            .map(|value| match value {
+                SqlValue::Null => "NULL".to_string(),
+                SqlValue::Integer(value) => value.to_string(),
+                SqlValue::Real(value) => format!("{:?}", value),
+                SqlValue::Text(value) => format!("'{}'", value.replace('\'', "''")),
+                SqlValue::Blob(value) => format!("X'{}'", hex::encode(value)),
+            })
+            .collect();
+        writeln!(
+            self.file,
+            "INSERT INTO {} VALUES({});",
+            self.table,
+            values.join(",")
+        )?;
+        Ok(())
+    }
+
+    async fn end_table(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn finish(mut self, schema: &[String]) -> Result<()> {
+        // Created after the rows, as sqlite3 does, so loading does not update
+        // them row by row
+        for sql in schema {
+            writeln!(self.file, "{};", sql)?;
+        }
+        writeln!(self.file, "COMMIT;")?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// COPY blocks for psql, to load into a database cdk's postgres backend
+/// created. The SQLite schema does not carry over, so the tables are taken
+/// as they exist there, and the bookkeeping tables of sqlx and this tool are
+/// left out.
+pub struct PostgresCopySink {
+    file: BufWriter<File>,
+    skipping: bool,
+}
+
+impl PostgresCopySink {
+    fn new(path: &Path, sql_db_path: &Path) -> Result<Self> {
+        let mut file = create_file(path)?;
+        writeln!(
+            file,
+            "-- {:?} dumped for PostgreSQL by cdk-convert-redb-to-sqlite {}",
+            sql_db_path,
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(file, "-- Load with psql into a database created by cdk")?;
+        writeln!(file, "BEGIN;")?;
+
+        Ok(Self {
+            file,
+            skipping: false,
+        })
+    }
+}
+
+impl OutputSink for PostgresCopySink {
+    async fn begin_table(&mut self, name: &str, columns: &[String], _sql: &str) -> Result<()> {
+        self.skipping = name.starts_with('_');
+        if self.skipping {
+            return Ok(());
+        }
+
+        let columns: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+        writeln!(
+            self.file,
+            "COPY {} ({}) FROM stdin;",
+            quote_identifier(name),
+            columns.join(", ")
+        )?;
+        Ok(())
+    }
+
+    async fn row(&mut self, values: &[SqlValue]) -> Result<()> {
+        if self.skipping {
+            return Ok(());
+        }
+
+        let values: Vec<String> = values
+            .iter()
+            .map(|value| match value {
+                SqlValue::Null => "\\N".to_string(),
+                // Also read as a boolean, where SQLite stores one as 0 or 1
+                SqlValue::Integer(value) => value.to_string(),
+                SqlValue::Real(value) => format!("{:?}", value),
+                SqlValue::Text(value) => copy_escape(value),
+                // bytea hex input, with its backslash escaped for COPY
+                SqlValue::Blob(value) => format!("\\\\x{}", hex::encode(value)),
+            })
+            .collect();
+        writeln!(self.file, "{}", values.join("\t"))?;
+        Ok(())
+    }
+
+    async fn end_table(&mut self) -> Result<()> {
+        if !self.skipping {
+            writeln!(self.file, "\\.")?;
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self, _schema: &[String]) -> Result<()> {
+        writeln!(self.file, "COMMIT;")?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+fn copy_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// One JSON object holding each table as a list of rows keyed by column,
+/// blobs as hex
+pub struct JsonSink {
+    file: BufWriter<File>,
+    columns: Vec<String>,
+    first_table: bool,
+    first_row: bool,
+}
+
+impl JsonSink {
+    fn new(path: &Path) -> Result<Self> {
+        let mut file = create_file(path)?;
+        write!(file, "{{\"tables\":{{")?;
+
+        Ok(Self {
+            file,
+            columns: vec![],
+            first_table: true,
+            first_row: true,
+        })
+    }
+}
+
+impl OutputSink for JsonSink {
+    async fn begin_table(&mut self, name: &str, columns: &[String], _sql: &str) -> Result<()> {
+        if !self.first_table {
+            write!(self.file, ",")?;
+        }
+        self.first_table = false;
+        self.first_row = true;
+        self.columns = columns.to_vec();
+        write!(self.file, "\n{}:[", Value::from(name))?;
+        Ok(())
+    }
+
+    async fn row(&mut self, values: &[SqlValue]) -> Result<()> {
+        let mut object = Map::new();
+        for (column, value) in self.columns.iter().zip(values) {
+            let value = match value {
+                SqlValue::Null => Value::Null,
+                SqlValue::Integer(value) => Value::from(*value),
+                SqlValue::Real(value) => Value::from(*value),
+                SqlValue::Text(value) => Value::from(value.as_str()),
+                SqlValue::Blob(value) => Value::from(hex::encode(value)),
+            };
+            object.insert(column.clone(), value);
+        }
+
+        if !self.first_row {
+            write!(self.file, ",")?;
+        }
+        self.first_row = false;
+        write!(self.file, "\n{}", Value::Object(object))?;
+        Ok(())
+    }
+
+    async fn end_table(&mut self) -> Result<()> {
+        write!(self.file, "]")?;
+        Ok(())
+    }
+
+    async fn finish(mut self, _schema: &[String]) -> Result<()> {
+        writeln!(self.file, "\n}}}}")?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
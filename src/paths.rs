@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use cdk_common::bitcoin::hashes::{Hash, sha256};
+
+/// Locations of the source and target databases of one migration
+#[derive(Debug, Clone)]
+pub struct MigrationPaths {
+    pub redb: PathBuf,
+    pub sqlite: PathBuf,
+    pub auth_redb: PathBuf,
+    pub auth_sqlite: PathBuf,
+}
+
+impl MigrationPaths {
+    pub fn new(work_dir: &Path) -> Self {
+        Self {
+            redb: work_dir.join("cdk-mintd.redb"),
+            sqlite: work_dir.join("cdk-mintd.sqlite"),
+            auth_redb: work_dir.join("cdk-mintd-auth.redb"),
+            auth_sqlite: work_dir.join("cdk-mintd-auth.sqlite"),
+        }
+    }
+
+    /// Same sources, with the mint database written as `name` next to the
+    /// default one and the auth database named after it
+    pub fn named(&self, name: &str) -> Self {
+        let name = Path::new(name);
+        let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+        let auth_name = match name.extension() {
+            Some(extension) => format!("{}-auth.{}", stem, extension.to_string_lossy()),
+            None => format!("{}-auth", stem),
+        };
+
+        Self {
+            sqlite: self.sqlite.with_file_name(name),
+            auth_sqlite: self.auth_sqlite.with_file_name(auth_name),
+            ..self.clone()
+        }
+    }
+
+    /// Same sources, with the targets at the temporary paths they are built under,
+    /// next to the final ones or in a staging directory under `tmp_dir`
+    pub fn building(&self, tmp_dir: Option<&Path>) -> Self {
+        let staged = |target: &Path| match tmp_dir {
+            Some(tmp_dir) => with_suffix(
+                &staging_dir(tmp_dir, &self.redb).join(target.file_name().unwrap_or_default()),
+                ".tmp",
+            ),
+            None => with_suffix(target, ".tmp"),
+        };
+
+        Self {
+            sqlite: staged(&self.sqlite),
+            auth_sqlite: staged(&self.auth_sqlite),
+            ..self.clone()
+        }
+    }
+}
+
+// Mints migrated in one batch may share a tmp dir, each one stages under a
+// directory named after its source so a resume never picks up another mint
+fn staging_dir(tmp_dir: &Path, redb: &Path) -> PathBuf {
+    let source = std::fs::canonicalize(redb).unwrap_or_else(|_| redb.to_path_buf());
+    let hash = sha256::Hash::hash(source.as_os_str().as_encoded_bytes()).to_string();
+
+    tmp_dir.join(format!("cdk-convert-{}", &hash[..16]))
+}
+
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub fn parse_output_name(name: &str) -> Result<String, String> {
+    let path = Path::new(name);
+    if path.file_name() != Some(path.as_os_str()) {
+        return Err(format!("{} is not a file name", name));
+    }
+    if name == "cdk-mintd.sqlite" || name == "cdk-mintd-auth.sqlite" {
+        return Err(format!("{} is where the link to the database goes", name));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Points the symlink `link` at `target` in the same directory, replacing
+/// an earlier link in one rename so mintd never finds neither
+#[cfg(unix)]
+pub fn link_database(link: &Path, target: &Path) -> std::io::Result<()> {
+    let partial = with_suffix(link, ".link");
+    let _ = std::fs::remove_file(&partial);
+    std::os::unix::fs::symlink(target.file_name().unwrap_or_default(), &partial)?;
+    std::fs::rename(&partial, link)
+}
+
+#[cfg(not(unix))]
+pub fn link_database(link: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("cannot link {:?}, named outputs need symlinks", link),
+    ))
+}
+
+/// The database a link made by `link_database` points at, or `path` itself
+pub fn resolve_link(path: &Path) -> PathBuf {
+    match std::fs::read_link(path) {
+        Ok(target) => path.with_file_name(target),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Flushes the directory entry so renames and new files inside it survive a power loss
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::File::open(dir)?.sync_all()
+}
+
+// Windows has no way to open a directory for syncing, NTFS journals the metadata itself
+#[cfg(not(unix))]
+pub fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use cdk_common::Bolt11Invoice;
+use cdk_common::nuts::PaymentMethod;
+
+// cdk 0.10 records no payment method on quotes, it is implied by the request
+// string: a bolt11 invoice, or a bolt12 offer or invoice. A request that
+// changes shape on the way is paid, or looked up, as the wrong method after
+// the switch, so the kind of every request is compared on its own.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestKind {
+    Bolt11,
+    Bolt12Offer,
+    Bolt12Invoice,
+    Unknown,
+}
+
+impl fmt::Display for RequestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RequestKind::Bolt11 => "bolt11",
+            RequestKind::Bolt12Offer => "bolt12 offer",
+            RequestKind::Bolt12Invoice => "bolt12 invoice",
+            RequestKind::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn request_kind(request: &str) -> RequestKind {
+    let request = request.trim().to_lowercase();
+
+    if request.starts_with("lno1") {
+        RequestKind::Bolt12Offer
+    } else if request.starts_with("lni1") {
+        RequestKind::Bolt12Invoice
+    } else if Bolt11Invoice::from_str(&request).is_ok() {
+        RequestKind::Bolt11
+    } else {
+        RequestKind::Unknown
+    }
+}
+
+/// Number of requests of each kind
+pub fn count_kinds<'a>(requests: impl Iterator<Item = &'a str>) -> BTreeMap<RequestKind, usize> {
+    let mut counts = BTreeMap::new();
+    for request in requests {
+        *counts.entry(request_kind(request)).or_default() += 1;
+    }
+
+    counts
+}
+
+/// Whether a melt request recorded with `method` can pay a request of `kind`.
+/// cdk 0.10 only names bolt11, bolt12 backends register as a custom method.
+pub fn method_matches(kind: RequestKind, method: &PaymentMethod) -> bool {
+    match (kind, method) {
+        (RequestKind::Bolt11, PaymentMethod::Bolt11) => true,
+        (RequestKind::Bolt12Offer | RequestKind::Bolt12Invoice, PaymentMethod::Custom(method)) => {
+            method.eq_ignore_ascii_case("bolt12")
+        }
+        (RequestKind::Unknown, PaymentMethod::Custom(_)) => true,
+        _ => false,
+    }
+}
+
+pub fn describe_counts(counts: &BTreeMap<RequestKind, usize>) -> String {
+    counts
+        .iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
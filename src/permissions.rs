@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+use crate::paths::with_suffix;
+
+/// Owner and group for `--chown`, given as `user[:group]` by name or id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    let parsed = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|_| format!("{} is not an octal mode such as 640", mode))?;
+
+    if parsed > 0o7777 {
+        return Err(format!("{} is not an octal mode such as 640", mode));
+    }
+
+    Ok(parsed)
+}
+
+pub fn parse_owner(owner: &str) -> Result<Owner, String> {
+    let (user, group) = match owner.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (owner, None),
+    };
+
+    let uid = match user {
+        "" => None,
+        user => Some(resolve_id(user, "/etc/passwd")?),
+    };
+    let gid = match group {
+        None | Some("") => None,
+        Some(group) => Some(resolve_id(group, "/etc/group")?),
+    };
+
+    if uid.is_none() && gid.is_none() {
+        return Err("expected user, user:group or :group".to_string());
+    }
+
+    Ok(Owner { uid, gid })
+}
+
+// Names are looked up in the local account files, numeric ids are taken as is
+fn resolve_id(name: &str, database: &str) -> Result<u32, String> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    let entries = std::fs::read_to_string(database)
+        .map_err(|e| format!("could not read {}: {}", database, e))?;
+
+    entries
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+        .and_then(|fields| fields.get(2)?.parse().ok())
+        .ok_or_else(|| format!("{} not found in {}", name, database))
+}
+
+/// Applies the mode and owner to a SQLite database and its WAL and shared
+/// memory files, mintd has to write all of them
+pub fn apply(path: &Path, mode: Option<u32>, owner: Option<Owner>) -> Result<()> {
+    for suffix in ["", "-wal", "-shm"] {
+        let file = with_suffix(path, suffix);
+        if !file.exists() {
+            continue;
+        }
+
+        if let Some(mode) = mode {
+            set_mode(&file, mode)?;
+        }
+        if let Some(owner) = owner {
+            set_owner(&file, owner)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .map_err(|e| anyhow!("Could not chmod {:?} to {:o}: {}", path, mode, e))
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, owner: Owner) -> Result<()> {
+    std::os::unix::fs::chown(path, owner.uid, owner.gid)
+        .map_err(|e| anyhow!("Could not chown {:?}: {}", path, e))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Err(anyhow!("--chmod is only supported on unix"))
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _owner: Owner) -> Result<()> {
+    Err(anyhow!("--chown is only supported on unix"))
+}
@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_common::database::MintProofsDatabase;
+use cdk_common::nuts::{Id, ProofsMethods};
+use cdk_common::{BlindSignature, Proof, PublicKey, State};
+use cdk_redb::MintRedbDatabase;
+use redb::ReadableTable;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::batch::panic_message;
+use crate::compact;
+use crate::raw::{self, RedbReader};
+use crate::salvage;
+use crate::spending_conditions::{self, InvalidSecret};
+
+// Reading from redb and writing to SQLite are both mostly CPU bound:
+// deserializing and hashing proofs on one side, binding and inserting rows on
+// the other. The reads run on their own task and hand batches to the writer
+// through a bounded channel, so the two overlap while no more than a few
+// batches are held in memory.
+
+// Batches read ahead of the writer
+const CHANNEL_DEPTH: usize = 4;
+
+// Proofs and blind signatures sent to the writer at a time
+pub const PROOF_BATCH_SIZE: usize = 1000;
+const SIGNATURE_BATCH_SIZE: usize = 1000;
+
+/// Proofs of one keyset, with what the writer needs worked out already
+pub struct ProofBatch {
+    pub keyset: Id,
+    pub proofs: Vec<Proof>,
+    pub ys: Vec<PublicKey>,
+    pub states: Vec<Option<State>>,
+    pub invalid_secrets: Vec<InvalidSecret>,
+    // Exact duplicates of proofs in the batch that were dropped
+    pub duplicates: u64,
+}
+
+pub struct SignatureBatch {
+    pub messages: Vec<PublicKey>,
+    pub sigs: Vec<BlindSignature>,
+}
+
+impl SignatureBatch {
+    fn new() -> Self {
+        Self {
+            messages: Vec::with_capacity(SIGNATURE_BATCH_SIZE),
+            sigs: Vec::with_capacity(SIGNATURE_BATCH_SIZE),
+        }
+    }
+}
+
+/// Batches coming from a reader task
+pub struct Reader<T> {
+    batches: Receiver<Result<T>>,
+    // Taken once it has been waited for
+    task: Option<JoinHandle<()>>,
+}
+
+impl<T> Reader<T> {
+    /// The next batch, `None` once the reader is done. Errors and panics of
+    /// the reader are returned as errors reading the redb.
+    pub async fn next(&mut self) -> Result<Option<T>> {
+        match self.batches.recv().await {
+            Some(batch) => batch.map(Some).map_err(salvage::read_error),
+            None => {
+                if let Some(task) = self.task.take()
+                    && let Err(err) = task.await
+                    && err.is_panic()
+                {
+                    return Err(salvage::read_error(anyhow!(
+                        "redb panicked: {}",
+                        panic_message(&*err.into_panic())
+                    )));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stops the reader and waits for it, so the redb it had open can be
+    /// opened again, e.g. by a salvage after the writer failed
+    pub async fn close(mut self) {
+        self.batches.close();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+pub fn read_proofs(
+    redb_db: MintRedbDatabase,
+    keysets: Vec<Id>,
+    compact: bool,
+) -> Reader<ProofBatch> {
+    let (sender, batches) = mpsc::channel(CHANNEL_DEPTH);
+    let task = tokio::spawn(
+        async move {
+            for keyset in keysets {
+                if let Err(err) = send_keyset_proofs(&redb_db, keyset, compact, &sender).await {
+                    let _ = sender.send(Err(err)).await;
+                    return;
+                }
+            }
+        }
+        .in_current_span(),
+    );
+
+    Reader {
+        batches,
+        task: Some(task),
+    }
+}
+
+// Keysets are split into batches so writing starts before a large keyset is
+// hashed in full
+async fn send_keyset_proofs(
+    redb_db: &MintRedbDatabase,
+    keyset: Id,
+    compact: bool,
+    sender: &Sender<Result<ProofBatch>>,
+) -> Result<()> {
+    let (mut proofs, mut states) = redb_db.get_proofs_by_keyset_id(&keyset).await?;
+    assert_eq!(proofs.len(), states.len());
+    tracing::debug!("Read {} proofs of keyset {}", proofs.len(), keyset);
+
+    // A proof and its duplicates have the same keyset, so they are all read here
+    let mut duplicates = if compact {
+        compact::dedup_proofs(&mut proofs, &mut states)?
+    } else {
+        0
+    };
+
+    while !proofs.is_empty() {
+        let rest = proofs.split_off(proofs.len().min(PROOF_BATCH_SIZE));
+        let batch_proofs = std::mem::replace(&mut proofs, rest);
+        let rest = states.split_off(batch_proofs.len());
+        let batch_states = std::mem::replace(&mut states, rest);
+
+        let batch = ProofBatch {
+            keyset,
+            ys: batch_proofs.ys()?,
+            invalid_secrets: spending_conditions::invalid_secrets(&batch_proofs, &batch_states)?,
+            proofs: batch_proofs,
+            states: batch_states,
+            duplicates: std::mem::take(&mut duplicates),
+        };
+        // The writer only stops listening once it failed itself
+        if sender.send(Ok(batch)).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_blind_signatures(redb_path: PathBuf) -> Reader<SignatureBatch> {
+    let (sender, batches) = mpsc::channel(CHANNEL_DEPTH);
+    let span = tracing::Span::current();
+    let task = tokio::task::spawn_blocking(move || {
+        let _span = span.enter();
+        if let Err(err) = send_blind_signatures(&redb_path, &sender) {
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+
+    Reader {
+        batches,
+        task: Some(task),
+    }
+}
+
+fn send_blind_signatures(redb_path: &Path, sender: &Sender<Result<SignatureBatch>>) -> Result<()> {
+    let read_txn = RedbReader::open(redb_path)?.begin_read()?;
+    let Some(table) = raw::open_table::<[u8; 33], &str>(&read_txn, "blinded_signatures")? else {
+        return Ok(());
+    };
+
+    let mut batch = SignatureBatch::new();
+    for entry in table.iter()? {
+        let (message, sig) = entry?;
+        batch
+            .sigs
+            .push(serde_json::from_slice::<BlindSignature>(sig.value())?);
+        batch.messages.push(PublicKey::from_slice(message.value())?);
+
+        if batch.messages.len() == SIGNATURE_BATCH_SIZE {
+            let full = std::mem::replace(&mut batch, SignatureBatch::new());
+            if sender.blocking_send(Ok(full)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    if !batch.messages.is_empty() {
+        let _ = sender.blocking_send(Ok(batch));
+    }
+
+    Ok(())
+}
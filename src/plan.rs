@@ -0,0 +1,361 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+
+use crate::cli::MigrateArgs;
+use crate::disk_space;
+use crate::marker;
+use crate::meta;
+use crate::output::say;
+use crate::paths::{self, MigrationPaths};
+use crate::pipeline;
+use crate::progress::count_records;
+use crate::raw;
+use crate::redb_format;
+use crate::table_names;
+use crate::throttle::RateLimit;
+
+// `plan` shows what a migration of the work dir would do with the given
+// flags: what it refuses to do, the records per section it would copy and the
+// files it would create, rename or remove. It only counts the redb tables, so
+// it runs in moments even for a large mint and never writes anything.
+
+// Sections of the mint database with the redb tables they are read from
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("mint info", &["config"]),
+    ("quotes", &["mint_quotes", "melt_quotes", "melt_requests"]),
+    ("keysets", &["keysets"]),
+    ("proofs", &["proofs"]),
+    ("blind signatures", &["blinded_signatures"]),
+];
+
+const AUTH_SECTIONS: &[(&str, &[&str])] = &[
+    ("auth keysets", &["keysets"]),
+    ("auth proofs", &["proofs"]),
+    ("auth blind signatures", &["blinded_signatures"]),
+    ("protected endpoints", &["endpoints"]),
+];
+
+pub async fn plan(work_dir: PathBuf, args: &MigrateArgs) -> Result<()> {
+    let links = MigrationPaths::new(&work_dir);
+    let output_name = args.output_name();
+    let paths = match &output_name {
+        Some(name) => links.named(name),
+        None => links.clone(),
+    };
+    let building = paths.building(args.tmp_dir.as_deref());
+    let auth = paths.auth_redb.exists();
+    let mut blockers = vec![];
+
+    say!("📋 Migration plan for {:?}", work_dir);
+
+    if !paths.redb.exists() {
+        return Err(anyhow!("No redb database found at {:?}", paths.redb));
+    }
+
+    println!("\nSource:");
+    for redb in [&paths.redb, &paths.auth_redb] {
+        if redb.exists() {
+            describe_source(redb, &mut blockers);
+        }
+    }
+    if auth && cfg!(not(feature = "auth")) {
+        blockers.push(format!(
+            "{:?} is an auth database, which this build cannot migrate",
+            paths.auth_redb
+        ));
+    }
+
+    if let Some(section) = args.replace_section {
+        println!("\nReplace:");
+        if paths.sqlite.exists() {
+            println!(
+                "  ~ {} section of {:?}, rebuilt from the redb and swapped in",
+                section.name(),
+                paths.sqlite
+            );
+        } else {
+            blockers.push(format!(
+                "--replace-section needs an existing SQLite database at {:?}",
+                paths.sqlite
+            ));
+        }
+        return finish(&blockers, format!("replace the {} section", section.name()));
+    }
+
+    let mut records = 0;
+    println!("\nSections:");
+    records += print_sections(&paths.redb, SECTIONS);
+    if auth {
+        records += print_sections(&paths.auth_redb, AUTH_SECTIONS);
+    }
+
+    if let Some(marker) = marker::read_marker(&paths.redb)? {
+        if args.force {
+            say!(
+                "⚠️  Already migrated to {:?}, migrating again because of --force",
+                marker.target
+            );
+        } else {
+            blockers.push(format!(
+                "{:?} was already migrated to {:?}, use --force to migrate it again",
+                paths.redb, marker.target
+            ));
+        }
+    }
+
+    let merging = args.merge && paths.sqlite.exists();
+    if paths.sqlite.exists() && output_name.is_some() {
+        blockers.push(format!(
+            "{:?} already exists, choose another output name",
+            paths.sqlite
+        ));
+    } else if paths.sqlite.exists() && !merging {
+        blockers.push(format!(
+            "{:?} already exists, use --merge to merge into it",
+            paths.sqlite
+        ));
+    }
+
+    if output_name.is_some() {
+        for link in [&links.sqlite, &links.auth_sqlite] {
+            if link.exists() && !link.is_symlink() {
+                blockers.push(format!(
+                    "{:?} is a database, not a link to one, move it out of the way for a named output",
+                    link
+                ));
+            }
+        }
+    }
+
+    if let Some(path) = &args.post_sql
+        && !path.is_file()
+    {
+        blockers.push(format!("Post migration SQL {:?} cannot be read", path));
+    }
+
+    let mut created = 0;
+    println!("\nFiles:");
+    if building.sqlite.exists() {
+        let status = meta::read_verification(&building.sqlite).await?;
+        let status = status.as_deref().unwrap_or("never reached");
+        if args.resume {
+            println!(
+                "  ~ {:?} from an incomplete run (verification: {}), resumed",
+                building.sqlite, status
+            );
+        } else if args.wipe {
+            println!(
+                "  - {:?} from an incomplete run (verification: {}), removed and built again",
+                building.sqlite, status
+            );
+        } else {
+            blockers.push(format!(
+                "Incomplete SQLite database at {:?} (verification: {}), use --resume to continue it or --wipe to start over",
+                building.sqlite, status
+            ));
+        }
+    }
+
+    let mut targets = vec![(&building.sqlite, &paths.sqlite)];
+    if auth {
+        targets.push((&building.auth_sqlite, &paths.auth_sqlite));
+    }
+    for (built, target) in targets {
+        if merging && target.exists() {
+            println!("  + {:?}, merged into {:?} and removed", built, target);
+        } else {
+            created += 1;
+            println!("  + {:?}, renamed to {:?} once verified", built, target);
+        }
+    }
+
+    if output_name.is_some() {
+        let mut linked = vec![(&links.sqlite, &paths.sqlite)];
+        if auth {
+            linked.push((&links.auth_sqlite, &paths.auth_sqlite));
+        }
+        for (link, target) in linked {
+            let action = if link.is_symlink() { "~" } else { "+" };
+            println!("  {} {:?}, linked to {:?}", action, link, target);
+        }
+    }
+    if args.repair {
+        let dir = building.sqlite.parent().unwrap_or(&work_dir);
+        println!(
+            "  + repaired copies of the redb in {:?}, removed afterwards",
+            dir
+        );
+    }
+    if args.salvage {
+        println!(
+            "  + {:?} if the redb cannot be read as a whole",
+            paths::with_suffix(&paths.sqlite, ".salvage.json")
+        );
+    }
+    created += 1;
+    println!("  + {:?}", marker::marker_path(&paths.redb));
+    if args.shred_source {
+        for redb in [&paths.redb, &paths.auth_redb] {
+            if redb.exists() {
+                println!("  - {:?}, overwritten and removed once verified", redb);
+            }
+        }
+    }
+
+    if !args.skip_space_check {
+        println!("\nDisk space:");
+        if let Err(err) = disk_space::check(&paths, &building, args.repair) {
+            blockers.push(err.to_string());
+        }
+    }
+
+    println!("\nOptions:");
+    print_options(args, merging);
+
+    finish(
+        &blockers,
+        format!(
+            "{} record(s) to migrate, {} file(s) to create",
+            records, created
+        ),
+    )
+}
+
+fn describe_source(redb: &Path, blockers: &mut Vec<String>) {
+    let size = std::fs::metadata(redb).map(|m| m.len()).unwrap_or_default();
+    println!("  {:?}, {:.1} MiB", redb, size as f64 / (1024.0 * 1024.0));
+
+    if let Err(err) = redb_format::check_format(redb) {
+        blockers.push(err.to_string());
+    }
+    if raw::is_locked(redb) {
+        blockers.push(format!(
+            "{:?} is locked by another process, stop mintd or use --wait-for-lock",
+            redb
+        ));
+    }
+}
+
+fn print_sections(redb: &Path, sections: &[(&str, &[&str])]) -> u64 {
+    let mut total = 0;
+
+    for (section, tables) in sections {
+        let counts: Vec<String> = tables
+            .iter()
+            .map(|table| {
+                // A table cdk-redb never created holds nothing to migrate
+                let count = count_records(redb, table_names::resolve(table)).unwrap_or_default();
+                total += count;
+                if tables.len() == 1 {
+                    count.to_string()
+                } else {
+                    format!("{} {}", count, table)
+                }
+            })
+            .collect();
+        println!("  + {:<22} {}", section, counts.join(", "));
+    }
+
+    total
+}
+
+fn print_options(args: &MigrateArgs, merging: bool) {
+    let mut options = vec![];
+
+    let mut tables: Vec<&str> = SECTIONS
+        .iter()
+        .chain(AUTH_SECTIONS)
+        .flat_map(|(_, tables)| tables.iter().copied())
+        .collect();
+    tables.sort();
+    tables.dedup();
+    for table in tables {
+        let actual = table_names::resolve(table);
+        if actual != table {
+            options.push(format!("{} is read from table {}", table, actual));
+        }
+    }
+
+    if args.low_memory {
+        options.push(
+            "proofs and blind signatures are streamed in small batches (--low-memory)".to_string(),
+        );
+    } else {
+        options.push(format!(
+            "proofs and blind signatures are written in batches of {}",
+            pipeline::PROOF_BATCH_SIZE
+        ));
+    }
+    if let Some(limit) = args.rate_limit {
+        options.push(match limit {
+            RateLimit::RecordsPerSec(rate) => {
+                format!("records are copied at most {} per second", rate)
+            }
+            RateLimit::BytesPerSec(rate) => {
+                format!("records are copied at most {:.1} MB per second", rate / 1e6)
+            }
+        });
+    }
+    if args.compact {
+        options.push(
+            "proofs stored more than once under different keys are migrated once (--compact)"
+                .to_string(),
+        );
+    }
+    if args.repair {
+        options.push("the redb is repaired and compacted on a copy first (--repair)".to_string());
+    }
+    if args.salvage {
+        options.push("unreadable records are skipped instead of failing (--salvage)".to_string());
+    }
+    if merging {
+        options.push(format!(
+            "conflicting rows are resolved by {} (--merge)",
+            if args.on_conflict.is_empty() {
+                "failing".to_string()
+            } else {
+                format!("{} rule(s)", args.on_conflict.len())
+            }
+        ));
+    }
+    if let Some(path) = &args.post_sql {
+        options.push(format!("{:?} runs after the data is loaded", path));
+    }
+    if let Some(mode) = args.chmod {
+        options.push(format!("the SQLite files get mode {:o}", mode));
+    }
+    if args.chown.is_some() {
+        options.push("the SQLite files are handed to another owner (--chown)".to_string());
+    }
+    if args.validate_proofs {
+        options.push("every proof's signature is checked with the mint's keys".to_string());
+    }
+    if args.smoke_test {
+        options.push("mintd's startup queries run against the result".to_string());
+    }
+    if args.fsync {
+        options.push("the result is flushed to disk".to_string());
+    }
+
+    for option in options {
+        println!("  {}", option);
+    }
+}
+
+fn finish(blockers: &[String], summary: String) -> Result<()> {
+    if !blockers.is_empty() {
+        println!();
+        for blocker in blockers {
+            say!("❌ {}", blocker);
+        }
+        return Err(anyhow!(
+            "The migration would stop, {} issue(s) to resolve first",
+            blockers.len()
+        ));
+    }
+
+    say!("\n✅ Plan: {}. Run without `plan` to apply it.", summary);
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::raw::RedbReader;
+
+/// Steps of a migration, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    MintInfo,
+    Quotes,
+    Keysets,
+    Proofs,
+    BlindSignatures,
+    #[cfg_attr(not(feature = "auth"), allow(dead_code))]
+    Auth,
+    Verification,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::MintInfo => "mint info",
+            Phase::Quotes => "quotes",
+            Phase::Keysets => "keysets",
+            Phase::Proofs => "proofs",
+            Phase::BlindSignatures => "blind signatures",
+            Phase::Auth => "auth",
+            Phase::Verification => "verification",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Phase {
+    /// Span the work of this phase runs in
+    pub fn span(self) -> tracing::Span {
+        tracing::info_span!("phase", phase = %self)
+    }
+}
+
+/// Stable codes of the warnings a migration raises, for --deny and --allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum WarningCode {
+    /// Reading the redb failed and the salvage migration took over
+    #[value(name = "W-SALVAGE")]
+    Salvage,
+    /// An auth endpoint without an auth requirement is not recorded
+    #[value(name = "W-UNPROTECTED-ENDPOINT")]
+    UnprotectedEndpoint,
+    /// A proof's secret or spending conditions no longer parse
+    #[value(name = "W-MALFORMED-SECRET")]
+    MalformedSecret,
+    /// An amountless melt quote has no amount to restore
+    #[value(name = "W-AMOUNTLESS-QUOTE")]
+    AmountlessQuote,
+    /// A timestamp is zero, in the future or before its creation
+    #[value(name = "W-TIMESTAMP")]
+    Timestamp,
+    /// A table of the schema is not filled by this tool
+    #[value(name = "W-UNFILLED-TABLE")]
+    UnfilledTable,
+    /// A column of the schema is left at its default
+    #[value(name = "W-UNFILLED-COLUMN")]
+    UnfilledColumn,
+    /// --merge found rows whose key is taken by a different row
+    #[value(name = "W-MERGE-CONFLICT")]
+    MergeConflict,
+    /// A paid melt quote has no payment preimage
+    #[value(name = "W-MISSING-PREIMAGE")]
+    MissingPreimage,
+    /// A melt quote is paid with a payment method its request does not use
+    #[value(name = "W-PAYMENT-METHOD")]
+    PaymentMethod,
+    /// A melt quote's msat_to_pay does not match its amount
+    #[value(name = "W-MSAT-MISMATCH")]
+    MsatMismatch,
+}
+
+impl fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped codes");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// Receives the progress of a migration, for applications that embed the
+/// migrator and want to show it live, e.g. a mintd admin dashboard. Every
+/// method does nothing unless overridden.
+pub trait ProgressObserver: Send + Sync {
+    fn phase_started(&self, _phase: Phase) {}
+
+    /// `done` records of the phase are written, out of `total` if known
+    fn records(&self, _phase: Phase, _done: u64, _total: Option<u64>) {}
+
+    fn warning(&self, _code: WarningCode, _message: &str) {}
+}
+
+pub type Progress = Arc<dyn ProgressObserver>;
+
+/// Observer for runs nobody watches beyond the console output
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+/// Observer passing the progress on to each of several others
+pub struct Observers(pub Vec<Progress>);
+
+impl ProgressObserver for Observers {
+    fn phase_started(&self, phase: Phase) {
+        for observer in &self.0 {
+            observer.phase_started(phase);
+        }
+    }
+
+    fn records(&self, phase: Phase, done: u64, total: Option<u64>) {
+        for observer in &self.0 {
+            observer.records(phase, done, total);
+        }
+    }
+
+    fn warning(&self, code: WarningCode, message: &str) {
+        for observer in &self.0 {
+            observer.warning(code, message);
+        }
+    }
+}
+
+/// Observer keeping the codes of the warnings a migration raised, for
+/// --strict and --deny
+#[derive(Default)]
+pub struct RaisedWarnings(Mutex<Vec<WarningCode>>);
+
+impl RaisedWarnings {
+    /// Each code raised with the number of times it was
+    pub fn counts(&self) -> Vec<(WarningCode, usize)> {
+        let mut codes = self.0.lock().expect("progress lock").clone();
+        codes.sort();
+        let mut counts: Vec<(WarningCode, usize)> = vec![];
+        for code in codes {
+            match counts.last_mut() {
+                Some((last, count)) if *last == code => *count += 1,
+                _ => counts.push((code, 1)),
+            }
+        }
+        counts
+    }
+}
+
+impl ProgressObserver for RaisedWarnings {
+    fn warning(&self, code: WarningCode, _message: &str) {
+        self.0.lock().expect("progress lock").push(code);
+    }
+}
+
+/// Latest progress of a migration as recorded by [`ProgressRecorder`]
+#[derive(Debug, Default)]
+pub struct ProgressState {
+    pub phase: Option<Phase>,
+    pub phase_started: Option<Instant>,
+    pub done: u64,
+    pub total: Option<u64>,
+    pub warnings: Vec<String>,
+    // None while the migration runs
+    pub outcome: Option<Result<(), String>>,
+}
+
+impl ProgressState {
+    /// Share of the current phase's records written so far
+    pub fn percent(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(100.0),
+            Some(total) => Some(self.done as f64 * 100.0 / total as f64),
+            None => None,
+        }
+    }
+
+    /// Time the current phase still needs at the rate it has run so far
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total?;
+        if self.done == 0 {
+            return None;
+        }
+
+        let elapsed = self.phase_started?.elapsed();
+        let left = total.saturating_sub(self.done);
+        Some(elapsed.mul_f64(left as f64 / self.done as f64))
+    }
+}
+
+/// Observer keeping the latest progress for others to poll
+pub struct ProgressRecorder(pub Arc<Mutex<ProgressState>>);
+
+impl ProgressObserver for ProgressRecorder {
+    fn phase_started(&self, phase: Phase) {
+        let mut state = self.0.lock().expect("progress lock");
+        state.phase = Some(phase);
+        state.phase_started = Some(Instant::now());
+        state.done = 0;
+        state.total = None;
+    }
+
+    fn records(&self, _phase: Phase, done: u64, total: Option<u64>) {
+        let mut state = self.0.lock().expect("progress lock");
+        state.done = done;
+        state.total = total;
+    }
+
+    fn warning(&self, code: WarningCode, message: &str) {
+        let mut state = self.0.lock().expect("progress lock");
+        state.warnings.push(format!("[{}] {}", code, message));
+    }
+}
+
+/// Number of records in a redb table, if the table can be read without
+/// cdk-redb upgrading the file first
+pub fn count_records(redb_path: &Path, table: &str) -> Option<u64> {
+    RedbReader::open(redb_path).ok()?.table_len(table).ok()?
+}
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::output::say;
+use crate::progress::{Phase, ProgressObserver, WarningCode};
+
+// `--progress-socket` streams the progress of a migration to every process
+// connected to a Unix domain socket, one JSON event per line, for supervisors
+// that would otherwise parse the logs or poll --status-addr. A client that
+// connects late first gets the phase and record count so far. A client that
+// reads too slowly misses events rather than holding up the migration.
+
+// Events kept for a client that has not read them yet
+const BACKLOG: usize = 1024;
+
+// How long clients get to read the last events once the migration ended
+const DRAIN: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+struct Event {
+    line: String,
+    last: bool,
+}
+
+#[derive(Default)]
+struct Latest {
+    phase: Option<Value>,
+    records: Option<Value>,
+}
+
+/// Observer sending the progress to every connected client
+pub struct ProgressFeed {
+    events: broadcast::Sender<Event>,
+    latest: Mutex<Latest>,
+}
+
+impl ProgressFeed {
+    fn send(&self, event: Value, last: bool) {
+        // Fails only while nobody is connected
+        let _ = self.events.send(Event {
+            line: event.to_string(),
+            last,
+        });
+    }
+}
+
+impl ProgressObserver for ProgressFeed {
+    fn phase_started(&self, phase: Phase) {
+        let event = json!({ "event": "phase", "phase": phase.to_string() });
+        let mut latest = self.latest.lock().expect("progress lock");
+        latest.phase = Some(event.clone());
+        latest.records = None;
+        self.send(event, false);
+    }
+
+    fn records(&self, phase: Phase, done: u64, total: Option<u64>) {
+        let event = json!({
+            "event": "records",
+            "phase": phase.to_string(),
+            "done": done,
+            "total": total,
+        });
+        self.latest.lock().expect("progress lock").records = Some(event.clone());
+        self.send(event, false);
+    }
+
+    fn warning(&self, code: WarningCode, message: &str) {
+        self.send(
+            json!({ "event": "warning", "code": code.to_string(), "message": message }),
+            false,
+        );
+    }
+}
+
+/// Stops accepting clients and removes the socket once dropped, also when
+/// the migration panics
+pub struct ProgressSocket {
+    path: PathBuf,
+    feed: Arc<ProgressFeed>,
+    accept: JoinHandle<()>,
+    clients: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Drop for ProgressSocket {
+    fn drop(&mut self) {
+        self.accept.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl ProgressSocket {
+    /// Observer to hand to the migration
+    pub fn feed(&self) -> Arc<ProgressFeed> {
+        self.feed.clone()
+    }
+
+    /// Tells the clients how the migration ended and gives them a moment to
+    /// read it before the socket goes away
+    pub async fn finish(self, outcome: &Result<()>) {
+        let event = match outcome {
+            Ok(()) => json!({ "event": "finished", "status": "ok" }),
+            Err(err) => json!({
+                "event": "finished",
+                "status": "failed",
+                "error": format!("{:#}", err),
+            }),
+        };
+        self.feed.send(event, true);
+        self.accept.abort();
+
+        let clients = std::mem::take(&mut *self.clients.lock().expect("progress lock"));
+        for client in clients {
+            let _ = tokio::time::timeout(DRAIN, client).await;
+        }
+    }
+}
+
+pub async fn spawn_progress_socket(path: &Path) -> Result<ProgressSocket> {
+    if path.exists() {
+        // A socket left by a run that was killed is taken over, one that is
+        // still served is not
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(anyhow!(
+                "{:?} is served by another process, choose another progress socket",
+                path
+            ));
+        }
+        if !is_socket(path) {
+            return Err(anyhow!(
+                "{:?} exists and is not a socket, will not replace it",
+                path
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|e| anyhow!("Could not create progress socket {:?}: {}", path, e))?;
+    say!("📡 Streaming migration progress on {:?}", path);
+
+    let feed = Arc::new(ProgressFeed {
+        events: broadcast::channel(BACKLOG).0,
+        latest: Mutex::default(),
+    });
+
+    let clients: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::default();
+    let accept = tokio::spawn({
+        let feed = feed.clone();
+        let clients = clients.clone();
+        async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+
+                // Subscribed before the state so no event falls between them
+                let events = feed.events.subscribe();
+                let latest = {
+                    let latest = feed.latest.lock().expect("progress lock");
+                    [&latest.phase, &latest.records]
+                        .into_iter()
+                        .flatten()
+                        .map(Value::to_string)
+                        .collect()
+                };
+                let client = tokio::spawn(async move {
+                    if let Err(err) = stream_events(stream, latest, events).await {
+                        tracing::debug!("Progress socket client went away: {}", err);
+                    }
+                });
+                let mut clients = clients.lock().expect("progress lock");
+                clients.retain(|client| !client.is_finished());
+                clients.push(client);
+            }
+        }
+    });
+
+    Ok(ProgressSocket {
+        path: path.to_path_buf(),
+        feed,
+        accept,
+        clients,
+    })
+}
+
+async fn stream_events(
+    mut stream: UnixStream,
+    latest: Vec<String>,
+    mut events: broadcast::Receiver<Event>,
+) -> std::io::Result<()> {
+    for event in latest {
+        stream.write_all(format!("{}\n", event).as_bytes()).await?;
+    }
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                stream
+                    .write_all(format!("{}\n", event.line).as_bytes())
+                    .await?;
+                if event.last {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                tracing::debug!("Progress socket client missed {} events", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+
+    stream.shutdown().await
+}
+
+fn is_socket(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket())
+}
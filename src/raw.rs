@@ -0,0 +1,452 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+use anyhow::{Result, anyhow};
+#[cfg(feature = "auth")]
+use cdk_common::AuthProof;
+use cdk_common::common::PaymentProcessorKey;
+use cdk_common::mint::{MeltQuote, MintKeySetInfo, MintQuote};
+use cdk_common::nuts::MeltRequest;
+use cdk_common::{BlindSignature, Proof, PublicKey, State};
+use redb::{
+    Database, DatabaseError, Key, MultimapTableDefinition, ReadOnlyTable, ReadTransaction,
+    ReadableMultimapTable, ReadableTable, ReadableTableMetadata, TableDefinition, TableError,
+    TableHandle, TypeName, Value,
+};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::{redb_format, table_names};
+
+// cdk-redb stores every record as JSON under a fixed-width key. Migration,
+// inspection, salvage and the doctor read the tables directly rather than
+// through cdk-redb, to see records it would skip or fail on, to read a file
+// cdk-redb cannot open, or to read a table without holding all of cdk-redb's
+// lock. The tables are opened as raw bytes through [`Raw`], so one
+// definition reads a table whatever version of cdk wrote it, and
+// [`RedbReader`] parses them into the cdk types. The batched readers of
+// --low-memory and the signature pipeline stream their tables instead of
+// loading them whole. Lock checks, record counts and the repair of a copy
+// open the redb here as well, so no other module defines its own tables.
+
+/// A redb mint or auth database opened for reading its tables
+pub struct RedbReader {
+    db: Database,
+}
+
+impl RedbReader {
+    pub fn open(redb_path: &Path) -> Result<Self> {
+        if !redb_path.exists() {
+            return Err(anyhow!("No redb database found at {:?}", redb_path));
+        }
+        redb_format::check_format(redb_path)?;
+
+        Ok(Self {
+            db: Database::open(redb_path)?,
+        })
+    }
+
+    /// Opens a redb that was not shut down cleanly, repairing it in place,
+    /// and tells whether it needed the repair. Only for a copy of the source,
+    /// as the repair writes to the file.
+    pub fn open_repaired(redb_path: &Path) -> Result<(Self, bool)> {
+        let repaired = Arc::new(AtomicBool::new(false));
+        let flag = repaired.clone();
+        let db = Database::builder()
+            .set_repair_callback(move |session| {
+                flag.store(true, AtomicOrdering::Relaxed);
+                tracing::info!("Repairing redb copy: {:.0}%", session.progress() * 100.0);
+            })
+            .open(redb_path)?;
+
+        Ok((Self { db }, repaired.load(AtomicOrdering::Relaxed)))
+    }
+
+    /// Compacts the file, which also writes to it, and tells whether there
+    /// was anything to compact
+    pub fn compact(&mut self) -> Result<bool> {
+        Ok(self.db.compact()?)
+    }
+
+    pub fn begin_read(&self) -> Result<ReadTransaction> {
+        Ok(self.db.begin_read()?)
+    }
+
+    /// Number of records in a table, looked up by its name on disk, `None`
+    /// for a table that does not exist
+    pub fn table_len(&self, name: &str) -> Result<Option<u64>> {
+        let read_txn = self.begin_read()?;
+        let Some(handle) = read_txn.list_tables()?.find(|handle| handle.name() == name) else {
+            return Ok(None);
+        };
+
+        Ok(Some(read_txn.open_untyped_table(handle)?.len()?))
+    }
+
+    /// Number of records in all tables
+    pub fn record_count(&self) -> Result<u64> {
+        let read_txn = self.begin_read()?;
+        let mut records = 0;
+        for handle in read_txn.list_tables()? {
+            records += read_txn.open_untyped_table(handle)?.len()?;
+        }
+
+        Ok(records)
+    }
+
+    pub fn keysets(&self) -> Result<Vec<MintKeySetInfo>> {
+        self.values::<&str, _>("keysets")
+    }
+
+    pub fn mint_quotes(&self) -> Result<Vec<MintQuote>> {
+        self.values::<[u8; 16], _>("mint_quotes")
+    }
+
+    pub fn melt_quotes(&self) -> Result<Vec<MeltQuote>> {
+        self.values::<[u8; 16], _>("melt_quotes")
+    }
+
+    /// Melt requests with the quote they pay and the payment processor
+    pub fn melt_requests(&self) -> Result<Vec<(Uuid, MeltRequest<Uuid>, PaymentProcessorKey)>> {
+        self.entries::<[u8; 16], (&str, &str)>("melt_requests")?
+            .into_iter()
+            .map(|(quote, value)| {
+                let (request, payment_key) = <(&str, &str) as Value>::from_bytes(&value);
+                Ok((
+                    Uuid::from_slice(&quote)?,
+                    serde_json::from_str(request)?,
+                    serde_json::from_str(payment_key)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Proofs keyed by their Y
+    pub fn proofs(&self) -> Result<Vec<(PublicKey, Proof)>> {
+        self.keyed_values("proofs")
+    }
+
+    /// States cdk-redb recorded, it only writes one once a proof leaves the
+    /// unspent state
+    pub fn proof_states(&self) -> Result<HashMap<PublicKey, State>> {
+        Ok(self.keyed_values("proofs_state")?.into_iter().collect())
+    }
+
+    /// Proofs keyed by their Y, with their state
+    pub fn proofs_with_states(&self) -> Result<Vec<(PublicKey, Proof, State)>> {
+        let states = self.proof_states()?;
+
+        Ok(self
+            .proofs()?
+            .into_iter()
+            .map(|(y, proof)| {
+                let state = states.get(&y).copied().unwrap_or(State::Unspent);
+                (y, proof, state)
+            })
+            .collect())
+    }
+
+    /// Blind signatures keyed by the blinded message they sign
+    pub fn blind_signatures(&self) -> Result<Vec<(PublicKey, BlindSignature)>> {
+        self.keyed_values("blinded_signatures")
+    }
+
+    /// Ys of the proofs each melt quote was paid with
+    pub fn quote_proofs(&self) -> Result<Vec<(Uuid, PublicKey)>> {
+        self.quote_links("quote_proofs")
+    }
+
+    /// Blinded messages each mint quote was issued for
+    pub fn quote_signatures(&self) -> Result<Vec<(Uuid, PublicKey)>> {
+        self.quote_links("quote_signatures")
+    }
+
+    #[cfg(feature = "auth")]
+    pub fn auth_proofs(&self) -> Result<Vec<AuthProof>> {
+        self.values::<[u8; 33], _>("proofs")
+    }
+
+    /// Raw entries of a table, a table cdk-redb never created reads as empty
+    fn entries<K: Key + 'static, V: Value + 'static>(
+        &self,
+        name: &str,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        match read_table::<K, V>(&self.begin_read()?, name) {
+            Err(err) if matches!(err.downcast_ref(), Some(TableError::TableDoesNotExist(_))) => {
+                Ok(vec![])
+            }
+            entries => entries,
+        }
+    }
+
+    fn values<K: Key + 'static, T: DeserializeOwned>(&self, name: &str) -> Result<Vec<T>> {
+        self.entries::<K, &str>(name)?
+            .into_iter()
+            .map(|(_, value)| Ok(serde_json::from_slice(&value)?))
+            .collect()
+    }
+
+    fn keyed_values<T: DeserializeOwned>(&self, name: &str) -> Result<Vec<(PublicKey, T)>> {
+        self.entries::<[u8; 33], &str>(name)?
+            .into_iter()
+            .map(|(key, value)| {
+                Ok((
+                    PublicKey::from_slice(&key)?,
+                    serde_json::from_slice(&value)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn quote_links(&self, name: &str) -> Result<Vec<(Uuid, PublicKey)>> {
+        let entries = match read_multimap_table::<[u8; 16], [u8; 33]>(&self.begin_read()?, name) {
+            Err(err) if matches!(err.downcast_ref(), Some(TableError::TableDoesNotExist(_))) => {
+                vec![]
+            }
+            entries => entries?,
+        };
+
+        entries
+            .into_iter()
+            .map(|(quote, key)| Ok((Uuid::from_slice(&quote)?, PublicKey::from_slice(&key)?)))
+            .collect()
+    }
+}
+
+/// Whether another process, such as a running mintd, holds the redb open
+pub fn is_locked(redb_path: &Path) -> bool {
+    matches!(
+        Database::open(redb_path),
+        Err(DatabaseError::DatabaseAlreadyOpen)
+    )
+}
+
+/// Reads the stored bytes of a redb table without decoding them into `T`
+///
+/// The type name is taken from `T` so redb accepts the definition for a table
+/// that was created with `T`.
+#[derive(Debug)]
+pub struct Raw<T>(PhantomData<T>);
+
+impl<T: Value + 'static> Value for Raw<T> {
+    type SelfType<'a>
+        = &'a [u8]
+    where
+        Self: 'a;
+
+    type AsBytes<'a>
+        = &'a [u8]
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        T::fixed_width()
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> &'a [u8]
+    where
+        Self: 'a,
+    {
+        data
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a &'b [u8]) -> &'a [u8]
+    where
+        Self: 'b,
+    {
+        value
+    }
+
+    fn type_name() -> TypeName {
+        T::type_name()
+    }
+}
+
+impl<T: Key + 'static> Key for Raw<T> {
+    fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+        T::compare(data1, data2)
+    }
+}
+
+pub fn read_table<K: Key + 'static, V: Value + 'static>(
+    read_txn: &ReadTransaction,
+    name: &str,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let definition: TableDefinition<Raw<K>, Raw<V>> =
+        TableDefinition::new(table_names::resolve(name));
+    let table = read_txn.open_table(definition)?;
+
+    Ok(table
+        .iter()?
+        .flatten()
+        .map(|(key, value)| (key.value().to_vec(), value.value().to_vec()))
+        .collect())
+}
+
+/// Opens a table to stream its raw records instead of loading it whole,
+/// `None` for a table cdk-redb never created
+pub fn open_table<K: Key + 'static, V: Value + 'static>(
+    read_txn: &ReadTransaction,
+    name: &str,
+) -> Result<Option<ReadOnlyTable<Raw<K>, Raw<V>>>> {
+    let definition: TableDefinition<Raw<K>, Raw<V>> =
+        TableDefinition::new(table_names::resolve(name));
+    match read_txn.open_table(definition) {
+        Ok(table) => Ok(Some(table)),
+        Err(TableError::TableDoesNotExist(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records of a table read past damaged pages, with what could not be read
+#[derive(Debug, Default)]
+pub struct SalvagedTable {
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    pub errors: Vec<String>,
+}
+
+/// Like [`read_table`], but an unreadable record or page does not end the
+/// read. The table is read forward up to the first error and then backward
+/// from its end, so only the records in the damaged stretch are lost. A
+/// missing table reads as empty.
+pub fn salvage_table<K: Key + 'static, V: Value + 'static>(
+    read_txn: &ReadTransaction,
+    name: &str,
+) -> SalvagedTable {
+    let definition: TableDefinition<Raw<K>, Raw<V>> =
+        TableDefinition::new(table_names::resolve(name));
+    let mut salvaged = SalvagedTable::default();
+
+    let table = match read_txn.open_table(definition) {
+        Ok(table) => table,
+        Err(TableError::TableDoesNotExist(_)) => return salvaged,
+        Err(err) => {
+            salvaged.errors.push(err.to_string());
+            return salvaged;
+        }
+    };
+
+    let mut head = vec![];
+    if let Err(err) = guarded(|| -> Result<()> {
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            head.push((key.value().to_vec(), value.value().to_vec()));
+        }
+        Ok(())
+    }) {
+        salvaged.errors.push(err);
+    }
+    salvaged.entries = head;
+
+    if salvaged.errors.is_empty() {
+        return salvaged;
+    }
+
+    let last_good = salvaged.entries.last().map(|(key, _)| key.clone());
+    let mut tail = vec![];
+    if let Err(err) = guarded(|| -> Result<()> {
+        for entry in table.iter()?.rev() {
+            let (key, value) = entry?;
+            if Some(key.value()) == last_good.as_deref() {
+                break;
+            }
+            tail.push((key.value().to_vec(), value.value().to_vec()));
+        }
+        Ok(())
+    }) {
+        salvaged.errors.push(err);
+    }
+    salvaged.entries.extend(tail.into_iter().rev());
+
+    salvaged
+}
+
+// redb asserts on some inconsistencies, a panic counts as an unreadable
+// stretch like any other error
+fn guarded(read: impl FnOnce() -> Result<()>) -> Result<(), String> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(read)) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(panic) => Err(format!(
+            "redb panicked: {}",
+            crate::batch::panic_message(&*panic)
+        )),
+    }
+}
+
+pub fn read_multimap_table<K: Key + 'static, V: Key + 'static>(
+    read_txn: &ReadTransaction,
+    name: &str,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let definition: MultimapTableDefinition<Raw<K>, Raw<V>> =
+        MultimapTableDefinition::new(table_names::resolve(name));
+    let table = read_txn.open_multimap_table(definition)?;
+
+    let mut entries = vec![];
+    for (key, values) in table.iter()?.flatten() {
+        for value in values.flatten() {
+            entries.push((key.value().to_vec(), value.value().to_vec()));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Raw values stored under `key`, empty for a missing record or table
+pub fn lookup<K: Key + 'static, V: Value + 'static>(
+    read_txn: &ReadTransaction,
+    name: &str,
+    key: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    check_key_width::<K>(name, key)?;
+
+    let Some(table) = open_table::<K, V>(read_txn, name)? else {
+        return Ok(vec![]);
+    };
+
+    Ok(table
+        .get(key)?
+        .map(|value| value.value().to_vec())
+        .into_iter()
+        .collect())
+}
+
+/// Raw values of a multimap table stored under `key`, empty for a missing
+/// record or table
+pub fn lookup_multimap<K: Key + 'static, V: Key + 'static>(
+    read_txn: &ReadTransaction,
+    name: &str,
+    key: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    check_key_width::<K>(name, key)?;
+
+    let definition: MultimapTableDefinition<Raw<K>, Raw<V>> =
+        MultimapTableDefinition::new(table_names::resolve(name));
+    let table = match read_txn.open_multimap_table(definition) {
+        Ok(table) => table,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(table
+        .get(key)?
+        .flatten()
+        .map(|value| value.value().to_vec())
+        .collect())
+}
+
+fn check_key_width<K: Key>(name: &str, key: &[u8]) -> Result<()> {
+    match K::fixed_width() {
+        Some(width) if width != key.len() => Err(anyhow!(
+            "Keys of table {} are {} bytes, got {}",
+            name,
+            width,
+            key.len()
+        )),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+// redb only reports a file format it does not know as generic corruption, so
+// the version is read from the file header before redb opens it. The header
+// starts with a magic number and a god byte whose lowest bit selects the
+// primary of two commit slots, each starting with the format version.
+const MAGIC_NUMBER: [u8; 9] = [b'r', b'e', b'd', b'b', 0x1A, 0x0A, 0xA9, 0x0D, 0x0A];
+const GOD_BYTE_OFFSET: usize = MAGIC_NUMBER.len();
+const PRIMARY_BIT: u8 = 1;
+const COMMIT_SLOT_OFFSET: usize = 64;
+const COMMIT_SLOT_SIZE: usize = 128;
+const HEADER_SIZE: usize = COMMIT_SLOT_OFFSET + 2 * COMMIT_SLOT_SIZE;
+
+// File format versions the linked redb 2.6 reads
+const OLDEST_SUPPORTED: u8 = 2;
+const NEWEST_SUPPORTED: u8 = 3;
+
+/// The file format version of a redb database, `None` if the file is too
+/// short to carry a header
+fn format_version(redb_path: &Path) -> Result<Option<u8>> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    File::open(redb_path)
+        .map_err(|e| anyhow!("Could not open {:?}: {}", redb_path, e))?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+
+    if header.len() < HEADER_SIZE {
+        return Ok(None);
+    }
+
+    if header[..MAGIC_NUMBER.len()] != MAGIC_NUMBER {
+        return Err(anyhow!("{:?} is not a redb database", redb_path));
+    }
+
+    let primary_slot = (header[GOD_BYTE_OFFSET] & PRIMARY_BIT) as usize;
+    Ok(Some(header[COMMIT_SLOT_OFFSET + primary_slot * COMMIT_SLOT_SIZE]))
+}
+
+/// Fails with the detected version if the linked redb cannot read the file
+pub fn check_format(redb_path: &Path) -> Result<()> {
+    match format_version(redb_path)? {
+        Some(version) if version > NEWEST_SUPPORTED => Err(anyhow!(
+            "{:?} uses redb file format v{}, this tool reads up to v{}. It was written by a newer redb, upgrade cdk-convert-redb-to-sqlite to a release built on it.",
+            redb_path,
+            version,
+            NEWEST_SUPPORTED
+        )),
+        Some(version) if version < OLDEST_SUPPORTED => Err(anyhow!(
+            "{:?} uses redb file format v{}, this tool reads v{} to v{}. It was written by redb 1 and has to be upgraded to the redb 2 format first.",
+            redb_path,
+            version,
+            OLDEST_SUPPORTED,
+            NEWEST_SUPPORTED
+        )),
+        _ => Ok(()),
+    }
+}
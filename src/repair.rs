@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+
+use crate::output::say;
+use crate::raw::RedbReader;
+
+// A redb that was not shut down cleanly is repaired the next time it is
+// opened, and repair writes to the file. The source is never touched, so the
+// repair and a compaction run on a copy that the migration then reads.
+
+/// Copies of the source databases, removed once dropped
+pub struct RepairedSnapshot {
+    pub redb: PathBuf,
+    pub auth_redb: Option<PathBuf>,
+}
+
+impl Drop for RepairedSnapshot {
+    fn drop(&mut self) {
+        for path in std::iter::once(&self.redb).chain(&self.auth_redb) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Copies `redb` and, if given, `auth_redb` into `dir` and repairs and
+/// compacts the copies
+pub fn repaired_snapshot(
+    redb: &Path,
+    auth_redb: Option<&Path>,
+    dir: &Path,
+) -> Result<RepairedSnapshot> {
+    let snapshot = RepairedSnapshot {
+        redb: snapshot_path(redb, dir),
+        auth_redb: auth_redb.map(|auth_redb| snapshot_path(auth_redb, dir)),
+    };
+
+    repair_copy(redb, &snapshot.redb)?;
+    if let (Some(source), Some(copy)) = (auth_redb, &snapshot.auth_redb) {
+        repair_copy(source, copy)?;
+    }
+
+    Ok(snapshot)
+}
+
+fn snapshot_path(source: &Path, dir: &Path) -> PathBuf {
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
+    dir.join(format!("{}.repaired", name))
+}
+
+fn repair_copy(source: &Path, copy: &Path) -> Result<()> {
+    std::fs::copy(source, copy)
+        .map_err(|e| anyhow!("Could not copy {:?} to {:?}: {}", source, copy, e))?;
+
+    let (mut db, repaired) = RedbReader::open_repaired(copy)
+        .map_err(|e| anyhow!("Could not repair a copy of {:?}: {}", source, e))?;
+
+    if repaired {
+        say!(
+            "🔧 {:?} was not shut down cleanly, migrating from a repaired copy",
+            source
+        );
+    }
+
+    let compacted = db
+        .compact()
+        .map_err(|e| anyhow!("Could not compact a copy of {:?}: {}", source, e))?;
+    tracing::info!(
+        "Copy of {:?} {}",
+        source,
+        if compacted {
+            "compacted"
+        } else {
+            "needed no compaction"
+        }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_common::database::MintKeysDatabase;
+use cdk_redb::MintRedbDatabase;
+use cdk_sqlite::MintSqliteDatabase;
+
+use crate::checkpoint::{self, Checkpoints};
+use crate::cli::Section;
+use crate::output::say;
+use crate::paths::MigrationPaths;
+use crate::progress::NoProgress;
+use crate::throttle::Throttle;
+use crate::verify_blind_signatures::verify_blind_signatures;
+use crate::verify_migration::verify_migration;
+use crate::{meta, redb_format, sqlite};
+
+impl Section {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Info => "info",
+            Section::Keysets => "keysets",
+            Section::Quotes => "quotes",
+            Section::Proofs => "proofs",
+            Section::Signatures => "signatures",
+        }
+    }
+
+    fn tables(&self) -> &'static [&'static str] {
+        match self {
+            Section::Info => &["config"],
+            Section::Keysets => &["keyset"],
+            Section::Quotes => &["mint_quote", "melt_quote", "melt_request"],
+            Section::Proofs => &["proof"],
+            Section::Signatures => &["blind_signature"],
+        }
+    }
+}
+
+/// Re-migrates one section of an existing target from the redb
+///
+/// The section is first built into a scratch database with the regular
+/// migration steps and then swapped into the target in a single transaction.
+pub async fn replace_section(work_dir: PathBuf, section: Section) -> Result<()> {
+    let redb_path = work_dir.join("cdk-mintd.redb");
+    let sql_db_path = work_dir.join("cdk-mintd.sqlite");
+    let scratch_path = work_dir.join("cdk-mintd.sqlite.section");
+
+    if !sql_db_path.exists() {
+        return Err(anyhow!(
+            "No SQLite database at {:?} to replace the {} section in",
+            sql_db_path,
+            section.name()
+        ));
+    }
+    redb_format::check_format(&redb_path)?;
+
+    println!(
+        "Replacing the {} section of {:?}",
+        section.name(),
+        sql_db_path
+    );
+
+    sqlite::remove_database(&scratch_path)?;
+    let result = build_and_swap(&redb_path, &sql_db_path, &scratch_path, section).await;
+    sqlite::remove_database(&scratch_path)?;
+    result?;
+
+    println!("Section replaced! Starting verification...");
+
+    let paths = MigrationPaths::new(&work_dir);
+    // The checkpoints of an earlier verification are stale now
+    checkpoint::clear(&sql_db_path).await?;
+    let verified = async {
+        let mut checkpoints = Checkpoints::load(&sql_db_path).await?;
+        verify_blind_signatures(&paths, &mut checkpoints).await?;
+        verify_migration(&paths, false, &mut checkpoints, &NoProgress).await
+    }
+    .await;
+
+    match verified {
+        Ok(()) => meta::set_verification(&sql_db_path, "verified").await?,
+        Err(err) => {
+            meta::set_verification(&sql_db_path, "failed").await?;
+            return Err(err);
+        }
+    }
+
+    say!(
+        "\n🎉 The {} section was replaced and verified",
+        section.name()
+    );
+
+    Ok(())
+}
+
+async fn build_and_swap(
+    redb_path: &Path,
+    sql_db_path: &Path,
+    scratch_path: &Path,
+    section: Section,
+) -> Result<()> {
+    {
+        let scratch_db = MintSqliteDatabase::new(scratch_path).await?;
+
+        if section == Section::Signatures {
+            crate::migrate_blind_signatures(
+                redb_path,
+                &scratch_db,
+                false,
+                &NoProgress,
+                &Throttle::default(),
+            )
+            .await?;
+        } else {
+            let redb_db = MintRedbDatabase::new(redb_path)?;
+
+            match section {
+                Section::Info => crate::migrate_mint_info(&redb_db, &scratch_db).await?,
+                Section::Quotes => {
+                    crate::migrate_quotes(&redb_db, &scratch_db, &NoProgress, &Throttle::default())
+                        .await?
+                }
+                Section::Keysets => {
+                    for keyset in redb_db.get_keyset_infos().await? {
+                        scratch_db.add_keyset_info(keyset).await?;
+                    }
+                }
+                Section::Proofs => {
+                    let keyset_ids = redb_db
+                        .get_keyset_infos()
+                        .await?
+                        .into_iter()
+                        .map(|keyset| keyset.id)
+                        .collect();
+                    crate::migrate_proofs(
+                        keyset_ids,
+                        &redb_db,
+                        &scratch_db,
+                        false,
+                        &NoProgress,
+                        None,
+                        false,
+                        &Throttle::default(),
+                    )
+                    .await?;
+                }
+                Section::Signatures => unreachable!(),
+            }
+        }
+    }
+
+    let pool = sqlite::connect(sql_db_path).await?;
+    sqlx::query("ATTACH DATABASE ? AS scratch")
+        .bind(scratch_path.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+
+    let mut tx = pool.begin().await?;
+    for table in section.tables() {
+        let removed = sqlx::query(&format!("DELETE FROM main.{}", table))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        let inserted = sqlx::query(&format!(
+            "INSERT INTO main.{table} SELECT * FROM scratch.{table}",
+            table = table
+        ))
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tracing::info!("Replaced {} rows of {} with {}", removed, table, inserted);
+        println!(
+            "  {}: {} rows removed, {} rows written",
+            table, removed, inserted
+        );
+    }
+    tx.commit().await?;
+
+    sqlx::query("DETACH DATABASE scratch")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    meta::set_value(
+        sql_db_path,
+        &format!("replaced_section.{}", section.name()),
+        &meta::unix_time().to_string(),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+
+use crate::cleanup::{archive_path, restore_file};
+use crate::marker;
+use crate::meta;
+use crate::output::say;
+use crate::paths::{MigrationPaths, resolve_link, with_suffix};
+use crate::sqlite;
+
+// The migration never moves or writes the source redb, so undoing it means
+// removing the SQLite databases it produced and the migrated marker, and
+// unpacking the redb again if `cleanup` archived it. The SQLite files are
+// only removed while they are byte for byte what the migration left,
+// otherwise mintd has already written to them and removing them would lose
+// those records.
+
+pub async fn rollback(work_dir: PathBuf, discard_changes: bool) -> Result<()> {
+    let paths = MigrationPaths::new(&work_dir);
+
+    let Some(marker) = marker::read_marker(&paths.redb)? else {
+        return Err(anyhow!(
+            "{:?} has no migrated marker, there is no migration to roll back",
+            paths.redb
+        ));
+    };
+    // Removing a merged target would also remove every row it held before
+    if marker.merged {
+        return Err(anyhow!(
+            "{:?} was merged into {:?}, which held data of its own. Rolling back would remove that data too, restore the target from a backup instead.",
+            paths.redb,
+            marker.target
+        ));
+    }
+    let restore = !paths.redb.exists();
+    if restore && !archive_path(&paths.redb).exists() {
+        return Err(anyhow!(
+            "The source {:?} is gone and was not archived, rolling back would leave the mint without a database",
+            paths.redb
+        ));
+    }
+
+    say!(
+        "🔍 Rolling back the migration of {:?} to {:?} from unix time {}",
+        paths.redb,
+        marker.target,
+        marker.migrated_at
+    );
+
+    // A named output's auth database is only known by its link
+    let auth_target = resolve_link(&paths.auth_sqlite);
+    let targets = [
+        (&marker.target, marker.target_sha256.as_deref()),
+        (&auth_target, marker.auth_target_sha256.as_deref()),
+    ];
+    let mut changed = 0;
+    for (target, expected) in targets {
+        if !target.exists() {
+            continue;
+        }
+
+        match check_unchanged(target, expected)? {
+            None => say!("✅ {:?} is unchanged since the migration", target),
+            Some(reason) => {
+                changed += 1;
+                say!("❌ {:?} {}", target, reason);
+            }
+        }
+    }
+
+    if changed > 0 && !discard_changes {
+        return Err(anyhow!(
+            "Not rolling back, {} SQLite database(s) may hold records mintd wrote after the switch. Use --discard-changes to remove them anyway.",
+            changed
+        ));
+    }
+
+    if restore {
+        restore_file(
+            &archive_path(&paths.redb),
+            &paths.redb,
+            Some(&marker.source_sha256),
+        )?;
+        let auth_archive = archive_path(&paths.auth_redb);
+        if auth_archive.exists() {
+            restore_file(&auth_archive, &paths.auth_redb, None)?;
+            std::fs::remove_file(auth_archive)?;
+        }
+        std::fs::remove_file(archive_path(&paths.redb))?;
+        say!("📋 Restored {:?} from its archive", paths.redb);
+    }
+
+    for (target, _) in targets {
+        sqlite::remove_database(target)?;
+    }
+    for link in [&paths.sqlite, &paths.auth_sqlite] {
+        if link.is_symlink() && !link.exists() {
+            std::fs::remove_file(link)?;
+        }
+    }
+    marker::remove_marker(&paths.redb)?;
+
+    say!(
+        "\n✅ Rolled back, {:?} is the mint's database again. Switch mintd back to the redb engine before starting it.",
+        paths.redb
+    );
+
+    Ok(())
+}
+
+/// Moves SQLite databases that failed `--verify-after` out of the way, to
+/// `<target>.failed`, so mintd cannot be started on them but they can still
+/// be investigated
+pub async fn set_aside_failed(targets: &[&Path]) -> Result<()> {
+    for target in targets {
+        if !target.exists() {
+            continue;
+        }
+
+        let failed = with_suffix(target, ".failed");
+        sqlite::remove_database(&failed)?;
+        sqlite::rename_database(target, &failed).await?;
+        say!("⚠️  Set {:?} aside as {:?}", target, failed);
+    }
+
+    Ok(())
+}
+
+/// Why the database differs from the one the migration produced, if it does
+pub fn check_unchanged(path: &Path, expected: Option<&str>) -> Result<Option<String>> {
+    let Some(expected) = expected else {
+        return Ok(Some(
+            "has no checksum in the migrated marker, it was merged into or migrated by an older version".to_string(),
+        ));
+    };
+
+    // Committed transactions mintd has not checkpointed yet
+    let wal = with_suffix(path, "-wal");
+    if wal.exists() && std::fs::metadata(&wal)?.len() > 0 {
+        return Ok(Some("has a write-ahead log with changes".to_string()));
+    }
+
+    if meta::sha256_file(path)? != expected {
+        return Ok(Some("changed since the migration".to_string()));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+use crate::progress::{Phase, count_records};
+use crate::sqlite;
+
+// Verification compares every record, but only once everything is written,
+// and not at all with --skip-verify. A write that silently stored fewer rows
+// than it was given, e.g. proofs of a keyset missing from the keysets table,
+// is caught right after its phase instead: the rows in SQLite are counted and
+// compared with the records the redb held. The redb is counted before
+// cdk-redb opens it, since a process can open a redb file only once.
+
+// The redb tables each phase migrates, with the SQLite table they go to
+fn phase_tables(phase: Phase) -> &'static [(&'static str, &'static str)] {
+    match phase {
+        Phase::Quotes => &[("mint_quotes", "mint_quote"), ("melt_quotes", "melt_quote")],
+        Phase::Keysets => &[("keysets", "keyset")],
+        Phase::Proofs => &[("proofs", "proof")],
+        Phase::BlindSignatures => &[("blinded_signatures", "blind_signature")],
+        // Endpoints without an auth requirement cannot be stored, so they
+        // are not counted
+        Phase::Auth => &[
+            ("keysets", "keyset"),
+            ("proofs", "proof"),
+            ("blinded_signatures", "blind_signature"),
+        ],
+        Phase::MintInfo | Phase::Verification => &[],
+    }
+}
+
+/// Records in the redb tables the migration reads
+pub struct SourceCounts(HashMap<&'static str, Option<u64>>);
+
+impl SourceCounts {
+    pub fn read(redb_path: &Path, phases: &[Phase]) -> Self {
+        let counts = phases
+            .iter()
+            .flat_map(|phase| phase_tables(*phase))
+            .map(|(table, _)| (*table, count_records(redb_path, table)))
+            .collect();
+        Self(counts)
+    }
+
+    /// Fails if the SQLite tables `phase` wrote hold other than the records
+    /// read from the redb, less the `collapsed` duplicate proofs
+    pub async fn check_phase(
+        &self,
+        phase: Phase,
+        sql_db_path: &Path,
+        collapsed: u64,
+    ) -> Result<()> {
+        let pool = sqlite::connect(sql_db_path).await?;
+
+        for (redb_table, sqlite_table) in phase_tables(phase) {
+            let Some(Some(records)) = self.0.get(redb_table) else {
+                tracing::warn!(
+                    "Cannot count the {} records of the redb, not checking the {} rows written",
+                    redb_table,
+                    sqlite_table
+                );
+                continue;
+            };
+            let expected = match *sqlite_table {
+                "proof" => records.saturating_sub(collapsed),
+                _ => *records,
+            };
+
+            let (rows,): (i64,) = sqlx::query_as(&format!("SELECT count(*) FROM {}", sqlite_table))
+                .fetch_one(&pool)
+                .await?;
+            if rows as u64 != expected {
+                pool.close().await;
+                return Err(anyhow!(
+                    "The {} phase wrote {} {} row(s) to {:?} but the redb holds {} {} record(s), aborting",
+                    phase,
+                    rows,
+                    sqlite_table,
+                    sql_db_path,
+                    expected,
+                    redb_table
+                ));
+            }
+            tracing::info!(
+                "{} {} row(s) written for {} {} record(s)",
+                rows,
+                sqlite_table,
+                expected,
+                redb_table
+            );
+        }
+        pool.close().await;
+
+        Ok(())
+    }
+}
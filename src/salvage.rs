@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use cdk_common::common::{PaymentProcessorKey, QuoteTTL};
+use cdk_common::database::{
+    MintDatabase, MintKeysDatabase, MintProofsDatabase, MintQuotesDatabase, MintSignaturesDatabase,
+};
+use cdk_common::mint::{MeltQuote, MintKeySetInfo, MintQuote};
+use cdk_common::nuts::{CurrencyUnit, Id, MeltRequest, MintInfo};
+use cdk_common::util::hex;
+use cdk_common::{BlindSignature, Proof, PublicKey, State};
+use cdk_sqlite::MintSqliteDatabase;
+use futures::FutureExt;
+use redb::{Key, ReadTransaction, Value};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::batch::panic_message;
+use crate::mint_info::normalize_mint_info;
+use crate::output::say;
+use crate::raw::{RedbReader, salvage_table};
+
+// A salvage migration reads the redb tables raw instead of through
+// MintRedbDatabase and keeps every record that still parses. Records that
+// do not parse or are refused by cdk-sqlite are skipped, stretches of a
+// table that cannot be read at all are stepped over, and every loss is
+// recorded for the report.
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct TableCounts {
+    pub recovered: u64,
+    pub skipped: u64,
+    // Damaged stretches, how many records they held is unknown
+    pub unreadable: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Loss {
+    pub table: &'static str,
+    // Hex key of a skipped record, none for an unreadable stretch
+    pub key: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SalvageReport {
+    pub tables: BTreeMap<&'static str, TableCounts>,
+    pub losses: Vec<Loss>,
+}
+
+impl SalvageReport {
+    fn recovered(&mut self, table: &'static str) {
+        self.tables.entry(table).or_default().recovered += 1;
+    }
+
+    fn skipped(&mut self, table: &'static str, key: &[u8], reason: impl std::fmt::Display) {
+        tracing::warn!("Skipping {} record {}: {}", table, hex::encode(key), reason);
+        self.tables.entry(table).or_default().skipped += 1;
+        self.losses.push(Loss {
+            table,
+            key: Some(hex::encode(key)),
+            reason: reason.to_string(),
+        });
+    }
+
+    // Reads what is left of a table, recording the stretches it had to skip
+    fn read<K: Key + 'static, V: Value + 'static>(
+        &mut self,
+        read_txn: &ReadTransaction,
+        table: &'static str,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let salvaged = salvage_table::<K, V>(read_txn, table);
+        for error in salvaged.errors {
+            tracing::warn!("Unreadable records in {}: {}", table, error);
+            self.tables.entry(table).or_default().unreadable += 1;
+            self.losses.push(Loss {
+                table,
+                key: None,
+                reason: format!("unreadable records: {}", error),
+            });
+        }
+
+        salvaged.entries
+    }
+
+    fn parse<T: DeserializeOwned>(
+        &mut self,
+        table: &'static str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<T> {
+        match serde_json::from_slice(value) {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                self.skipped(table, key, err);
+                None
+            }
+        }
+    }
+
+    fn insert<E: std::fmt::Display>(
+        &mut self,
+        table: &'static str,
+        key: &[u8],
+        result: Result<(), E>,
+    ) {
+        match result {
+            Ok(()) => self.recovered(table),
+            Err(err) => self.skipped(table, key, err),
+        }
+    }
+
+    pub fn skipped_total(&self) -> u64 {
+        self.tables.values().map(|counts| counts.skipped).sum()
+    }
+
+    pub fn unreadable_total(&self) -> u64 {
+        self.tables.values().map(|counts| counts.unreadable).sum()
+    }
+}
+
+/// An error reading the redb. Only these make a migration fall back to a
+/// salvage migration, an error writing SQLite or a mismatch in the rows
+/// written is passed on as is.
+#[derive(Debug)]
+pub struct RedbReadError(anyhow::Error);
+
+impl fmt::Display for RedbReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for RedbReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Marks an error as raised reading the redb
+pub fn read_error(err: impl Into<anyhow::Error>) -> anyhow::Error {
+    let err = err.into();
+    if is_read_error(&err) {
+        return err;
+    }
+    RedbReadError(err).into()
+}
+
+pub fn is_read_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<RedbReadError>().is_some()
+}
+
+/// Reads from the redb, with its errors and the panics redb raises on some
+/// damaged pages turned into read errors
+pub async fn reading<T, E: Into<anyhow::Error>>(
+    read: impl Future<Output = Result<T, E>>,
+) -> Result<T> {
+    match AssertUnwindSafe(read).catch_unwind().await {
+        Ok(result) => result.map_err(read_error),
+        Err(panic) => Err(read_error(anyhow!(
+            "redb panicked: {}",
+            panic_message(&*panic)
+        ))),
+    }
+}
+
+/// Like [`reading`], for reads of the raw tables
+pub fn reading_sync<T>(read: impl FnOnce() -> Result<T>) -> Result<T> {
+    match std::panic::catch_unwind(AssertUnwindSafe(read)) {
+        Ok(result) => result.map_err(read_error),
+        Err(panic) => Err(read_error(anyhow!(
+            "redb panicked: {}",
+            panic_message(&*panic)
+        ))),
+    }
+}
+
+/// Migrates whatever can still be read from the raw redb tables
+pub async fn salvage(redb_path: &Path, sqlite_db: &MintSqliteDatabase) -> Result<SalvageReport> {
+    let db = RedbReader::open(redb_path)?;
+    let read_txn = db.begin_read()?;
+    let mut report = SalvageReport::default();
+
+    for (key, value) in report.read::<&str, &str>(&read_txn, "config") {
+        match key.as_slice() {
+            b"mint_info" => {
+                if let Some(info) = report.parse::<MintInfo>("config", &key, &value) {
+                    let info = normalize_mint_info(&info).unwrap_or(info);
+                    let result = sqlite_db.set_mint_info(info).await;
+                    report.insert("config", &key, result);
+                }
+            }
+            b"quote_ttl" => {
+                if let Some(ttl) = report.parse::<QuoteTTL>("config", &key, &value) {
+                    let result = sqlite_db.set_quote_ttl(ttl).await;
+                    report.insert("config", &key, result);
+                }
+            }
+            // The schema version of redb only, SQLite tracks its own
+            b"db_version" => (),
+            _ => report.skipped("config", &key, "unknown config key"),
+        }
+    }
+
+    for (key, value) in report.read::<&str, &str>(&read_txn, "keysets") {
+        if let Some(keyset) = report.parse::<MintKeySetInfo>("keysets", &key, &value) {
+            let result = sqlite_db.add_keyset_info(keyset).await;
+            report.insert("keysets", &key, result);
+        }
+    }
+
+    for (key, value) in report.read::<&str, &str>(&read_txn, "active_keysets") {
+        let unit = CurrencyUnit::from_str(&String::from_utf8_lossy(&key));
+        let id = Id::from_str(&String::from_utf8_lossy(&value));
+        match (unit, id) {
+            (Ok(unit), Ok(id)) => {
+                let result = sqlite_db.set_active_keyset(unit, id).await;
+                report.insert("active_keysets", &key, result);
+            }
+            _ => report.skipped("active_keysets", &key, "invalid unit or keyset id"),
+        }
+    }
+
+    for (key, value) in report.read::<[u8; 16], &str>(&read_txn, "mint_quotes") {
+        if let Some(quote) = report.parse::<MintQuote>("mint_quotes", &key, &value) {
+            let result = sqlite_db.add_mint_quote(quote).await;
+            report.insert("mint_quotes", &key, result);
+        }
+    }
+
+    for (key, value) in report.read::<[u8; 16], &str>(&read_txn, "melt_quotes") {
+        if let Some(quote) = report.parse::<MeltQuote>("melt_quotes", &key, &value) {
+            let result = sqlite_db.add_melt_quote(quote).await;
+            report.insert("melt_quotes", &key, result);
+        }
+    }
+
+    for (key, value) in report.read::<[u8; 16], (&str, &str)>(&read_txn, "melt_requests") {
+        let (request, payment_key) = <(&str, &str) as Value>::from_bytes(&value);
+        let request = serde_json::from_str::<MeltRequest<Uuid>>(request);
+        let payment_key = serde_json::from_str::<PaymentProcessorKey>(payment_key);
+        match (request, payment_key) {
+            (Ok(request), Ok(payment_key)) => {
+                let result = sqlite_db.add_melt_request(request, payment_key).await;
+                report.insert("melt_requests", &key, result);
+            }
+            (Err(err), _) | (_, Err(err)) => report.skipped("melt_requests", &key, err),
+        }
+    }
+
+    let losses = report.losses.len();
+    let states: HashMap<Vec<u8>, Vec<u8>> = report
+        .read::<[u8; 33], &str>(&read_txn, "proofs_state")
+        .into_iter()
+        .collect();
+    // Unspent proofs have no state entry, with part of the states lost a
+    // missing entry no longer means unspent
+    let states_damaged = report.losses.len() > losses;
+
+    for (key, value) in report.read::<[u8; 33], &str>(&read_txn, "proofs") {
+        let Some(proof) = report.parse::<Proof>("proofs", &key, &value) else {
+            continue;
+        };
+
+        // A spent proof must never come back as unspent, so a state that no
+        // longer parses is taken to be spent
+        let state = match states.get(&key) {
+            Some(state) => serde_json::from_slice::<State>(state).unwrap_or_else(|err| {
+                tracing::warn!(
+                    "Unreadable state of proof {}, marking it spent: {}",
+                    hex::encode(&key),
+                    err
+                );
+                State::Spent
+            }),
+            None if states_damaged => {
+                tracing::warn!(
+                    "No readable state for proof {}, marking it pending",
+                    hex::encode(&key)
+                );
+                State::Pending
+            }
+            None => State::Unspent,
+        };
+
+        let result = match sqlite_db.add_proofs(vec![proof.clone()], None).await {
+            Ok(()) if matches!(state, State::Spent | State::Pending) => sqlite_db
+                .update_proofs_states(&[proof.y()?], state)
+                .await
+                .map(|_| ()),
+            result => result,
+        };
+        report.insert("proofs", &key, result);
+    }
+
+    for (key, value) in report.read::<[u8; 33], &str>(&read_txn, "blinded_signatures") {
+        let Some(signature) = report.parse::<BlindSignature>("blinded_signatures", &key, &value)
+        else {
+            continue;
+        };
+
+        match PublicKey::from_slice(&key) {
+            Ok(message) => {
+                let result = sqlite_db
+                    .add_blind_signatures(&[message], &[signature], None)
+                    .await;
+                report.insert("blinded_signatures", &key, result);
+            }
+            Err(err) => report.skipped("blinded_signatures", &key, err),
+        }
+    }
+
+    Ok(report)
+}
+
+const MAX_LOSSES_SHOWN: usize = 20;
+
+pub fn print_report(report: &SalvageReport) {
+    say!("\n⚠️  === SALVAGE MIGRATION ===");
+    say!(
+        "The source could not be read as a whole, only the records that still parse were migrated."
+    );
+    say!("The result was not verified against the source.");
+    say!(
+        "{:<20} {:>10} {:>10} {:>10}",
+        "redb table",
+        "recovered",
+        "skipped",
+        "unreadable"
+    );
+    for (table, counts) in &report.tables {
+        say!(
+            "{:<20} {:>10} {:>10} {:>10}",
+            table,
+            counts.recovered,
+            counts.skipped,
+            counts.unreadable
+        );
+    }
+    say!(
+        "{} record(s) skipped, {} unreadable stretch(es) in total",
+        report.skipped_total(),
+        report.unreadable_total()
+    );
+
+    for loss in report.losses.iter().take(MAX_LOSSES_SHOWN) {
+        say!(
+            "  {} {}: {}",
+            loss.table,
+            loss.key.as_deref().unwrap_or("(unreadable)"),
+            loss.reason
+        );
+    }
+    if report.losses.len() > MAX_LOSSES_SHOWN {
+        say!("  ... and {} more", report.losses.len() - MAX_LOSSES_SHOWN);
+    }
+}
+
+/// Writes every loss of the salvage next to the migrated database
+pub fn write_report(report: &SalvageReport, path: &Path) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(report)?)
+        .map_err(|e| anyhow!("Could not write salvage report {:?}: {}", path, e))?;
+    say!("📋 Salvage loss report written to {:?}", path);
+    Ok(())
+}
@@ -0,0 +1,323 @@
+#[cfg(feature = "auth")]
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use cdk_common::bitcoin::bip32::DerivationPath;
+use cdk_common::bitcoin::hashes::{Hash, sha256};
+use cdk_common::bitcoin::secp256k1::{self, Secp256k1};
+use cdk_common::common::{PaymentProcessorKey, QuoteTTL};
+#[cfg(feature = "auth")]
+use cdk_common::database::MintAuthDatabase;
+use cdk_common::database::{
+    MintDatabase, MintKeysDatabase, MintProofsDatabase, MintQuotesDatabase, MintSignaturesDatabase,
+};
+use cdk_common::lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+use cdk_common::mint::{MeltQuote, MintKeySetInfo, MintQuote};
+use cdk_common::nuts::nut11::SpendingConditions;
+#[cfg(feature = "auth")]
+use cdk_common::nuts::{AuthProof, AuthRequired, Method, ProtectedEndpoint, RoutePath};
+use cdk_common::nuts::{
+    BlindSignature, CurrencyUnit, Id, MeltQuoteState, MeltRequest, MintInfo, MintQuoteState,
+    PaymentMethod, Proof, SecretKey, State,
+};
+use cdk_common::secret::Secret;
+use cdk_common::{Amount, nuts};
+use cdk_redb::MintRedbDatabase;
+#[cfg(feature = "auth")]
+use cdk_redb::mint::MintRedbAuthDatabase;
+use uuid::Uuid;
+
+use crate::cli::{MigrateArgs, OutputFormat};
+use crate::output::say;
+use crate::progress::NoProgress;
+
+// Proofs and signatures written per keyset
+const ROWS_PER_KEYSET: u64 = 24;
+
+// The self-test migrates a mint written by the linked cdk-redb, touching
+// every table the migration reads, with the same checks a real migration
+// runs. It needs no work dir, so packagers can run it right after building.
+
+pub async fn self_test() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("cdk-convert-self-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| anyhow!("Could not create self-test dir {:?}: {}", dir, e))?;
+
+    say!("🧪 Writing a sample redb mint to {:?}", dir);
+    let migrated = async {
+        write_mint(&dir.join("cdk-mintd.redb")).await?;
+        #[cfg(feature = "auth")]
+        write_auth(&dir.join("cdk-mintd-auth.redb")).await?;
+        crate::migrate_with_progress(dir.clone(), migrate_args(), Arc::new(NoProgress)).await
+    }
+    .await;
+
+    match migrated {
+        Ok(()) => {
+            let _ = std::fs::remove_dir_all(&dir);
+            say!("\n✅ Self-test passed");
+            Ok(())
+        }
+        // Left in place so the failure can be looked into
+        Err(err) => Err(anyhow!(
+            "Self-test failed, its databases are kept in {:?}: {}",
+            dir,
+            err
+        )),
+    }
+}
+
+fn migrate_args() -> MigrateArgs {
+    MigrateArgs {
+        post_sql: None,
+        force: false,
+        resume: false,
+        wipe: false,
+        replace_section: None,
+        auth_only: false,
+        auth_redb: None,
+        auth_sqlite: None,
+        fsync: false,
+        salvage: false,
+        repair: false,
+        merge: false,
+        on_conflict: vec![],
+        smoke_test: true,
+        verify_after: false,
+        wait_for_lock: None,
+        low_memory: false,
+        tmp_dir: None,
+        chmod: None,
+        chown: None,
+        status_addr: None,
+        shred_source: false,
+        output_name: None,
+        timestamped_output: false,
+        compact: false,
+        validate_proofs: false,
+        rate_limit: None,
+        skip_space_check: false,
+        progress_socket: None,
+        backup_encrypt: None,
+        source_archive: None,
+        compress_output: false,
+        strict: false,
+        deny: vec![],
+        allow: vec![],
+        output_format: OutputFormat::Sqlite,
+        audit_log: None,
+    }
+}
+
+fn keyset(id: &str, unit: CurrencyUnit, index: u32, input_fee_ppk: u64) -> Result<MintKeySetInfo> {
+    Ok(MintKeySetInfo {
+        id: Id::from_str(id)?,
+        unit,
+        active: true,
+        valid_from: 1_700_000_000,
+        valid_to: None,
+        derivation_path: DerivationPath::from_str(&format!("m/0'/0'/{}'", index))?,
+        derivation_path_index: Some(index),
+        max_order: 32,
+        input_fee_ppk,
+    })
+}
+
+fn proof(keyset_id: Id, amount: u64, p2pk: bool) -> Result<Proof> {
+    let secret = if p2pk {
+        let conditions = SpendingConditions::new_p2pk(SecretKey::generate().public_key(), None);
+        Secret::try_from(nuts::nut10::Secret::from(conditions))?
+    } else {
+        Secret::generate()
+    };
+
+    Ok(Proof::new(
+        Amount::from(amount),
+        keyset_id,
+        secret,
+        SecretKey::generate().public_key(),
+    ))
+}
+
+fn signature(keyset_id: Id, amount: u64) -> BlindSignature {
+    BlindSignature {
+        amount: Amount::from(amount),
+        keyset_id,
+        c: SecretKey::generate().public_key(),
+        dleq: None,
+    }
+}
+
+// A signed invoice, so the request keeps its bolt11 shape through the checks
+fn invoice(seed: u8, amount_msat: u64) -> Result<String> {
+    let secp = Secp256k1::new();
+    let key = secp256k1::SecretKey::from_slice(&[seed.max(1); 32])?;
+    let invoice = InvoiceBuilder::new(Currency::Bitcoin)
+        .description("self-test".to_string())
+        .amount_milli_satoshis(amount_msat)
+        .payment_hash(sha256::Hash::hash(&[seed]))
+        .payment_secret(PaymentSecret([seed; 32]))
+        .duration_since_epoch(Duration::from_secs(1_720_000_000))
+        .min_final_cltv_expiry_delta(144)
+        .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &key))?;
+
+    Ok(invoice.to_string())
+}
+
+async fn write_mint(path: &Path) -> Result<()> {
+    let db = MintRedbDatabase::new(path)?;
+    db.set_mint_info(MintInfo::new().name("Self-test mint"))
+        .await?;
+    db.set_quote_ttl(QuoteTTL::new(600, 1200)).await?;
+
+    let retired = MintKeySetInfo {
+        active: false,
+        ..keyset("009a1f293253e41e", CurrencyUnit::Sat, 0, 0)?
+    };
+    let active = keyset("00456a94ab4e1c46", CurrencyUnit::Sat, 1, 100)?;
+    for info in [&retired, &active] {
+        db.add_keyset_info(info.clone()).await?;
+    }
+    db.set_active_keyset(CurrencyUnit::Sat, active.id).await?;
+
+    // Every state a proof can be in, with and without spending conditions
+    for keyset_id in [retired.id, active.id] {
+        let proofs = (0..ROWS_PER_KEYSET)
+            .map(|i| proof(keyset_id, 1 << (i % 8), i % 5 == 0))
+            .collect::<Result<Vec<_>>>()?;
+        let ys = proofs
+            .iter()
+            .map(|proof| proof.y())
+            .collect::<Result<Vec<_>, _>>()?;
+        db.add_proofs(proofs, None).await?;
+        db.update_proofs_states(
+            &ys.iter().step_by(2).copied().collect::<Vec<_>>(),
+            State::Spent,
+        )
+        .await?;
+        db.update_proofs_states(
+            &ys.iter().skip(1).step_by(4).copied().collect::<Vec<_>>(),
+            State::Pending,
+        )
+        .await?;
+
+        let messages: Vec<_> = (0..ROWS_PER_KEYSET)
+            .map(|_| SecretKey::generate().public_key())
+            .collect();
+        let signatures: Vec<_> = (0..ROWS_PER_KEYSET)
+            .map(|i| signature(keyset_id, 1 << (i % 8)))
+            .collect();
+        db.add_blind_signatures(&messages, &signatures, None)
+            .await?;
+    }
+
+    let mint_states = [
+        MintQuoteState::Unpaid,
+        MintQuoteState::Paid,
+        MintQuoteState::Issued,
+    ];
+    for (i, state) in mint_states.into_iter().enumerate() {
+        let amount = 100 + i as u64;
+        let mut quote = MintQuote::new(
+            invoice(i as u8 + 1, amount * 1000)?,
+            CurrencyUnit::Sat,
+            Amount::from(amount),
+            1_900_000_000,
+            format!("self-test-mint-{}", i),
+            None,
+        );
+        quote.state = state;
+        quote.created_time = 1_717_000_000 + i as u64 * 86_400;
+        if state != MintQuoteState::Unpaid {
+            quote.paid_time = Some(quote.created_time + 60);
+        }
+        if state == MintQuoteState::Issued {
+            quote.issued_time = Some(quote.created_time + 120);
+        }
+        db.add_mint_quote(quote).await?;
+    }
+
+    let melt_states = [
+        MeltQuoteState::Unpaid,
+        MeltQuoteState::Pending,
+        MeltQuoteState::Paid,
+    ];
+    for (i, state) in melt_states.into_iter().enumerate() {
+        let amount = 200 + i as u64;
+        let mut quote = MeltQuote::new(
+            invoice(i as u8 + 10, amount * 1000)?,
+            CurrencyUnit::Sat,
+            Amount::from(amount),
+            Amount::from(2u64),
+            1_900_000_000,
+            format!("self-test-melt-{}", i),
+            None,
+        );
+        quote.state = state;
+        quote.created_time = 1_717_500_000 + i as u64 * 86_400;
+        if state == MeltQuoteState::Paid {
+            quote.paid_time = Some(quote.created_time + 30);
+            quote.payment_preimage = Some("00".repeat(32));
+        }
+        let id = quote.id;
+        db.add_melt_quote(quote).await?;
+
+        let request = MeltRequest::<Uuid>::new(id, vec![proof(active.id, 256, false)?], None);
+        db.add_melt_request(
+            request,
+            PaymentProcessorKey::new(CurrencyUnit::Sat, PaymentMethod::Bolt11),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "auth")]
+async fn write_auth(path: &Path) -> Result<()> {
+    let db = MintRedbAuthDatabase::new(path)?;
+
+    let keyset = keyset("00b4cd27d8861a44", CurrencyUnit::Auth, 0, 0)?;
+    db.add_keyset_info(keyset.clone()).await?;
+    db.set_active_keyset(keyset.id).await?;
+
+    for i in 0..ROWS_PER_KEYSET {
+        let proof = AuthProof {
+            keyset_id: keyset.id,
+            secret: Secret::generate(),
+            c: SecretKey::generate().public_key(),
+            dleq: None,
+        };
+        let y = proof.y()?;
+        db.add_proof(proof).await?;
+        if i % 2 == 0 {
+            db.update_proof_state(&y, State::Spent).await?;
+        }
+    }
+
+    let messages: Vec<_> = (0..ROWS_PER_KEYSET)
+        .map(|_| SecretKey::generate().public_key())
+        .collect();
+    let signatures: Vec<_> = (0..ROWS_PER_KEYSET)
+        .map(|_| signature(keyset.id, 1))
+        .collect();
+    db.add_blind_signatures(&messages, &signatures).await?;
+
+    let endpoints = HashMap::from([
+        (
+            ProtectedEndpoint::new(Method::Post, RoutePath::Swap),
+            AuthRequired::Blind,
+        ),
+        (
+            ProtectedEndpoint::new(Method::Post, RoutePath::MintQuoteBolt11),
+            AuthRequired::Clear,
+        ),
+    ]);
+    db.add_protected_endpoints(endpoints).await?;
+
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::batch::panic_message;
+use crate::cli::{MigrateArgs, OutputFormat};
+use crate::meta;
+use crate::output::say;
+use crate::paths::MigrationPaths;
+use crate::progress::{ProgressRecorder, ProgressState};
+
+pub mod proto {
+    tonic::include_proto!("migration");
+}
+
+use proto::migration_server::{Migration, MigrationServer};
+use proto::{
+    GetReportRequest, GetStatusRequest, MetaEntry, MigrationReport, MigrationState,
+    MigrationStatus, StartMigrationRequest, StartMigrationResponse,
+};
+
+/// Serves the migration gRPC API until interrupted
+pub async fn serve(addr: SocketAddr, root: &Path, insecure: bool) -> Result<()> {
+    // The API has no authentication, so it is only reachable from other
+    // hosts when the operator asks for it
+    if !addr.ip().is_loopback() && !insecure {
+        return Err(anyhow!(
+            "{} is not a loopback address and the API has no authentication, pass --insecure to listen on it anyway",
+            addr
+        ));
+    }
+    let root = root
+        .canonicalize()
+        .map_err(|err| anyhow!("Could not resolve --root {:?}: {}", root, err))?;
+
+    say!(
+        "🛰️  Serving the migration API on {}, for work dirs in {:?}",
+        addr,
+        root
+    );
+
+    let service = MigrationService {
+        root,
+        migrations: Mutex::default(),
+    };
+    tonic::transport::Server::builder()
+        .add_service(MigrationServer::new(service))
+        .serve_with_shutdown(addr, async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await?;
+
+    Ok(())
+}
+
+struct RunningMigration {
+    work_dir: PathBuf,
+    progress: Arc<Mutex<ProgressState>>,
+}
+
+struct MigrationService {
+    root: PathBuf,
+    migrations: Mutex<HashMap<String, RunningMigration>>,
+}
+
+impl MigrationService {
+    /// The work dir of a request, resolved so a `..` or a symlink cannot
+    /// lead out of the root
+    fn work_dir(&self, work_dir: &str) -> Result<PathBuf> {
+        let work_dir = self
+            .root
+            .join(work_dir)
+            .canonicalize()
+            .map_err(|err| anyhow!("{}: {}", work_dir, err))?;
+        if !work_dir.starts_with(&self.root) {
+            return Err(anyhow!("{:?} is not in {:?}", work_dir, self.root));
+        }
+
+        Ok(work_dir)
+    }
+
+    fn progress(&self, migration_id: &str) -> Option<(PathBuf, Arc<Mutex<ProgressState>>)> {
+        let migrations = self.migrations.lock().expect("migrations lock");
+        let migration = migrations.get(migration_id)?;
+
+        Some((migration.work_dir.clone(), migration.progress.clone()))
+    }
+}
+
+#[tonic::async_trait]
+impl Migration for MigrationService {
+    async fn start_migration(
+        &self,
+        request: Request<StartMigrationRequest>,
+    ) -> Result<Response<StartMigrationResponse>, Status> {
+        let request = request.into_inner();
+        if request.work_dir.is_empty() {
+            return Err(Status::invalid_argument("work_dir is required"));
+        }
+        let work_dir = self
+            .work_dir(&request.work_dir)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let mut migrations = self.migrations.lock().expect("migrations lock");
+        let busy = migrations.values().any(|migration| {
+            migration.work_dir == work_dir
+                && migration
+                    .progress
+                    .lock()
+                    .expect("progress lock")
+                    .outcome
+                    .is_none()
+        });
+        if busy {
+            return Err(Status::already_exists(format!(
+                "{:?} is already being migrated",
+                work_dir
+            )));
+        }
+
+        let args = MigrateArgs {
+            post_sql: None,
+            force: request.force,
+            resume: request.resume,
+            wipe: request.wipe,
+            replace_section: None,
+            auth_only: false,
+            auth_redb: None,
+            auth_sqlite: None,
+            fsync: request.fsync,
+            salvage: false,
+            repair: false,
+            merge: false,
+            on_conflict: vec![],
+            smoke_test: false,
+            verify_after: false,
+            wait_for_lock: request.wait_for_lock,
+            low_memory: request.low_memory,
+            tmp_dir: None,
+            chmod: None,
+            chown: None,
+            status_addr: None,
+            shred_source: false,
+            output_name: None,
+            timestamped_output: false,
+            compact: false,
+            validate_proofs: false,
+            rate_limit: None,
+            skip_space_check: false,
+            progress_socket: None,
+            backup_encrypt: None,
+            source_archive: None,
+            compress_output: false,
+            strict: false,
+            deny: vec![],
+            allow: vec![],
+            output_format: OutputFormat::Sqlite,
+            audit_log: None,
+        };
+        if args.resume && args.wipe {
+            return Err(Status::invalid_argument(
+                "resume and wipe exclude each other",
+            ));
+        }
+
+        let migration_id = Uuid::new_v4().to_string();
+        let progress = Arc::new(Mutex::new(ProgressState::default()));
+        tracing::info!("Starting migration {} of {:?}", migration_id, work_dir);
+
+        let observer = Arc::new(ProgressRecorder(progress.clone()));
+        let outcome = progress.clone();
+        let task_work_dir = work_dir.clone();
+        tokio::spawn(async move {
+            // Verification asserts on mismatches, the panic fails this
+            // migration only
+            let result =
+                match tokio::spawn(crate::migrate_with_progress(task_work_dir, args, observer))
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(err) if err.is_panic() => Err(anyhow!(
+                        "Migration panicked: {}",
+                        panic_message(&*err.into_panic())
+                    )),
+                    Err(err) => Err(err.into()),
+                };
+
+            outcome.lock().expect("progress lock").outcome =
+                Some(result.map_err(|err| format!("{:#}", err)));
+        });
+
+        migrations.insert(
+            migration_id.clone(),
+            RunningMigration { work_dir, progress },
+        );
+
+        Ok(Response::new(StartMigrationResponse { migration_id }))
+    }
+
+    async fn get_status(
+        &self,
+        request: Request<GetStatusRequest>,
+    ) -> Result<Response<MigrationStatus>, Status> {
+        let migration_id = request.into_inner().migration_id;
+        let (work_dir, progress) = self
+            .progress(&migration_id)
+            .ok_or_else(|| Status::not_found(format!("No migration {}", migration_id)))?;
+        let progress = progress.lock().expect("progress lock");
+
+        Ok(Response::new(MigrationStatus {
+            migration_id,
+            work_dir: work_dir.to_string_lossy().to_string(),
+            state: state(&progress).into(),
+            phase: progress
+                .phase
+                .map(|phase| phase.to_string())
+                .unwrap_or_default(),
+            records_done: progress.done,
+            records_total: progress.total,
+            warnings: progress.warnings.clone(),
+            error: error(&progress),
+        }))
+    }
+
+    async fn get_report(
+        &self,
+        request: Request<GetReportRequest>,
+    ) -> Result<Response<MigrationReport>, Status> {
+        let migration_id = request.into_inner().migration_id;
+        let (work_dir, progress) = self
+            .progress(&migration_id)
+            .ok_or_else(|| Status::not_found(format!("No migration {}", migration_id)))?;
+        let (state, warnings, error) = {
+            let progress = progress.lock().expect("progress lock");
+            (
+                state(&progress),
+                progress.warnings.clone(),
+                error(&progress),
+            )
+        };
+
+        // The database only sits at its final path once it was verified
+        let sqlite = MigrationPaths::new(&work_dir).sqlite;
+        let meta = if state == MigrationState::Succeeded {
+            meta::read_meta(&sqlite)
+                .await
+                .map_err(|err| Status::internal(err.to_string()))?
+        } else {
+            vec![]
+        };
+
+        Ok(Response::new(MigrationReport {
+            migration_id,
+            state: state.into(),
+            meta: meta
+                .into_iter()
+                .map(|(key, value)| MetaEntry { key, value })
+                .collect(),
+            warnings,
+            error,
+        }))
+    }
+}
+
+fn state(progress: &ProgressState) -> MigrationState {
+    match progress.outcome {
+        None => MigrationState::Running,
+        Some(Ok(())) => MigrationState::Succeeded,
+        Some(Err(_)) => MigrationState::Failed,
+    }
+}
+
+fn error(progress: &ProgressState) -> String {
+    match &progress.outcome {
+        Some(Err(err)) => err.clone(),
+        _ => String::new(),
+    }
+}
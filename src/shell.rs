@@ -0,0 +1,276 @@
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_common::util::hex;
+use sqlx::Row;
+
+use crate::cli::Backend;
+use crate::inspect::{redb_path, sqlite_path};
+use crate::output::say;
+use crate::raw::RedbReader;
+use crate::sqlite;
+
+const PAGE_SIZE: usize = 20;
+
+const HELP: &str = "Commands:
+  keysets              List all keysets
+  proofs [page]        Page through proofs
+  quotes [page]        Page through mint and melt quotes
+  signatures [page]    Page through blind signatures
+  use <redb|sqlite>    Switch the database being browsed
+  help                 Show this help
+  exit                 Leave the shell";
+
+struct Shell {
+    work_dir: PathBuf,
+    backend: Backend,
+}
+
+pub async fn run(work_dir: &Path) -> Result<()> {
+    let mut shell = Shell {
+        work_dir: work_dir.to_path_buf(),
+        backend: Backend::Redb,
+    };
+
+    println!("{}", HELP);
+
+    let stdin = io::stdin();
+    loop {
+        print!("{}> ", shell.backend_name());
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let argument = words.next();
+
+        if matches!(command, "exit" | "quit") {
+            break;
+        }
+
+        // Errors are printed instead of returned so a typo does not end the session
+        if let Err(err) = shell.execute(command, argument).await {
+            say!("❌ {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+impl Shell {
+    fn backend_name(&self) -> &'static str {
+        match self.backend {
+            Backend::Redb => "redb",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+
+    async fn execute(&mut self, command: &str, argument: Option<&str>) -> Result<()> {
+        let lines = match command {
+            "help" => vec![HELP.to_string()],
+            "use" => {
+                self.backend = match argument {
+                    Some("redb") => Backend::Redb,
+                    Some("sqlite") => Backend::Sqlite,
+                    _ => return Err(anyhow!("Usage: use <redb|sqlite>")),
+                };
+                vec![format!("Now browsing {}", self.backend_name())]
+            }
+            "keysets" => match self.backend {
+                Backend::Redb => self.redb_keysets()?,
+                Backend::Sqlite => self.sqlite_keysets().await?,
+            },
+            "proofs" | "quotes" | "signatures" => {
+                let page = match argument {
+                    Some(page) => page
+                        .parse::<usize>()
+                        .map_err(|_| anyhow!("Page must be a number"))?,
+                    None => 1,
+                }
+                .max(1);
+                let offset = (page - 1) * PAGE_SIZE;
+
+                let lines = match (command, self.backend) {
+                    ("proofs", Backend::Redb) => self.redb_proofs()?,
+                    ("quotes", Backend::Redb) => self.redb_quotes()?,
+                    ("signatures", Backend::Redb) => self.redb_signatures()?,
+                    (_, Backend::Sqlite) => return self.print_sqlite_page(command, offset).await,
+                    _ => unreachable!(),
+                };
+
+                page_lines(lines, offset)
+            }
+            _ => return Err(anyhow!("Unknown command {}, try help", command)),
+        };
+
+        for line in lines {
+            println!("{}", line);
+        }
+
+        Ok(())
+    }
+
+    fn redb_keysets(&self) -> Result<Vec<String>> {
+        let reader = RedbReader::open(&redb_path(&self.work_dir, false))?;
+
+        Ok(reader
+            .keysets()?
+            .into_iter()
+            .map(|keyset| {
+                format!(
+                    "{} unit {} active {} max_order {} input_fee_ppk {}",
+                    keyset.id, keyset.unit, keyset.active, keyset.max_order, keyset.input_fee_ppk
+                )
+            })
+            .collect())
+    }
+
+    fn redb_proofs(&self) -> Result<Vec<String>> {
+        let reader = RedbReader::open(&redb_path(&self.work_dir, false))?;
+
+        Ok(reader
+            .proofs_with_states()?
+            .into_iter()
+            .map(|(y, proof, state)| {
+                format!(
+                    "{} amount {} keyset {} state {}",
+                    y.to_hex(),
+                    proof.amount,
+                    proof.keyset_id,
+                    state
+                )
+            })
+            .collect())
+    }
+
+    fn redb_quotes(&self) -> Result<Vec<String>> {
+        let reader = RedbReader::open(&redb_path(&self.work_dir, false))?;
+
+        let mut lines = vec![];
+        for quote in reader.mint_quotes()? {
+            lines.push(format!(
+                "mint {} amount {} {} state {}",
+                quote.id, quote.amount, quote.unit, quote.state
+            ));
+        }
+        for quote in reader.melt_quotes()? {
+            lines.push(format!(
+                "melt {} amount {} {} state {}",
+                quote.id, quote.amount, quote.unit, quote.state
+            ));
+        }
+
+        Ok(lines)
+    }
+
+    fn redb_signatures(&self) -> Result<Vec<String>> {
+        let reader = RedbReader::open(&redb_path(&self.work_dir, false))?;
+
+        Ok(reader
+            .blind_signatures()?
+            .into_iter()
+            .map(|(blinded_message, signature)| {
+                format!(
+                    "{} amount {} keyset {}",
+                    blinded_message.to_hex(),
+                    signature.amount,
+                    signature.keyset_id
+                )
+            })
+            .collect())
+    }
+
+    async fn sqlite_keysets(&self) -> Result<Vec<String>> {
+        let pool = sqlite::connect(&sqlite_path(&self.work_dir, false)).await?;
+        let rows = sqlx::query(
+            "SELECT id, unit, active, max_order, input_fee_ppk FROM keyset ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await?;
+        pool.close().await;
+
+        rows.iter()
+            .map(|row| {
+                Ok(format!(
+                    "{} unit {} active {} max_order {} input_fee_ppk {}",
+                    row.try_get::<String, _>("id")?,
+                    row.try_get::<String, _>("unit")?,
+                    row.try_get::<bool, _>("active")?,
+                    row.try_get::<i64, _>("max_order")?,
+                    row.try_get::<i64, _>("input_fee_ppk")?
+                ))
+            })
+            .collect()
+    }
+
+    async fn print_sqlite_page(&self, command: &str, offset: usize) -> Result<()> {
+        let sql = match command {
+            "proofs" => "SELECT y, amount, keyset_id, state FROM proof ORDER BY y LIMIT ? OFFSET ?",
+            "quotes" => {
+                "SELECT 'mint' AS kind, id, amount, unit, state FROM mint_quote
+                 UNION ALL
+                 SELECT 'melt' AS kind, id, amount, unit, state FROM melt_quote
+                 ORDER BY kind DESC, id LIMIT ? OFFSET ?"
+            }
+            _ => "SELECT y, amount, keyset_id FROM blind_signature ORDER BY y LIMIT ? OFFSET ?",
+        };
+
+        let pool = sqlite::connect(&sqlite_path(&self.work_dir, false)).await?;
+        let rows = sqlx::query(sql)
+            .bind(PAGE_SIZE as i64)
+            .bind(offset as i64)
+            .fetch_all(&pool)
+            .await?;
+        pool.close().await;
+
+        if rows.is_empty() {
+            println!("No more entries");
+        }
+
+        for row in rows {
+            let line = match command {
+                "proofs" => format!(
+                    "{} amount {} keyset {} state {}",
+                    hex::encode(row.try_get::<Vec<u8>, _>("y")?),
+                    row.try_get::<i64, _>("amount")?,
+                    row.try_get::<String, _>("keyset_id")?,
+                    row.try_get::<String, _>("state")?
+                ),
+                "quotes" => format!(
+                    "{} {} amount {} {} state {}",
+                    row.try_get::<String, _>("kind")?,
+                    row.try_get::<String, _>("id")?,
+                    row.try_get::<i64, _>("amount")?,
+                    row.try_get::<String, _>("unit")?,
+                    row.try_get::<String, _>("state")?
+                ),
+                _ => format!(
+                    "{} amount {} keyset {}",
+                    hex::encode(row.try_get::<Vec<u8>, _>("y")?),
+                    row.try_get::<i64, _>("amount")?,
+                    row.try_get::<String, _>("keyset_id")?
+                ),
+            };
+            println!("{}", line);
+        }
+
+        Ok(())
+    }
+}
+
+fn page_lines(lines: Vec<String>, offset: usize) -> Vec<String> {
+    let page: Vec<String> = lines.into_iter().skip(offset).take(PAGE_SIZE).collect();
+
+    if page.is_empty() {
+        vec!["No more entries".to_string()]
+    } else {
+        page
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt::Display;
+
+use anyhow::{Result, anyhow};
+#[cfg(feature = "auth")]
+use cdk_common::database::MintAuthDatabase;
+use cdk_common::database::{
+    MintDatabase, MintKeysDatabase, MintProofsDatabase, MintQuotesDatabase, MintSignaturesDatabase,
+};
+use cdk_common::nuts::{MeltQuoteState, MintQuoteState};
+use cdk_sqlite::MintSqliteDatabase;
+#[cfg(feature = "auth")]
+use cdk_sqlite::mint::MintSqliteAuthDatabase;
+
+use crate::output::say;
+use crate::paths::MigrationPaths;
+
+// The queries cdk-mintd 0.10 runs while it starts: loading mint info and the
+// active keysets, totalling issued and redeemed ecash per keyset, and
+// checking unpaid mint quotes and pending melts against the payment backend.
+// Running them against the produced database catches a mint that would not
+// come up before the switch instead of after it.
+
+struct Checks {
+    failed: usize,
+}
+
+impl Checks {
+    fn record<T, E: Display>(
+        &mut self,
+        name: &str,
+        result: Result<T, E>,
+        detail: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                say!("✅ {}: {}", name, detail(&value));
+                Some(value)
+            }
+            Err(err) => {
+                self.failed += 1;
+                say!("❌ {}: {}", name, err);
+                None
+            }
+        }
+    }
+
+    fn fail(&mut self, name: &str, reason: String) {
+        self.failed += 1;
+        say!("❌ {}: {}", name, reason);
+    }
+}
+
+pub async fn smoke_test(paths: &MigrationPaths) -> Result<()> {
+    say!(
+        "\n🔧 Running mintd startup queries against {:?}",
+        paths.sqlite
+    );
+
+    let mut checks = Checks { failed: 0 };
+    let db = MintSqliteDatabase::new(&paths.sqlite).await?;
+
+    checks.record("mint info", db.get_mint_info().await, |info| {
+        info.name.clone().unwrap_or_else(|| "unnamed".to_string())
+    });
+    checks.record("quote TTL", db.get_quote_ttl().await, |ttl| {
+        format!("mint {}s, melt {}s", ttl.mint_ttl, ttl.melt_ttl)
+    });
+
+    let keysets = checks
+        .record("keysets", db.get_keyset_infos().await, |keysets| {
+            format!("{} loaded", keysets.len())
+        })
+        .unwrap_or_default();
+
+    if let Some(active) = checks.record("active keysets", db.get_active_keysets().await, |active| {
+        format!("{} unit(s)", active.len())
+    }) {
+        for (unit, id) in active {
+            // mintd derives the keys of every active keyset on startup
+            match db.get_keyset_info(&id).await {
+                Ok(Some(_)) => (),
+                Ok(None) => checks.fail(
+                    "active keysets",
+                    format!("active keyset {} of unit {} has no keyset info", id, unit),
+                ),
+                Err(err) => checks.fail("active keysets", err.to_string()),
+            }
+        }
+    }
+
+    for keyset in &keysets {
+        checks.record(
+            &format!("issued for keyset {}", keyset.id),
+            db.get_blind_signatures_for_keyset(&keyset.id).await,
+            |signatures| format!("{} signature(s)", signatures.len()),
+        );
+        checks.record(
+            &format!("redeemed for keyset {}", keyset.id),
+            db.get_proofs_by_keyset_id(&keyset.id).await,
+            |(proofs, _)| format!("{} proof(s)", proofs.len()),
+        );
+    }
+
+    for state in [MintQuoteState::Unpaid, MintQuoteState::Pending] {
+        checks.record(
+            &format!("{} mint quotes", state),
+            db.get_mint_quotes_with_state(state).await,
+            |quotes| format!("{} to check", quotes.len()),
+        );
+    }
+
+    let pending_melts = checks
+        .record("melt quotes", db.get_melt_quotes().await, |quotes| {
+            format!("{} loaded", quotes.len())
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|quote| quote.state == MeltQuoteState::Pending);
+    for quote in pending_melts {
+        let name = format!("pending melt {}", quote.id);
+        let Some(ys) = checks.record(&name, db.get_proof_ys_by_quote_id(&quote.id).await, |ys| {
+            format!("{} input proof(s)", ys.len())
+        }) else {
+            continue;
+        };
+        checks.record(&name, db.get_proofs_states(&ys).await, |_| {
+            "input states readable".to_string()
+        });
+        checks.record(
+            &name,
+            db.get_melt_request(&quote.id).await,
+            |request| match request {
+                Some((_, key)) => format!("paid with {} {}", key.unit, key.method),
+                None => "no melt request".to_string(),
+            },
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    if paths.auth_sqlite.exists() {
+        let auth_db = MintSqliteAuthDatabase::new(&paths.auth_sqlite).await?;
+
+        checks.record(
+            "auth keysets",
+            auth_db.get_keyset_infos().await,
+            |keysets| format!("{} loaded", keysets.len()),
+        );
+        if let Some(Some(id)) = checks.record(
+            "active auth keyset",
+            auth_db.get_active_keyset_id().await,
+            |id| match id {
+                Some(id) => id.to_string(),
+                None => "none".to_string(),
+            },
+        ) && !matches!(auth_db.get_keyset_info(&id).await, Ok(Some(_)))
+        {
+            checks.fail(
+                "active auth keyset",
+                format!("active auth keyset {} has no keyset info", id),
+            );
+        }
+        checks.record(
+            "protected endpoints",
+            auth_db.get_auth_for_endpoints().await,
+            |endpoints| format!("{} loaded", endpoints.len()),
+        );
+    }
+
+    if checks.failed > 0 {
+        return Err(anyhow!(
+            "{} mintd startup query(ies) failed against {:?}",
+            checks.failed,
+            paths.sqlite
+        ));
+    }
+
+    say!("✅ mintd startup queries succeed");
+
+    Ok(())
+}
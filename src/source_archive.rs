@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+
+use crate::meta;
+use crate::output::say;
+
+// `--source-archive` migrates from a compressed copy of the redb, e.g. one
+// carried over from the mint host to migrate elsewhere. A `.redb.gz` such as
+// `cleanup --archive` writes holds the mint database, with the auth database
+// taken from `cdk-mintd-auth.redb.gz` next to it if there is one. A `.tar.gz`
+// or `.tgz` holds `cdk-mintd.redb` and optionally `cdk-mintd-auth.redb`
+// anywhere in it, and the `manifest.json` of a `--backup-encrypt` archive is
+// checked against them. The databases are unpacked into a directory next to
+// the build that is removed once the migration is done.
+
+const REDB: &str = "cdk-mintd.redb";
+const AUTH_REDB: &str = "cdk-mintd-auth.redb";
+
+/// The unpacked databases, removed once dropped
+pub struct ExtractedSource {
+    dir: PathBuf,
+    pub redb: PathBuf,
+    pub auth_redb: PathBuf,
+}
+
+impl Drop for ExtractedSource {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    name: String,
+    sha256: String,
+}
+
+/// Unpacks the redb databases of `archive` into a directory in `dir`
+pub fn extract(archive: &Path, dir: &Path) -> Result<ExtractedSource> {
+    let name = archive
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let tar = name.ends_with(".tar.gz") || name.ends_with(".tgz");
+    if !tar && !name.ends_with(".gz") {
+        return Err(anyhow!(
+            "{:?} is neither a .redb.gz nor a .tar.gz archive",
+            archive
+        ));
+    }
+
+    let extracted_dir = dir.join(format!("{}.extracted", name));
+    if extracted_dir.exists() {
+        std::fs::remove_dir_all(&extracted_dir)?;
+    }
+    std::fs::create_dir_all(&extracted_dir)?;
+    let extracted = ExtractedSource {
+        redb: extracted_dir.join(REDB),
+        auth_redb: extracted_dir.join(AUTH_REDB),
+        dir: extracted_dir,
+    };
+
+    say!("📋 Unpacking {:?}...", archive);
+    if tar {
+        extract_tar(archive, &extracted)?;
+    } else {
+        gunzip(archive, &extracted.redb)?;
+        let auth_archive = archive.with_file_name(format!("{}.gz", AUTH_REDB));
+        if auth_archive.exists() {
+            gunzip(&auth_archive, &extracted.auth_redb)?;
+        }
+    }
+
+    if !extracted.redb.exists() {
+        return Err(anyhow!("{:?} holds no {}", archive, REDB));
+    }
+    say!(
+        "✅ Unpacked {}{} from {:?}",
+        REDB,
+        if extracted.auth_redb.exists() {
+            format!(" and {}", AUTH_REDB)
+        } else {
+            String::new()
+        },
+        archive
+    );
+
+    Ok(extracted)
+}
+
+fn gunzip(archive: &Path, to: &Path) -> Result<()> {
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(archive)?));
+    let mut file = BufWriter::new(File::create(to)?);
+    std::io::copy(&mut decoder, &mut file)
+        .map_err(|e| anyhow!("Could not unpack {:?}: {}", archive, e))?;
+    file.flush()?;
+
+    Ok(())
+}
+
+fn extract_tar(archive: &Path, extracted: &ExtractedSource) -> Result<()> {
+    let unreadable =
+        |e: std::io::Error| anyhow!("Could not read {:?} as a tar archive: {}", archive, e);
+    let mut tar = tar::Archive::new(GzDecoder::new(BufReader::new(File::open(archive)?)));
+    let mut manifest = None;
+
+    // Headers are checked against their checksum, and PAX and GNU long
+    // names are followed
+    for entry in tar.entries().map_err(unreadable)? {
+        let mut entry = entry.map_err(unreadable)?;
+        let regular = entry.header().entry_type().is_file();
+        let file_name = entry
+            .path()
+            .map_err(unreadable)?
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        match file_name.as_str() {
+            REDB | AUTH_REDB if regular => {
+                let to = extracted.dir.join(&file_name);
+                let mut file = BufWriter::new(File::create(&to)?);
+                std::io::copy(&mut entry, &mut file).map_err(unreadable)?;
+                file.flush()?;
+            }
+            "manifest.json" if regular => {
+                let mut json = vec![];
+                entry.read_to_end(&mut json).map_err(unreadable)?;
+                // A manifest that does not parse would skip the checksum check
+                manifest = Some(
+                    serde_json::from_slice::<Manifest>(&json)
+                        .map_err(|e| anyhow!("The manifest of {:?} is corrupt: {}", archive, e))?,
+                );
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(manifest) = manifest {
+        let expected: HashMap<String, String> = manifest
+            .files
+            .into_iter()
+            .map(|file| (file.name, file.sha256))
+            .collect();
+        for path in [&extracted.redb, &extracted.auth_redb] {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let Some(expected) = expected.get(name.as_ref()) else {
+                continue;
+            };
+            if !path.exists() || meta::sha256_file(path)? != *expected {
+                return Err(anyhow!(
+                    "{} in {:?} does not match the checksum in its manifest",
+                    name,
+                    archive
+                ));
+            }
+        }
+        say!(
+            "✅ The unpacked databases match the manifest of {:?}",
+            archive
+        );
+    }
+
+    Ok(())
+}
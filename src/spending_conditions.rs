@@ -0,0 +1,156 @@
+use anyhow::Result;
+use cdk_common::nuts::nut11::Tag;
+use cdk_common::nuts::{Nut10Secret, SpendingConditions};
+use cdk_common::secret::{self, Secret};
+use cdk_common::{Proof, PublicKey, State};
+
+use crate::output::say;
+use crate::progress::{ProgressObserver, WarningCode};
+
+// Proof secrets are either random strings or NUT-10 well-known secrets,
+// a JSON array of the kind and its data. A NUT-10 secret that no longer
+// parses as a P2PK or HTLC spending condition under the current cdk rules
+// locks the proof for good. Secrets of either kind that are far longer than
+// any wallet produces or carry characters no wallet writes are mostly left
+// over from experiments, and are reported along with them.
+
+// Longest secret nutshell mints accept, cdk wallets write 64 hex characters
+const MAX_SECRET_LENGTH: usize = 512;
+
+pub struct InvalidSecret {
+    pub y: PublicKey,
+    pub kind: String,
+    pub state: Option<State>,
+    pub error: String,
+}
+
+/// Proofs whose secret is malformed or whose NUT-10 secret does not parse
+/// into spending conditions
+pub fn invalid_secrets(proofs: &[Proof], states: &[Option<State>]) -> Result<Vec<InvalidSecret>> {
+    let mut invalid = vec![];
+
+    for (proof, state) in proofs.iter().zip(states) {
+        let secret = proof.secret.to_string();
+        let kind = nut10_kind(&secret);
+
+        let mut errors = check_format(&secret);
+        if kind.is_some()
+            && let Err(error) = check_secret(&proof.secret)
+        {
+            errors.push(error);
+        }
+
+        if !errors.is_empty() {
+            invalid.push(InvalidSecret {
+                y: proof.y()?,
+                kind: kind.unwrap_or_else(|| "plain".to_string()),
+                state: *state,
+                error: errors.join(", "),
+            });
+        }
+    }
+
+    Ok(invalid)
+}
+
+pub fn print_invalid_secrets(invalid: &[InvalidSecret]) {
+    if invalid.is_empty() {
+        return;
+    }
+
+    say!(
+        "\n⚠️  [{}] {} proof(s) have malformed secrets or spending conditions that no longer parse:",
+        WarningCode::MalformedSecret,
+        invalid.len()
+    );
+    for secret in invalid {
+        // A missing state means the proof was never spent
+        let state = secret.state.unwrap_or(State::Unspent);
+        say!(
+            "  {} {} ({}): {}",
+            secret.y,
+            secret.kind,
+            state,
+            secret.error
+        );
+    }
+
+    let spendable = invalid
+        .iter()
+        .filter(|secret| secret.state != Some(State::Spent))
+        .count();
+    if spendable > 0 {
+        say!(
+            "  {} of them are not spent yet, redeeming them may fail for the wallets holding them",
+            spendable
+        );
+    }
+}
+
+pub fn report_invalid_secrets(invalid: &[InvalidSecret], progress: &dyn ProgressObserver) {
+    for secret in invalid {
+        progress.warning(
+            WarningCode::MalformedSecret,
+            &format!(
+                "Proof {} has a malformed {} secret: {}",
+                secret.y, secret.kind, secret.error
+            ),
+        );
+    }
+}
+
+fn check_format(secret: &str) -> Vec<String> {
+    let mut errors = vec![];
+
+    if secret.is_empty() {
+        errors.push("empty secret".to_string());
+    }
+    if secret.len() > MAX_SECRET_LENGTH {
+        errors.push(format!(
+            "{} bytes long, over the {} other mints accept",
+            secret.len(),
+            MAX_SECRET_LENGTH
+        ));
+    }
+    if secret.chars().any(char::is_control) {
+        errors.push("contains control characters".to_string());
+    }
+    // Left where bytes that were not UTF-8 were decoded anyway
+    if secret.contains(char::REPLACEMENT_CHARACTER) {
+        errors.push("contains U+FFFD replacement characters".to_string());
+    }
+
+    errors
+}
+
+// cdk panics on malformed tags and silently drops tags it cannot parse, so
+// the tags are checked one by one before the secret is handed to it
+fn check_secret(secret: &Secret) -> Result<(), String> {
+    let nut10: Nut10Secret = secret
+        .try_into()
+        .map_err(|err: secret::Error| err.to_string())?;
+
+    for tag in nut10.secret_data().tags().into_iter().flatten() {
+        if tag.len() < 2 {
+            return Err(format!("tag {:?} has no value", tag));
+        }
+        Tag::try_from(tag.clone()).map_err(|err| format!("tag {:?}: {}", tag, err))?;
+    }
+
+    SpendingConditions::try_from(nut10).map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+/// P2PK, HTLC or another NUT-10 kind, plain for random secrets
+pub fn secret_kind(secret: &str) -> String {
+    nut10_kind(secret).unwrap_or_else(|| "plain".to_string())
+}
+
+// The kind of a NUT-10 secret, e.g. P2PK, if the secret looks like one
+fn nut10_kind(secret: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(secret).ok()?;
+    let kind = value.as_array()?.first()?.as_str()?;
+
+    Some(kind.to_string())
+}
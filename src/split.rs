@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use cdk_common::nuts::Id;
+
+use crate::output::say;
+use crate::raw::RedbReader;
+use crate::sqlite;
+
+// Rows belonging to a keyset. Quotes are linked through the proofs and
+// signatures that reference them. The migration leaves the quote_id columns
+// of proofs and signatures empty, the links are only kept in the redb's
+// quote_proofs and quote_signatures tables, so they are read from there into
+// temp tables along with any quote_id the SQLite database does hold.
+const SPLIT_QUERIES: &[(&str, &str)] = &[
+    ("keyset", "SELECT * FROM source.keyset WHERE id = ?1"),
+    ("proof", "SELECT * FROM source.proof WHERE keyset_id = ?1"),
+    (
+        "blind_signature",
+        "SELECT * FROM source.blind_signature WHERE keyset_id = ?1",
+    ),
+    (
+        "mint_quote",
+        "SELECT * FROM source.mint_quote WHERE id IN
+            (SELECT link.quote_id FROM temp.signature_quote link
+                JOIN source.blind_signature sig ON sig.y = link.y WHERE sig.keyset_id = ?1)",
+    ),
+    (
+        "melt_quote",
+        "SELECT * FROM source.melt_quote WHERE id IN
+            (SELECT link.quote_id FROM temp.proof_quote link
+                JOIN source.proof proof ON proof.y = link.y WHERE proof.keyset_id = ?1)",
+    ),
+    (
+        "melt_request",
+        "SELECT * FROM source.melt_request WHERE lower(hex(id)) IN
+            (SELECT replace(link.quote_id, '-', '') FROM temp.proof_quote link
+                JOIN source.proof proof ON proof.y = link.y WHERE proof.keyset_id = ?1)",
+    ),
+];
+
+pub async fn split(work_dir: PathBuf, keyset: &str, output: &Path) -> Result<()> {
+    let keyset_id = Id::from_str(keyset).map_err(|_| anyhow!("{} is not a keyset id", keyset))?;
+
+    let sql_db_path = work_dir.join("cdk-mintd.sqlite");
+
+    println!("Splitting keyset {} out of {:?}", keyset_id, sql_db_path);
+
+    let pool = sqlite::create_from_source(&sql_db_path, output).await?;
+
+    let known: Option<(String,)> = sqlx::query_as("SELECT id FROM source.keyset WHERE id = ?")
+        .bind(keyset_id.to_string())
+        .fetch_optional(&pool)
+        .await?;
+    if known.is_none() {
+        pool.close().await;
+        std::fs::remove_file(output)?;
+        return Err(anyhow!(
+            "Keyset {} not found in {:?}",
+            keyset_id,
+            sql_db_path
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+    let redb_path = work_dir.join("cdk-mintd.redb");
+    let (proof_links, signature_links) = match redb_path.exists() {
+        true => {
+            let reader = RedbReader::open(&redb_path)?;
+            (reader.quote_proofs()?, reader.quote_signatures()?)
+        }
+        false => (vec![], vec![]),
+    };
+    for (link_table, table, links) in [
+        ("proof_quote", "proof", &proof_links),
+        ("signature_quote", "blind_signature", &signature_links),
+    ] {
+        sqlx::query(&format!(
+            "CREATE TEMP TABLE {} AS SELECT y, quote_id FROM source.{} WHERE quote_id IS NOT NULL",
+            link_table, table
+        ))
+        .execute(&mut *tx)
+        .await?;
+        for (quote_id, y) in links {
+            sqlx::query(&format!(
+                "INSERT INTO temp.{} (y, quote_id) VALUES (?, ?)",
+                link_table
+            ))
+            .bind(y.to_bytes().to_vec())
+            .bind(quote_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    let linked: i64 = sqlx::query_scalar(
+        "SELECT (SELECT count(*) FROM temp.proof_quote) + (SELECT count(*) FROM temp.signature_quote)",
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if linked == 0 {
+        say!(
+            "⚠️  No proof or signature is linked to a quote in {:?} or in a redb at {:?}, no quotes are copied",
+            sql_db_path,
+            redb_path
+        );
+    }
+
+    for (table, select) in SPLIT_QUERIES {
+        let copied = sqlx::query(&format!("INSERT INTO main.{} {}", table, select))
+            .bind(keyset_id.to_string())
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        tracing::info!("Copied {} rows of {}", copied, table);
+        println!("  {}: {} rows", table, copied);
+    }
+    tx.commit().await?;
+
+    pool.close().await;
+
+    say!("✅ Keyset {} written to {:?}", keyset_id, output);
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_common::util::hex;
+use cdk_sqlite::MintSqliteDatabase;
+use serde_json::{Map, Value};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{Column, Executor, Row, SqlitePool, TypeInfo, ValueRef};
+
+use crate::cleanup;
+use crate::paths::with_suffix;
+
+/// Opens a pool on an existing SQLite database for queries cdk-sqlite does not expose
+pub async fn connect(path: &Path) -> Result<SqlitePool> {
+    if !path.exists() {
+        return Err(anyhow!("No SQLite database found at {:?}", path));
+    }
+
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(false);
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    Ok(pool)
+}
+
+/// Deletes a SQLite database together with its WAL and shared memory files
+pub fn remove_database(path: &Path) -> Result<()> {
+    for suffix in ["", "-wal", "-shm"] {
+        match std::fs::remove_file(with_suffix(path, suffix)) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes every row of a database, keeping its schema and migration history.
+/// cdk-sqlite opens databases with a shared cache, so a database removed and
+/// created again at the same path while its old pool is still closing would
+/// be written to the removed file.
+pub async fn clear_database(path: &Path) -> Result<()> {
+    let pool = connect(path).await?;
+    let tables: Vec<String> = sqlx::query(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT IN ('_sqlx_migrations', '_audit_log')",
+    )
+    .fetch_all(&pool)
+    .await?
+    .iter()
+    .map(|row| row.get("name"))
+    .collect();
+
+    let mut tx = pool.begin().await?;
+    for table in tables {
+        sqlx::query(&format!("DELETE FROM {}", quote_identifier(&table)))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Writes the pages in the WAL of a SQLite database into its main file and
+/// empties the WAL
+pub async fn fold_wal(path: &Path) -> Result<()> {
+    let pool = connect(path).await?;
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Writes a gzip copy of a SQLite database next to it, with its WAL folded
+/// in so the copy is complete on its own
+pub async fn compress_database(path: &Path) -> Result<PathBuf> {
+    fold_wal(path).await?;
+
+    let compressed = with_suffix(path, ".gz");
+    cleanup::gzip_file(path, &compressed)?;
+
+    Ok(compressed)
+}
+
+/// Moves a SQLite database to `to` once its WAL has been folded into the main file
+pub async fn rename_database(from: &Path, to: &Path) -> Result<()> {
+    fold_wal(from).await?;
+
+    // Any WAL left behind is renamed along with the database so no committed
+    // page is lost
+    for suffix in ["-wal", "-shm", ""] {
+        let source = with_suffix(from, suffix);
+        if source.exists() {
+            move_file(&source, &with_suffix(to, suffix))?;
+        }
+    }
+
+    Ok(())
+}
+
+// A database staged under --tmp-dir may live on another filesystem. It is
+// copied next to its destination first, so the destination only ever
+// appears complete.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            let copy = with_suffix(to, ".partial");
+            std::fs::copy(from, &copy)?;
+            File::open(&copy)?.sync_all()?;
+            std::fs::rename(&copy, to)?;
+            std::fs::remove_file(from)?;
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Points the temporary files of SQLite, such as sort spills and statement
+/// journals, at `dir`. SQLite reads the location once, so this has to run
+/// before the first database is opened.
+pub fn set_temp_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Could not create tmp dir {:?}: {}", dir, e))?;
+
+    // SAFETY: called at startup before any task reads the environment
+    unsafe { std::env::set_var("SQLITE_TMPDIR", dir) };
+
+    Ok(())
+}
+
+/// Flushes a SQLite database and its WAL to disk
+pub fn sync_database(path: &Path) -> Result<()> {
+    for suffix in ["", "-wal"] {
+        let file = with_suffix(path, suffix);
+        if file.exists() {
+            File::open(&file)?.sync_all()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a new mint database at `output` with the source database attached as `source`
+pub async fn create_from_source(source: &Path, output: &Path) -> Result<SqlitePool> {
+    if !source.exists() {
+        return Err(anyhow!("No SQLite database found at {:?}", source));
+    }
+
+    if output.exists() {
+        return Err(anyhow!(
+            "SQLite database already exists at {:?}. Will not overwrite existing database.",
+            output
+        ));
+    }
+
+    // Let cdk-sqlite create the schema so the new file opens like any other mint database
+    drop(MintSqliteDatabase::new(output).await?);
+
+    let pool = connect(output).await?;
+    sqlx::query("ATTACH DATABASE ? AS source")
+        .bind(source.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+
+    Ok(pool)
+}
+
+/// Runs every statement of `script` in a single transaction
+pub async fn run_script(path: &Path, script: &str) -> Result<()> {
+    let pool = connect(path).await?;
+
+    let mut tx = pool.begin().await?;
+    tx.execute(script).await?;
+    tx.commit().await?;
+
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Renders a row as a JSON object keyed by column name, blobs as hex
+pub fn row_to_json(row: &SqliteRow) -> Result<Value> {
+    let mut object = Map::new();
+
+    for column in row.columns() {
+        let raw = row.try_get_raw(column.ordinal())?;
+        let value = if raw.is_null() {
+            Value::Null
+        } else {
+            match raw.type_info().name() {
+                "INTEGER" | "BOOLEAN" => Value::from(row.try_get::<i64, _>(column.ordinal())?),
+                "REAL" => Value::from(row.try_get::<f64, _>(column.ordinal())?),
+                "BLOB" => Value::from(hex::encode(row.try_get::<Vec<u8>, _>(column.ordinal())?)),
+                _ => Value::from(row.try_get::<String, _>(column.ordinal())?),
+            }
+        };
+
+        object.insert(column.name().to_string(), value);
+    }
+
+    Ok(Value::Object(object))
+}
+
+/// Quotes a table or column name for use in a statement
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// SQL expression for the value of a column with blobs hex encoded, e.g. for
+/// json_array and json_object, as JSON cannot hold blobs
+pub fn blob_as_hex(column: &str) -> String {
+    format!(
+        "CASE WHEN typeof({0}) = 'blob' THEN hex({0}) ELSE {0} END",
+        column
+    )
+}
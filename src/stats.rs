@@ -0,0 +1,462 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use cdk_common::Proof;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::cli::Period;
+use crate::meta::unix_time;
+use crate::output::say;
+use crate::paths::MigrationPaths;
+use crate::raw::RedbReader;
+use crate::stats_snapshot::{self, Counts, Snapshot};
+use crate::{spending_conditions, sqlite};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub count: u64,
+    pub amount: u64,
+}
+
+/// Proof count and amount per keyset and state
+pub type ProofStates = BTreeMap<String, BTreeMap<String, Bucket>>;
+
+/// Quote count per kind (mint or melt) and state
+pub type QuoteStates = BTreeMap<&'static str, BTreeMap<String, u64>>;
+
+#[derive(Debug, Default)]
+pub struct Activity {
+    pub mint_created: u64,
+    pub mint_paid: u64,
+    pub mint_issued: u64,
+    pub melt_created: u64,
+    pub melt_paid: u64,
+}
+
+pub async fn stats(
+    work_dir: PathBuf,
+    period: Option<Period>,
+    save: Option<&Path>,
+    compare: Option<&Path>,
+) -> Result<()> {
+    let paths = MigrationPaths::new(&work_dir);
+
+    if let Some(period) = period {
+        print_quote_activity(&quote_activity(&paths.redb, period)?, period);
+        return Ok(());
+    }
+
+    // Read first, --save may be about to overwrite it
+    let before = compare.map(stats_snapshot::load).transpose()?;
+
+    let source = redb_proof_states(&paths.redb)?;
+    let target = if paths.sqlite.exists() {
+        Some(sqlite_proof_states(&paths.sqlite).await?)
+    } else {
+        say!(
+            "No SQLite database at {:?}, showing the redb only",
+            paths.sqlite
+        );
+        None
+    };
+
+    print_proof_states(&source, target.as_ref());
+    let (source_by_keyset, target_by_keyset) = (source, target);
+
+    let source = redb_proof_kinds(&paths.redb)?;
+    let target = if paths.sqlite.exists() {
+        Some(sqlite_proof_kinds(&paths.sqlite).await?)
+    } else {
+        None
+    };
+
+    print_proof_kinds(&source, target.as_ref());
+    let (source_by_kind, target_by_kind) = (source, target);
+
+    let source = redb_quote_states(&paths.redb)?;
+    let target = if paths.sqlite.exists() {
+        Some(sqlite_quote_states(&paths.sqlite).await?)
+    } else {
+        None
+    };
+
+    print_quote_states(&source, target.as_ref());
+
+    let now = Snapshot {
+        taken_at: unix_time(),
+        redb: Counts::new(source_by_keyset, source_by_kind, source),
+        sqlite: match (target_by_keyset, target_by_kind, target) {
+            (Some(by_keyset), Some(by_kind), Some(quotes)) => {
+                Some(Counts::new(by_keyset, by_kind, quotes))
+            }
+            _ => None,
+        },
+    };
+    if let Some(before) = &before {
+        stats_snapshot::print_changes(before, &now);
+    }
+    if let Some(path) = save {
+        stats_snapshot::save(&now, path)?;
+    }
+
+    Ok(())
+}
+
+// Unpaid quotes past their expiry are counted as expired, the state column
+// alone never says so
+fn quote_state(state: String, expiry: u64, now: u64) -> String {
+    if state == "UNPAID" && expiry < now {
+        "EXPIRED".to_string()
+    } else {
+        state
+    }
+}
+
+pub fn redb_quote_states(redb_path: &Path) -> Result<QuoteStates> {
+    let reader = RedbReader::open(redb_path)?;
+    let now = unix_time();
+
+    let mut quote_states = QuoteStates::new();
+    for quote in reader.mint_quotes()? {
+        *quote_states
+            .entry("mint")
+            .or_default()
+            .entry(quote_state(quote.state.to_string(), quote.expiry, now))
+            .or_default() += 1;
+    }
+    for quote in reader.melt_quotes()? {
+        *quote_states
+            .entry("melt")
+            .or_default()
+            .entry(quote_state(quote.state.to_string(), quote.expiry, now))
+            .or_default() += 1;
+    }
+
+    Ok(quote_states)
+}
+
+pub async fn sqlite_quote_states(sql_db_path: &Path) -> Result<QuoteStates> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let now = unix_time();
+
+    let mut quote_states = QuoteStates::new();
+    for (kind, table) in [("mint", "mint_quote"), ("melt", "melt_quote")] {
+        let rows = sqlx::query(&format!("SELECT state, expiry FROM {}", table))
+            .fetch_all(&pool)
+            .await?;
+
+        for row in rows {
+            let state = quote_state(
+                row.try_get("state")?,
+                row.try_get::<i64, _>("expiry")? as u64,
+                now,
+            );
+            *quote_states
+                .entry(kind)
+                .or_default()
+                .entry(state)
+                .or_default() += 1;
+        }
+    }
+    pool.close().await;
+
+    Ok(quote_states)
+}
+
+/// Prints the quote state histogram of the source, next to the target's when given
+pub fn print_quote_states(source: &QuoteStates, target: Option<&QuoteStates>) {
+    say!("\n=== Quote States ===");
+
+    for kind in ["mint", "melt"] {
+        say!("{} quotes", kind);
+
+        let source_states = source.get(kind);
+        let target_states = target.map(|t| t.get(kind));
+        let states: BTreeSet<&String> = source_states
+            .into_iter()
+            .flat_map(|s| s.keys())
+            .chain(target_states.flatten().into_iter().flat_map(|s| s.keys()))
+            .collect();
+
+        if states.is_empty() {
+            say!("  none");
+        }
+
+        for state in states {
+            let source_count = source_states
+                .and_then(|s| s.get(state))
+                .copied()
+                .unwrap_or_default();
+            match target_states {
+                Some(target_states) => {
+                    let target_count = target_states
+                        .and_then(|s| s.get(state))
+                        .copied()
+                        .unwrap_or_default();
+                    let marker = if source_count == target_count {
+                        "✓"
+                    } else {
+                        "⚠️"
+                    };
+                    say!(
+                        "  {} {:<14} redb {:>6} | sqlite {:>6}",
+                        marker,
+                        state,
+                        source_count,
+                        target_count
+                    );
+                }
+                None => say!("  {:<14} {:>6}", state, source_count),
+            }
+        }
+    }
+}
+
+pub fn redb_proof_states(redb_path: &Path) -> Result<ProofStates> {
+    redb_proofs(redb_path, |proof| proof.keyset_id.to_string())
+}
+
+pub fn redb_proof_kinds(redb_path: &Path) -> Result<ProofStates> {
+    redb_proofs(redb_path, |proof| {
+        spending_conditions::secret_kind(&proof.secret.to_string())
+    })
+}
+
+// Proof count and amount per state, grouped by what `group` returns
+fn redb_proofs(redb_path: &Path, group: impl Fn(&Proof) -> String) -> Result<ProofStates> {
+    let mut proof_states = ProofStates::new();
+    for (_, proof, state) in RedbReader::open(redb_path)?.proofs_with_states()? {
+        let bucket = proof_states
+            .entry(group(&proof))
+            .or_default()
+            .entry(state.to_string())
+            .or_default();
+        bucket.count += 1;
+        bucket.amount += u64::from(proof.amount);
+    }
+
+    Ok(proof_states)
+}
+
+pub async fn sqlite_proof_states(sql_db_path: &Path) -> Result<ProofStates> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let rows = sqlx::query(
+        "SELECT keyset_id, state, COUNT(*) AS count, SUM(amount) AS amount
+         FROM proof GROUP BY keyset_id, state",
+    )
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+
+    let mut proof_states = ProofStates::new();
+    for row in rows {
+        proof_states
+            .entry(row.try_get("keyset_id")?)
+            .or_default()
+            .insert(
+                row.try_get("state")?,
+                Bucket {
+                    count: row.try_get::<i64, _>("count")? as u64,
+                    amount: row.try_get::<i64, _>("amount")? as u64,
+                },
+            );
+    }
+
+    Ok(proof_states)
+}
+
+pub async fn sqlite_proof_kinds(sql_db_path: &Path) -> Result<ProofStates> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let rows = sqlx::query("SELECT secret, state, amount FROM proof")
+        .fetch_all(&pool)
+        .await?;
+    pool.close().await;
+
+    let mut proof_kinds = ProofStates::new();
+    for row in rows {
+        let bucket = proof_kinds
+            .entry(spending_conditions::secret_kind(row.try_get("secret")?))
+            .or_default()
+            .entry(row.try_get("state")?)
+            .or_default();
+        bucket.count += 1;
+        bucket.amount += row.try_get::<i64, _>("amount")? as u64;
+    }
+
+    Ok(proof_kinds)
+}
+
+/// Prints the proof state histogram of the source, next to the target's when given
+pub fn print_proof_states(source: &ProofStates, target: Option<&ProofStates>) {
+    print_proof_buckets("Proof States", "Keyset", source, target);
+}
+
+/// Prints proofs by secret kind, showing how much of the liability is condition-locked
+pub fn print_proof_kinds(source: &ProofStates, target: Option<&ProofStates>) {
+    print_proof_buckets("Proofs by Secret Kind", "Kind", source, target);
+}
+
+fn print_proof_buckets(
+    title: &str,
+    label: &str,
+    source: &ProofStates,
+    target: Option<&ProofStates>,
+) {
+    say!("\n=== {} ===", title);
+
+    let keysets: BTreeSet<&String> = source
+        .keys()
+        .chain(target.into_iter().flat_map(|t| t.keys()))
+        .collect();
+
+    for keyset in keysets {
+        say!("{} {}", label, keyset);
+
+        let source_states = source.get(keyset);
+        let target_states = target.map(|t| t.get(keyset));
+        let states: BTreeSet<&String> = source_states
+            .into_iter()
+            .flat_map(|s| s.keys())
+            .chain(target_states.flatten().into_iter().flat_map(|s| s.keys()))
+            .collect();
+
+        for state in states {
+            let source_bucket = lookup(source_states, state);
+            match target_states {
+                Some(target_states) => {
+                    let target_bucket = lookup(target_states, state);
+                    let marker = if source_bucket == target_bucket {
+                        "✓"
+                    } else {
+                        "⚠️"
+                    };
+                    say!(
+                        "  {} {:<14} redb {:>6} proofs {:>12} | sqlite {:>6} proofs {:>12}",
+                        marker,
+                        state,
+                        source_bucket.count,
+                        source_bucket.amount,
+                        target_bucket.count,
+                        target_bucket.amount
+                    );
+                }
+                None => say!(
+                    "  {:<14} {:>6} proofs {:>12}",
+                    state,
+                    source_bucket.count,
+                    source_bucket.amount
+                ),
+            }
+        }
+    }
+}
+
+fn lookup(states: Option<&BTreeMap<String, Bucket>>, state: &str) -> Bucket {
+    states
+        .and_then(|states| states.get(state))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Quote creation and settlement counts of the source per period
+pub fn quote_activity(redb_path: &Path, period: Period) -> Result<BTreeMap<String, Activity>> {
+    let reader = RedbReader::open(redb_path)?;
+
+    let mut activity: BTreeMap<String, Activity> = BTreeMap::new();
+
+    for quote in reader.mint_quotes()? {
+        bucket(&mut activity, quote.created_time, period).mint_created += 1;
+        if let Some(paid_time) = quote.paid_time {
+            bucket(&mut activity, paid_time, period).mint_paid += 1;
+        }
+        if let Some(issued_time) = quote.issued_time {
+            bucket(&mut activity, issued_time, period).mint_issued += 1;
+        }
+    }
+
+    for quote in reader.melt_quotes()? {
+        bucket(&mut activity, quote.created_time, period).melt_created += 1;
+        if let Some(paid_time) = quote.paid_time {
+            bucket(&mut activity, paid_time, period).melt_paid += 1;
+        }
+    }
+
+    Ok(activity)
+}
+
+pub fn print_quote_activity(activity: &BTreeMap<String, Activity>, period: Period) {
+    let heading = match period {
+        Period::Day => "Day",
+        Period::Week => "Week of",
+        Period::Month => "Month",
+    };
+
+    say!("\n=== Quote Activity ===");
+    say!(
+        "{:<12} {:>12} {:>10} {:>12} {:>12} {:>10}",
+        heading,
+        "mint created",
+        "mint paid",
+        "mint issued",
+        "melt created",
+        "melt paid"
+    );
+
+    for (label, activity) in activity {
+        say!(
+            "{:<12} {:>12} {:>10} {:>12} {:>12} {:>10}",
+            label,
+            activity.mint_created,
+            activity.mint_paid,
+            activity.mint_issued,
+            activity.melt_created,
+            activity.melt_paid
+        );
+    }
+}
+
+fn bucket(activity: &mut BTreeMap<String, Activity>, time: u64, period: Period) -> &mut Activity {
+    activity.entry(period_label(time, period)).or_default()
+}
+
+// Quotes written before cdk tracked timestamps carry 0 and are grouped apart
+fn period_label(time: u64, period: Period) -> String {
+    if time == 0 {
+        return "no timestamp".to_string();
+    }
+
+    let days = (time / 86_400) as i64;
+    match period {
+        Period::Day => {
+            let (year, month, day) = civil_from_days(days);
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        }
+        Period::Week => {
+            // 1970-01-01 was a Thursday, weeks start on Monday
+            let (year, month, day) = civil_from_days(days - (days + 3).rem_euclid(7));
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        }
+        Period::Month => {
+            let (year, month, _) = civil_from_days(days);
+            format!("{:04}-{:02}", year, month)
+        }
+    }
+}
+
+// Converts days since the unix epoch into a proleptic Gregorian date
+// (Howard Hinnant's civil_from_days)
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::output::say;
+use crate::stats::{ProofStates, QuoteStates};
+
+// `stats --save` writes the counts it shows to a JSON file, `--compare` reads
+// such a file and lists the counts that changed since, e.g. around a
+// catch-up pass, instead of leaving two printouts to be diffed by eye.
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: u64,
+    pub redb: Counts,
+    pub sqlite: Option<Counts>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct Counts {
+    pub proofs_by_keyset: ProofStates,
+    pub proofs_by_kind: ProofStates,
+    pub quotes: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Counts {
+    pub fn new(
+        proofs_by_keyset: ProofStates,
+        proofs_by_kind: ProofStates,
+        quotes: QuoteStates,
+    ) -> Self {
+        Self {
+            proofs_by_keyset,
+            proofs_by_kind,
+            quotes: quotes
+                .into_iter()
+                .map(|(kind, states)| (kind.to_string(), states))
+                .collect(),
+        }
+    }
+}
+
+pub fn save(snapshot: &Snapshot, path: &Path) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(snapshot)?)?;
+    say!("📝 Counts saved to {:?}", path);
+
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Snapshot> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Cannot read snapshot {:?}: {}", path, e))?;
+
+    serde_json::from_str(&json)
+        .map_err(|e| anyhow!("{:?} is not a snapshot saved by stats --save: {}", path, e))
+}
+
+/// Prints every count that differs between the snapshot and now
+pub fn print_changes(before: &Snapshot, now: &Snapshot) {
+    println!(
+        "\n=== Changes since the snapshot at unix time {} ===",
+        before.taken_at
+    );
+
+    let mut changed = print_counts_changes("redb", Some(&before.redb), Some(&now.redb));
+    changed += print_counts_changes("sqlite", before.sqlite.as_ref(), now.sqlite.as_ref());
+
+    if changed == 0 {
+        say!("✅ No counts changed");
+    } else {
+        println!("{} count(s) changed", changed);
+    }
+}
+
+fn print_counts_changes(database: &str, before: Option<&Counts>, now: Option<&Counts>) -> usize {
+    let (before, now) = match (before, now) {
+        (None, None) => return 0,
+        (Some(_), None) => {
+            println!("{}: gone since the snapshot", database);
+            return 1;
+        }
+        (None, Some(_)) => {
+            println!("{}: not in the snapshot, created since", database);
+            return 1;
+        }
+        (Some(before), Some(now)) if before == now => return 0,
+        (Some(before), Some(now)) => (before, now),
+    };
+
+    println!("{}", database);
+    let mut changed = 0;
+    changed += print_bucket_changes("keyset", &before.proofs_by_keyset, &now.proofs_by_keyset);
+    changed += print_bucket_changes("kind", &before.proofs_by_kind, &now.proofs_by_kind);
+
+    for (kind, before_states, now_states) in pairs(&before.quotes, &now.quotes) {
+        for (state, before_count, now_count) in pairs(&before_states, &now_states) {
+            if before_count != now_count {
+                changed += 1;
+                println!(
+                    "  {} quotes {:<14} {}",
+                    kind,
+                    state,
+                    change(before_count, now_count)
+                );
+            }
+        }
+    }
+
+    changed
+}
+
+fn print_bucket_changes(label: &str, before: &ProofStates, now: &ProofStates) -> usize {
+    let mut changed = 0;
+
+    for (group, before_states, now_states) in pairs(before, now) {
+        for (state, before_bucket, now_bucket) in pairs(&before_states, &now_states) {
+            if before_bucket != now_bucket {
+                changed += 1;
+                println!(
+                    "  proofs of {} {} {:<14} {} proofs, {} amount",
+                    label,
+                    group,
+                    state,
+                    change(before_bucket.count, now_bucket.count),
+                    change(before_bucket.amount, now_bucket.amount)
+                );
+            }
+        }
+    }
+
+    changed
+}
+
+/// The entries under every key of either map, with a default where one lacks it
+fn pairs<'a, V: Default + Clone>(
+    before: &'a BTreeMap<String, V>,
+    now: &'a BTreeMap<String, V>,
+) -> Vec<(&'a String, V, V)> {
+    let keys: BTreeSet<&String> = before.keys().chain(now.keys()).collect();
+
+    keys.into_iter()
+        .map(|key| {
+            let value = |map: &BTreeMap<String, V>| map.get(key).cloned().unwrap_or_default();
+            (key, value(before), value(now))
+        })
+        .collect()
+}
+
+fn change(before: u64, now: u64) -> String {
+    format!("{} -> {} ({:+})", before, now, now as i128 - before as i128)
+}
@@ -0,0 +1,80 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::output::say;
+use crate::progress::ProgressState;
+
+/// Stops serving once dropped, also when the migration panics
+pub struct StatusPage(JoinHandle<()>);
+
+impl Drop for StatusPage {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Serves the progress of a running migration as JSON on every request
+pub async fn spawn_status_page(
+    addr: SocketAddr,
+    work_dir: &Path,
+    progress: Arc<Mutex<ProgressState>>,
+) -> Result<StatusPage> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("Could not serve status on {}: {}", addr, e))?;
+    say!("📡 Serving migration status on http://{}", addr);
+
+    let work_dir = work_dir.to_string_lossy().to_string();
+    let started = Instant::now();
+
+    Ok(StatusPage(tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+
+            let body = status_json(&work_dir, started, &progress.lock().expect("progress lock"));
+            tokio::spawn(async move {
+                if let Err(err) = respond(stream, &body).await {
+                    tracing::debug!("Status request failed: {}", err);
+                }
+            });
+        }
+    })))
+}
+
+fn status_json(work_dir: &str, started: Instant, progress: &ProgressState) -> String {
+    json!({
+        "work_dir": work_dir,
+        "phase": progress.phase.map(|phase| phase.to_string()),
+        "records_done": progress.done,
+        "records_total": progress.total,
+        "percent": progress.percent().map(|percent| (percent * 10.0).round() / 10.0),
+        "eta_secs": progress.eta().map(|eta| eta.as_secs()),
+        "elapsed_secs": started.elapsed().as_secs(),
+        "warnings": progress.warnings,
+    })
+    .to_string()
+}
+
+// Every path gets the same page, the request itself is never looked at
+async fn respond(mut stream: TcpStream, body: &str) -> std::io::Result<()> {
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request).await?;
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use cdk_common::bitcoin::hashes::{Hash, HashEngine, sha256};
+use futures::TryStreamExt;
+use sqlx::Row;
+
+use crate::output::say;
+use crate::sqlite;
+
+// Records are read in the redb's key order and inserted as they are read, so
+// two runs over the same source write the same rows in the same order. The
+// hash of a table covers its rows in primary key order, each as the JSON array
+// of its columns with blobs hex encoded, so operators who migrated copies of
+// the same backup independently can compare their results without exchanging
+// the databases. The tool's own tables and cdk-sqlite's migration history,
+// which records when it ran, are left out, as are the columns that hold the
+// time of the migration.
+//
+// Rows are hashed as SQLite returns them, so a table larger than memory is
+// never held in full. The primary key order is read off the table's key
+// index, which needs no sort either.
+
+const META_PREFIX: &str = "table_sha256.";
+
+// redb keeps no creation time for proofs and signatures, cdk-sqlite fills in
+// the time they were migrated, which differs from run to run
+const NOT_HASHED: &[(&str, &str)] = &[
+    ("proof", "created_time"),
+    ("blind_signature", "created_time"),
+];
+
+/// SHA-256 of the rows of every mint table of the database
+pub async fn table_hashes(sql_db_path: &Path) -> Result<BTreeMap<String, String>> {
+    let pool = sqlite::connect(sql_db_path).await?;
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '\\_%' ESCAPE '\\' ORDER BY name",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut hashes = BTreeMap::new();
+    for table in tables {
+        let columns = sqlx::query(&format!(
+            "PRAGMA table_info({})",
+            sqlite::quote_identifier(&table)
+        ))
+        .fetch_all(&pool)
+        .await?;
+        let mut keys: Vec<(i64, String)> = columns
+            .iter()
+            .filter(|column| column.get::<i64, _>("pk") > 0)
+            .map(|column| (column.get("pk"), column.get("name")))
+            .collect();
+        keys.sort();
+        let order = match keys.is_empty() {
+            true => "rowid".to_string(),
+            false => keys
+                .iter()
+                .map(|(_, name)| sqlite::quote_identifier(name))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let values = columns
+            .iter()
+            .map(|column| column.get::<String, _>("name"))
+            .filter(|name| !NOT_HASHED.contains(&(table.as_str(), name.as_str())))
+            .map(|name| sqlite::blob_as_hex(&sqlite::quote_identifier(&name)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let select = format!(
+            "SELECT json_array({}) FROM {} ORDER BY {}",
+            values,
+            sqlite::quote_identifier(&table),
+            order
+        );
+        let mut rows = sqlx::query_scalar::<_, String>(&select).fetch(&pool);
+        let mut engine = sha256::Hash::engine();
+        while let Some(row) = rows.try_next().await? {
+            engine.input(row.as_bytes());
+            engine.input(b"\n");
+        }
+        drop(rows);
+        hashes.insert(table, sha256::Hash::from_engine(engine).to_string());
+    }
+    pool.close().await;
+
+    Ok(hashes)
+}
+
+/// Prints the table hashes for comparison with another run and, with
+/// `record`, keeps them in the database's migration metadata
+pub async fn report(sql_db_path: &Path, database: &Path, record: bool) -> Result<()> {
+    let hashes = table_hashes(sql_db_path).await?;
+    say!("Table hashes of {:?}:", database);
+    for (table, hash) in &hashes {
+        say!("  {:<16} {}", table, hash);
+        if record {
+            crate::meta::set_value(sql_db_path, &format!("{}{}", META_PREFIX, table), hash).await?;
+        }
+    }
+
+    Ok(())
+}
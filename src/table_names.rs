@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+
+use crate::inspect::is_known_table;
+
+// Some forks and old builds named their redb tables differently. The raw
+// readers look every table up by its cdk-redb name, which this map turns
+// into the name used by the database at hand.
+static TABLE_NAMES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableNamesFile {
+    tables: HashMap<String, String>,
+}
+
+/// Loads the overrides from a TOML file with a `[tables]` section mapping
+/// cdk-redb table names to the names used on disk
+pub fn load(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Could not read table names {:?}: {}", path, err))?;
+    let file: TableNamesFile = toml::from_str(&content)
+        .map_err(|err| anyhow!("Invalid table names {:?}: {}", path, err))?;
+
+    for (name, actual) in &file.tables {
+        if !is_known_table(name) {
+            return Err(anyhow!("Unknown redb table {} in {:?}", name, path));
+        }
+        tracing::info!("Reading redb table {} as {}", name, actual);
+    }
+
+    TABLE_NAMES
+        .set(file.tables)
+        .map_err(|_| anyhow!("Table names are already loaded"))
+}
+
+/// The name of the table on disk for a cdk-redb table name
+pub fn resolve(name: &str) -> &str {
+    TABLE_NAMES
+        .get()
+        .and_then(|names| names.get(name))
+        .map(String::as_str)
+        .unwrap_or(name)
+}
+
+/// The cdk-redb table name for a table on disk
+pub fn canonical(actual: &str) -> &str {
+    TABLE_NAMES
+        .get()
+        .and_then(|names| names.iter().find(|(_, on_disk)| on_disk.as_str() == actual))
+        .map(|(name, _)| name.as_str())
+        .unwrap_or(actual)
+}
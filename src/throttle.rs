@@ -0,0 +1,99 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+// `--rate-limit` paces a migration run next to a live mintd so it does not
+// take all of the disk's bandwidth. Writers call `Throttle::wait` with every
+// batch and sleep for as long as they are ahead of the rate. Bytes are the
+// size of the records as JSON, which is what cdk-redb stores and reads back.
+
+// Time a run may lag behind and make up at full speed, so the time spent
+// writing a batch counts towards the rate without saving up credit over slow
+// or unthrottled phases
+const MAX_BURST: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimit {
+    RecordsPerSec(f64),
+    BytesPerSec(f64),
+}
+
+/// Parses a record rate such as 5000 or 5000/s, or a byte rate such as
+/// 20MB/s or 512KiB/s
+pub fn parse_rate_limit(limit: &str) -> Result<RateLimit, String> {
+    let invalid = || {
+        format!(
+            "{} is not a rate such as 5000 records per second or 20MB/s",
+            limit
+        )
+    };
+
+    let rate = limit.trim().trim_end_matches("/s");
+    let split = rate
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rate.len());
+    let (number, unit) = rate.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let parsed = match unit.trim().to_ascii_lowercase().as_str() {
+        "" => RateLimit::RecordsPerSec(number),
+        "b" => RateLimit::BytesPerSec(number),
+        "k" | "kb" => RateLimit::BytesPerSec(number * 1e3),
+        "kib" => RateLimit::BytesPerSec(number * 1024.0),
+        "m" | "mb" => RateLimit::BytesPerSec(number * 1e6),
+        "mib" => RateLimit::BytesPerSec(number * 1024.0 * 1024.0),
+        "g" | "gb" => RateLimit::BytesPerSec(number * 1e9),
+        "gib" => RateLimit::BytesPerSec(number * 1024.0 * 1024.0 * 1024.0),
+        _ => return Err(invalid()),
+    };
+
+    match parsed {
+        RateLimit::RecordsPerSec(rate) | RateLimit::BytesPerSec(rate) if rate > 0.0 => Ok(parsed),
+        _ => Err(format!("{} must be above zero", limit)),
+    }
+}
+
+#[derive(Default)]
+pub struct Throttle {
+    limit: Option<RateLimit>,
+    // When the records accounted so far may be done at the earliest
+    next: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub fn new(limit: Option<RateLimit>) -> Self {
+        Self {
+            limit,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Waits until `records` more records, of `bytes()` bytes, fit the rate
+    pub async fn wait(&self, records: u64, bytes: impl FnOnce() -> u64) {
+        let seconds = match self.limit {
+            None => return,
+            Some(RateLimit::RecordsPerSec(rate)) => records as f64 / rate,
+            Some(RateLimit::BytesPerSec(rate)) => bytes() as f64 / rate,
+        };
+
+        let deadline = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let earliest = now.checked_sub(MAX_BURST).unwrap_or(now);
+            let deadline =
+                next.map_or(now, |next| next.max(earliest)) + Duration::from_secs_f64(seconds);
+            *next = Some(deadline);
+            deadline
+        };
+
+        tokio::time::sleep_until(deadline.into()).await;
+    }
+}
+
+/// Size of the records as cdk-redb stores them
+pub fn json_len<T: Serialize>(records: &[T]) -> u64 {
+    records
+        .iter()
+        .map(|record| serde_json::to_vec(record).map_or(0, |json| json.len() as u64))
+        .sum()
+}
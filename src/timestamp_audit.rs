@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::output::say;
+use crate::progress::{ProgressObserver, WarningCode};
+use crate::{meta, sqlite};
+
+// Clock skew tolerated before a timestamp counts as being in the future
+const FUTURE_TOLERANCE_SECS: u64 = 300;
+
+// Records listed per anomaly, the rest are only counted
+const MAX_LISTED: usize = 10;
+
+// Timestamp columns of the migrated tables with the expression naming a
+// record and whether the time may lie in the future, as expiries do
+const TIMESTAMP_COLUMNS: &[(&str, &str, &str, bool)] = &[
+    ("mint_quote", "id", "created_time", false),
+    ("mint_quote", "id", "paid_time", false),
+    ("mint_quote", "id", "issued_time", false),
+    ("mint_quote", "id", "expiry", true),
+    ("melt_quote", "id", "created_time", false),
+    ("melt_quote", "id", "paid_time", false),
+    ("melt_quote", "id", "expiry", true),
+    ("proof", "hex(y)", "created_time", false),
+    ("blind_signature", "hex(y)", "created_time", false),
+];
+
+const QUOTE_TABLES: &[&str] = &["mint_quote", "melt_quote"];
+
+pub async fn audit_timestamps(sql_db_path: &Path, progress: &dyn ProgressObserver) -> Result<()> {
+    say!("\n🕒 Checking timestamps of {:?}", sql_db_path);
+
+    let pool = sqlite::connect(sql_db_path).await?;
+    let now = meta::unix_time();
+    let mut anomalies = 0;
+
+    for (table, key, column, may_be_future) in TIMESTAMP_COLUMNS {
+        if !may_be_future {
+            let future: Vec<(String, i64)> = sqlx::query_as(&format!(
+                "SELECT {key}, {column} FROM {table} WHERE {column} > ?"
+            ))
+            .bind((now + FUTURE_TOLERANCE_SECS) as i64)
+            .fetch_all(&pool)
+            .await?;
+            anomalies += future.len();
+            print_anomalies(
+                progress,
+                &format!("{}.{} in the future", table, column),
+                &future,
+            );
+        }
+
+        let zero: Vec<(String, i64)> = sqlx::query_as(&format!(
+            "SELECT {key}, {column} FROM {table} WHERE {column} = 0"
+        ))
+        .fetch_all(&pool)
+        .await?;
+        anomalies += zero.len();
+        print_anomalies(progress, &format!("{}.{} is zero", table, column), &zero);
+    }
+
+    for table in QUOTE_TABLES {
+        let expired_early: Vec<(String, i64)> = sqlx::query_as(&format!(
+            "SELECT id, expiry FROM {table} WHERE created_time > 0 AND expiry < created_time"
+        ))
+        .fetch_all(&pool)
+        .await?;
+        anomalies += expired_early.len();
+        print_anomalies(
+            progress,
+            &format!("{}.expiry before created_time", table),
+            &expired_early,
+        );
+    }
+
+    if anomalies == 0 {
+        say!("✅ No timestamp anomalies found");
+    }
+
+    pool.close().await;
+
+    Ok(())
+}
+
+fn print_anomalies(progress: &dyn ProgressObserver, description: &str, records: &[(String, i64)]) {
+    if records.is_empty() {
+        return;
+    }
+
+    tracing::warn!("{} record(s) with {}", records.len(), description);
+    progress.warning(
+        WarningCode::Timestamp,
+        &format!("{} record(s) with {}", records.len(), description),
+    );
+    say!(
+        "⚠️  [{}] {} record(s) with {}:",
+        WarningCode::Timestamp,
+        records.len(),
+        description
+    );
+    for (key, time) in records.iter().take(MAX_LISTED) {
+        say!("    {} ({})", key.to_lowercase(), time);
+    }
+    if records.len() > MAX_LISTED {
+        say!("    ... and {} more", records.len() - MAX_LISTED);
+    }
+}
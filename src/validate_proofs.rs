@@ -0,0 +1,143 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use bip39::Mnemonic;
+use cdk_common::database::{self, MintKeysDatabase, MintProofsDatabase};
+use cdk_common::dhke::verify_message;
+use cdk_common::nuts::MintKeySet;
+use cdk_common::{PublicKey, SECP256K1, State};
+use cdk_sqlite::MintSqliteDatabase;
+use serde::Deserialize;
+
+use crate::output::say;
+
+// Verification compares the SQLite database with the redb, so a proof that
+// was already damaged in the redb passes it. `--validate-proofs` checks the
+// signature of every migrated proof, C = k * hash_to_curve(secret), with the
+// key k of its keyset for its amount. Only the mint holds k, so the keysets
+// are derived again from mintd's mnemonic, read from where mintd reads it.
+
+const MNEMONIC_ENV: &str = "CDK_MINTD_MNEMONIC";
+
+#[derive(Deserialize)]
+struct MintdConfig {
+    info: Option<MintdInfo>,
+}
+
+#[derive(Deserialize)]
+struct MintdInfo {
+    mnemonic: Option<String>,
+}
+
+struct InvalidProof {
+    y: PublicKey,
+    state: Option<State>,
+    error: String,
+}
+
+pub async fn validate_proofs(work_dir: &Path, sql_db_path: &Path) -> Result<()> {
+    let sqlite_db = MintSqliteDatabase::new(sql_db_path).await?;
+
+    validate_db_proofs(work_dir, &sqlite_db).await
+}
+
+pub async fn validate_db_proofs<D>(work_dir: &Path, db: &D) -> Result<()>
+where
+    D: MintKeysDatabase<Err = database::Error> + MintProofsDatabase<Err = database::Error>,
+{
+    say!("📋 Validating proof signatures...");
+
+    let seed = read_mnemonic(work_dir)?.to_seed_normalized("");
+
+    let mut checked = 0;
+    let mut derived = 0;
+    let mut invalid = vec![];
+    let keysets = db.get_keyset_infos().await?;
+    for info in &keysets {
+        let keyset = MintKeySet::generate_from_seed(
+            &SECP256K1,
+            &seed,
+            info.max_order,
+            info.unit.clone(),
+            info.derivation_path.clone(),
+        );
+        if keyset.id != info.id {
+            say!(
+                "⚠️  Keyset {} does not derive from the mnemonic, its proofs are not checked",
+                info.id
+            );
+            continue;
+        }
+        derived += 1;
+
+        let (proofs, states) = db.get_proofs_by_keyset_id(&info.id).await?;
+        for (proof, state) in proofs.iter().zip(states) {
+            checked += 1;
+            let error = match keyset.keys.get(&proof.amount) {
+                Some(key) => verify_message(&key.secret_key, proof.c, proof.secret.as_bytes())
+                    .err()
+                    .map(|_| "C is not the keyset's signature on the secret".to_string()),
+                None => Some(format!("keyset has no key for amount {}", proof.amount)),
+            };
+            if let Some(error) = error {
+                invalid.push(InvalidProof {
+                    y: proof.y()?,
+                    state,
+                    error,
+                });
+            }
+        }
+    }
+
+    if derived == 0 && !keysets.is_empty() {
+        return Err(anyhow!(
+            "No keyset derives from the mnemonic, it is not the one this mint was run with"
+        ));
+    }
+
+    if !invalid.is_empty() {
+        say!(
+            "❌ {} of {} proof(s) have an invalid signature:",
+            invalid.len(),
+            checked
+        );
+        for proof in &invalid {
+            // A missing state means the proof was never spent
+            let state = proof.state.unwrap_or(State::Unspent);
+            say!("  {} ({}): {}", proof.y, state, proof.error);
+        }
+        return Err(anyhow!(
+            "{} proof(s) carry signatures the mint did not make",
+            invalid.len()
+        ));
+    }
+
+    say!("✅ All {} proof signatures are valid", checked);
+
+    Ok(())
+}
+
+fn read_mnemonic(work_dir: &Path) -> Result<Mnemonic> {
+    let mnemonic = match std::env::var(MNEMONIC_ENV) {
+        Ok(mnemonic) => mnemonic,
+        Err(_) => {
+            let path = work_dir.join("config.toml");
+            let config = std::fs::read_to_string(&path).map_err(|e| {
+                anyhow!(
+                    "--validate-proofs needs mintd's mnemonic from {} or {:?}: {}",
+                    MNEMONIC_ENV,
+                    path,
+                    e
+                )
+            })?;
+            let config: MintdConfig = toml::from_str(&config)?;
+            config
+                .info
+                .and_then(|info| info.mnemonic)
+                .ok_or_else(|| anyhow!("{:?} has no mnemonic in its [info] section", path))?
+        }
+    };
+
+    Mnemonic::from_str(mnemonic.trim()).map_err(|e| anyhow!("Invalid mnemonic: {}", e))
+}
@@ -0,0 +1,161 @@
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use cdk_sqlite::MintSqliteDatabase;
+use futures::FutureExt;
+
+use crate::batch::panic_message;
+use crate::checkpoint::{self, Checkpoints};
+use crate::compare::{compare_blind_signatures, compare_mints};
+use crate::mint_api;
+use crate::output::say;
+use crate::paths::{MigrationPaths, resolve_link};
+use crate::progress::NoProgress;
+use crate::verify_blind_signatures::verify_blind_signatures;
+use crate::verify_migration::verify_migration;
+use crate::{marker, meta, rollback, sqlite};
+
+// `verify` compares the migrated SQLite database of the work dir with its
+// redb again, e.g. after a migration whose verification was interrupted, and
+// continues from the checkpoints an earlier run left. With --mint-url the
+// mint running on it is asked what it serves instead. With --against it is
+// compared with another SQLite mint database instead, e.g. one loaded from
+// `export --format sql`, which checks the copy rather than the migration.
+
+pub async fn verify(
+    work_dir: PathBuf,
+    restart: bool,
+    mint_url: Option<&str>,
+    check_states: Option<usize>,
+) -> Result<()> {
+    let paths = MigrationPaths::new(&work_dir);
+    if !paths.redb.exists() {
+        return Err(anyhow!("No redb database found at {:?}", paths.redb));
+    }
+    if !paths.sqlite.exists() {
+        return Err(anyhow!(
+            "No SQLite database found at {:?}, migrate the work dir first",
+            paths.sqlite
+        ));
+    }
+
+    // Refused before the comparison, which can take hours
+    if let Some(mint_url) = mint_url
+        && !mint_url.starts_with("http://")
+        && !mint_url.starts_with("https://")
+    {
+        return Err(anyhow!(
+            "Only http:// and https:// mint URLs are supported, not {}",
+            mint_url
+        ));
+    }
+
+    // Once mintd runs on the database it keeps writing to it, so it no longer
+    // matches the redb record for record. The mint is checked on its own
+    // then, and nothing is written to its live database.
+    if let Some(mint_url) = mint_url {
+        mint_api::cross_check(mint_url, &paths.sqlite).await?;
+        if let Some(sample) = check_states {
+            mint_api::check_states(mint_url, &paths.redb, sample).await?;
+        }
+        return Ok(());
+    }
+
+    if restart {
+        checkpoint::clear(&paths.sqlite).await?;
+    }
+
+    // Proofs the redb held twice were migrated once
+    let compact = meta::read_meta(&paths.sqlite)
+        .await?
+        .iter()
+        .any(|(key, _)| key == "compacted.proofs");
+
+    // The checkpoints and the result are written to the database, and opening
+    // the auth database writes to it as well, which changes their checksums.
+    // One that matched the marker before is brought up to date so cleanup and
+    // rollback still accept it. One that did not is left alone, mintd has
+    // written to it since.
+    let auth_target = resolve_link(&paths.auth_sqlite);
+    let unchanged = |target: &Path, expected: Option<&str>| {
+        target.exists() && matches!(rollback::check_unchanged(target, expected), Ok(None))
+    };
+    let marker = marker::read_marker(&paths.redb)?.map(|marker| {
+        let refresh = unchanged(&marker.target, marker.target_sha256.as_deref());
+        let refresh_auth = unchanged(&auth_target, marker.auth_target_sha256.as_deref());
+        (marker, refresh, refresh_auth)
+    });
+
+    let mut checkpoints = Checkpoints::load(&paths.sqlite).await?;
+    // A mismatch panics, caught so the result and the marker are still updated
+    let verified = AssertUnwindSafe(async {
+        verify_blind_signatures(&paths, &mut checkpoints).await?;
+        verify_migration(&paths, compact, &mut checkpoints, &NoProgress).await
+    })
+    .catch_unwind()
+    .await
+    .unwrap_or_else(|panic| Err(anyhow!("{}", panic_message(&*panic))));
+
+    match &verified {
+        Ok(()) => {
+            checkpoint::clear(&paths.sqlite).await?;
+            meta::set_verification(&paths.sqlite, "verified").await?;
+        }
+        Err(_) => meta::set_verification(&paths.sqlite, "failed").await?,
+    }
+    if let Some((mut marker, refresh, refresh_auth)) = marker
+        && (refresh || refresh_auth)
+    {
+        // The connections of a comparison that panicked are closed in the
+        // background, and would fold the WAL in after the checksum is taken
+        if refresh {
+            sqlite::fold_wal(&marker.target).await?;
+            marker.target_sha256 = Some(meta::sha256_file(&marker.target)?);
+        }
+        if refresh_auth {
+            sqlite::fold_wal(&auth_target).await?;
+            marker.auth_target_sha256 = Some(meta::sha256_file(&auth_target)?);
+        }
+        marker::write_marker(&paths.redb, &marker)?;
+    }
+    verified?;
+
+    say!("\n✅ {:?} matches {:?}", paths.sqlite, paths.redb);
+
+    Ok(())
+}
+
+pub async fn verify_against(work_dir: PathBuf, other: &Path) -> Result<()> {
+    let paths = MigrationPaths::new(&work_dir);
+    for path in [paths.sqlite.as_path(), other] {
+        if !path.exists() {
+            return Err(anyhow!("No SQLite database found at {:?}", path));
+        }
+    }
+
+    println!("\n=== Comparing SQLite Databases ===");
+    println!("Comparing: {:?}", paths.sqlite);
+    println!("With: {:?}\n", other);
+
+    let source = MintSqliteDatabase::new(&paths.sqlite).await?;
+    let target = MintSqliteDatabase::new(other).await?;
+
+    // Nothing is recorded in either database, the comparison starts over
+    // every time
+    let mut checkpoints = Checkpoints::none();
+    compare_blind_signatures(&source, &target, &mut checkpoints).await?;
+    let compared = compare_mints(&source, &target, false, &mut checkpoints, &NoProgress).await?;
+
+    say!(
+        "\n✅ {:?} matches {:?}: {} keysets, {} proofs, {} mint quotes and {} melt quotes",
+        other,
+        paths.sqlite,
+        compared.keysets,
+        compared.proofs,
+        compared.mint_quotes,
+        compared.melt_quotes
+    );
+
+    Ok(())
+}
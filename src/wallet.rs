@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use cdk_common::database::WalletDatabase;
+use cdk_redb::WalletRedbDatabase;
+use cdk_sqlite::WalletSqliteDatabase;
+
+use crate::output::say;
+use crate::paths::with_suffix;
+use crate::sqlite;
+
+// Some builds of the cdk wallet, e.g. on mobile, still prefer the redb
+// backend. `wallet-to-redb` converts a cdk-sqlite wallet database back into
+// one, going through both crates' wallet database APIs.
+
+pub async fn wallet_to_redb(input: &Path, output: &Path) -> Result<()> {
+    if output.exists() {
+        return Err(anyhow!(
+            "Redb database already exists at {:?}. Will not overwrite existing database.",
+            output
+        ));
+    }
+
+    // cdk-sqlite upgrades the schema of the databases it opens, so it reads
+    // from a copy and the wallet's own file is left as it is. The copy holds
+    // the wallet's proofs, so it is kept next to the output rather than in
+    // the shared temp dir, and one a crashed run left is replaced.
+    let copy = with_suffix(output, ".source.sqlite");
+    sqlite::remove_database(&copy)?;
+    snapshot(input, &copy).await?;
+
+    let converted = async {
+        let sqlite_db = WalletSqliteDatabase::new(&copy).await?;
+        let redb_db = WalletRedbDatabase::new(output)?;
+        convert(&copy, &sqlite_db, &redb_db).await?;
+        verify(&copy, &sqlite_db, &redb_db).await
+    }
+    .await;
+    let _ = sqlite::remove_database(&copy);
+
+    if let Err(err) = converted {
+        // A partial wallet would only be mistaken for a complete one
+        let _ = std::fs::remove_file(output);
+        return Err(err);
+    }
+
+    say!("\n✅ Wallet converted to {:?}", output);
+
+    Ok(())
+}
+
+// VACUUM INTO writes a consistent copy that includes what is still in the WAL
+async fn snapshot(input: &Path, copy: &Path) -> Result<()> {
+    let pool = sqlite::connect(input).await?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(copy.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+async fn convert(
+    sqlite_path: &Path,
+    sqlite_db: &WalletSqliteDatabase,
+    redb_db: &WalletRedbDatabase,
+) -> Result<()> {
+    let mints = sqlite_db.get_mints().await?;
+    for (mint_url, info) in &mints {
+        redb_db.add_mint(mint_url.clone(), info.clone()).await?;
+
+        let keysets = sqlite_db
+            .get_mint_keysets(mint_url.clone())
+            .await?
+            .unwrap_or_default();
+        for keyset in &keysets {
+            if let Some(keys) = sqlite_db.get_keys(&keyset.id).await? {
+                redb_db.add_keys(keys).await?;
+            }
+            if let Some(counter) = sqlite_db.get_keyset_counter(&keyset.id).await? {
+                redb_db
+                    .increment_keyset_counter(&keyset.id, counter)
+                    .await?;
+            }
+        }
+        tracing::info!("Converted mint {} with {} keysets", mint_url, keysets.len());
+        redb_db.add_mint_keysets(mint_url.clone(), keysets).await?;
+    }
+
+    for quote in sqlite_db.get_mint_quotes().await? {
+        redb_db.add_mint_quote(quote).await?;
+    }
+
+    // The wallet database API has no way to list melt quotes
+    for id in melt_quote_ids(sqlite_path).await? {
+        if let Some(quote) = sqlite_db.get_melt_quote(&id).await? {
+            redb_db.add_melt_quote(quote).await?;
+        }
+    }
+
+    let proofs = sqlite_db.get_proofs(None, None, None, None).await?;
+    tracing::info!("Converting {} proofs", proofs.len());
+    redb_db.update_proofs(proofs, vec![]).await?;
+
+    for transaction in sqlite_db.list_transactions(None, None, None).await? {
+        redb_db.add_transaction(transaction).await?;
+    }
+
+    Ok(())
+}
+
+async fn melt_quote_ids(sqlite_path: &Path) -> Result<Vec<String>> {
+    let pool = sqlite::connect(sqlite_path).await?;
+    let ids = sqlx::query_scalar("SELECT id FROM melt_quote")
+        .fetch_all(&pool)
+        .await?;
+    pool.close().await;
+
+    Ok(ids)
+}
+
+async fn verify(
+    sqlite_path: &Path,
+    sqlite_db: &WalletSqliteDatabase,
+    redb_db: &WalletRedbDatabase,
+) -> Result<()> {
+    let mut mismatches = 0;
+    let mut check = |name: &str, source: usize, target: usize| {
+        if source == target {
+            say!("✅ {}: {}", name, source);
+        } else {
+            mismatches += 1;
+            say!("❌ {}: {} in SQLite, {} in redb", name, source, target);
+        }
+    };
+
+    let mints = sqlite_db.get_mints().await?;
+    check("mints", mints.len(), redb_db.get_mints().await?.len());
+
+    let (mut source_keysets, mut target_keysets) = (0, 0);
+    for mint_url in mints.keys() {
+        let count = |keysets: Option<Vec<_>>| keysets.map_or(0, |keysets| keysets.len());
+        source_keysets += count(sqlite_db.get_mint_keysets(mint_url.clone()).await?);
+        target_keysets += count(redb_db.get_mint_keysets(mint_url.clone()).await?);
+    }
+    check("keysets", source_keysets, target_keysets);
+
+    check(
+        "mint quotes",
+        sqlite_db.get_mint_quotes().await?.len(),
+        redb_db.get_mint_quotes().await?.len(),
+    );
+
+    let melt_quotes = melt_quote_ids(sqlite_path).await?;
+    let mut converted = 0;
+    for id in &melt_quotes {
+        if redb_db.get_melt_quote(id).await?.is_some() {
+            converted += 1;
+        }
+    }
+    check("melt quotes", melt_quotes.len(), converted);
+
+    let source_proofs = sqlite_db.get_proofs(None, None, None, None).await?;
+    let target_proofs = redb_db.get_proofs(None, None, None, None).await?;
+    check("proofs", source_proofs.len(), target_proofs.len());
+    let balance = |proofs: &[cdk_common::common::ProofInfo]| -> u64 {
+        proofs.iter().map(|info| u64::from(info.proof.amount)).sum()
+    };
+    check(
+        "proof amount",
+        balance(&source_proofs) as usize,
+        balance(&target_proofs) as usize,
+    );
+
+    check(
+        "transactions",
+        sqlite_db.list_transactions(None, None, None).await?.len(),
+        redb_db.list_transactions(None, None, None).await?.len(),
+    );
+
+    if mismatches > 0 {
+        return Err(anyhow!(
+            "{} count(s) differ between the SQLite and the redb wallet",
+            mismatches
+        ));
+    }
+
+    Ok(())
+}